@@ -63,6 +63,7 @@ type Args struct {
 	image                       string
 	namespace                   string
 	trusteeImage                string
+	rvpsImage                   string
 	pcrsComputeImage            string
 	registerServerImage         string
 	attestationKeyRegisterImage string
@@ -75,6 +76,7 @@ func main() {
 	flag.StringVar(&args.image, "image", "quay.io/trusted-execution-clusters/trusted-cluster-operator:latest", "Container image to use in the deployment")
 	flag.StringVar(&args.namespace, "namespace", "trusted-execution-clusters", "Namespace where to install the operator")
 	flag.StringVar(&args.trusteeImage, "trustee-image", "operators", "Container image with all-in-one Trustee")
+	flag.StringVar(&args.rvpsImage, "rvps-image", "quay.io/trusted-execution-clusters/reference-value-provider-service:latest", "Container image with the standalone reference value provider service")
 	flag.StringVar(&args.pcrsComputeImage, "pcrs-compute-image", "quay.io/trusted-execution-clusters/compute-pcrs:latest", "Container image with the Trusted Execution Clusters compute-pcrs binary")
 	flag.StringVar(&args.registerServerImage, "register-server-image", "quay.io/trusted-execution-clusters/register-server:latest", "Register server image to use in the deployment")
 	flag.StringVar(&args.attestationKeyRegisterImage, "attestation-key-register-image", "quay.io/trusted-execution-clusters/attestation-key-register:latest", "Attestation key register image to use in the deployment")
@@ -134,6 +136,10 @@ func generateOperator(args *Args) error {
 							Name:  "RELATED_IMAGE_TRUSTEE",
 							Value: args.trusteeImage,
 						},
+						{
+							Name:  "RELATED_IMAGE_RVPS",
+							Value: args.rvpsImage,
+						},
 						{
 							Name:  "RELATED_IMAGE_COMPUTE_PCRS",
 							Value: args.pcrsComputeImage,
@@ -203,6 +209,9 @@ func generateTrustedExecutionClusterCR(args *Args) error {
 			TrusteeKbsPort:                   0,
 			RegisterServerPort:               0,
 			AttestationKeyRegisterPort:       0,
+			TrusteeAdminApi:                  false,
+			AttestationTokenKeyRotationDays:  nil,
+			Trustee:                          nil,
 		},
 	}
 
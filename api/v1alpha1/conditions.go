@@ -10,17 +10,37 @@ const (
 	NotInstalledReasonNonUnique    string = "NonUnique"
 	NotInstalledReasonInstalling   string = "Installing"
 	NotInstalledReasonUninstalling string = "Uninstalling"
+	NotInstalledReasonInvalid      string = "InvalidConfiguration"
 
 	KnownTrusteeAddressCondition string = "KnownTrusteeAddress"
 	KnownTrusteeAddressReason    string = "AddressFound"
 	UnknownTrusteeAddressReason  string = "NoAddressFound"
 
-	CommittedCondition          string = "Committed"
-	CommittedReason             string = "ImageCommitted"
-	NotCommittedReasonComputing string = "Computing"
-	NotCommittedReasonNoDigest  string = "NoDigestGiven"
-	NotCommittedReasonFailed    string = "ComputationFailed"
-	NotCommittedReasonPending   string = "PodPending"
+	CommittedCondition                  string = "Committed"
+	CommittedReason                     string = "ImageCommitted"
+	NotCommittedReasonComputing         string = "Computing"
+	NotCommittedReasonNoDigest          string = "NoDigestGiven"
+	NotCommittedReasonFailed            string = "ComputationFailed"
+	NotCommittedReasonPending           string = "PodPending"
+	NotCommittedReasonNotYet            string = "NotYetValid"
+	NotCommittedReasonExpired           string = "Expired"
+	NotCommittedReasonSignatureInvalid  string = "SignatureInvalid"
+	NotCommittedReasonQueued            string = "Queued"
+	NotCommittedReasonProfileMissing    string = "PlatformProfileMissing"
+	NotCommittedReasonCmdlineUnmeasured string = "KernelCommandLinesUnmeasured"
+
+	PcrCombinationsWithinLimitCondition string = "PcrCombinationsWithinLimit"
+	PcrCombinationsWithinLimitReason    string = "WithinLimit"
+	PcrCombinationsLimitExceededReason  string = "LimitExceeded"
+
+	TagResolvedCondition                 string = "TagResolved"
+	TagResolvedReason                    string = "DigestResolved"
+	TagNotResolvedReasonFailed           string = "ResolutionFailed"
+	TagNotResolvedReasonSignatureMissing string = "SignatureMissing"
+
+	ExpiringCondition string = "Expiring"
+	ExpiringReason    string = "ExpiresSoon"
+	NotExpiringReason string = "NotExpiringSoon"
 
 	// Conditions for the AttestationKey
 	AttestationKeyApprovedCondition     string = "Approved"
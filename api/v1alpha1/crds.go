@@ -9,6 +9,7 @@
 package v1alpha1
 
 import (
+	corev1 "k8s.io/api/core/v1"
 	metav1 "k8s.io/apimachinery/pkg/apis/meta/v1"
 	"k8s.io/apimachinery/pkg/runtime/schema"
 	"sigs.k8s.io/controller-runtime/pkg/scheme"
@@ -25,14 +26,19 @@ var (
 	AddToScheme = SchemeBuilder.AddToScheme
 )
 
-// +kubebuilder:rbac:groups="",resources=configmaps;services;secrets,verbs=create;get;list;patch;watch
+// +kubebuilder:rbac:groups="",resources=configmaps;services;secrets,verbs=create;get;list;patch;update;watch
+// +kubebuilder:rbac:groups="",resources=configmaps,verbs=delete
 // +kubebuilder:rbac:groups="",resources=pods,verbs=get;list
+// +kubebuilder:rbac:groups="",resources=nodes,verbs=list;watch
+// +kubebuilder:rbac:groups="",resources=pods/log,verbs=get
+// +kubebuilder:rbac:groups=events.k8s.io,resources=events,verbs=create;patch
 // +kubebuilder:rbac:groups=apps,resources=deployments,verbs=create;delete;get;list;patch;update;watch
 // +kubebuilder:rbac:groups=batch,resources=jobs,verbs=create;delete;get;list;patch;update;watch
 // +kubebuilder:rbac:groups=networking.k8s.io,resources=networkpolicies,verbs=create;delete;patch;update
-// +kubebuilder:rbac:groups=trusted-execution-clusters.io,resources=trustedexecutionclusters;machines;approvedimages;attestationkeys,verbs=create;delete;get;list;patch;update;watch
+// +kubebuilder:rbac:groups=trusted-execution-clusters.io,resources=trustedexecutionclusters;machines;approvedimages;approvedimagestreams;attestationkeys,verbs=create;delete;get;list;patch;update;watch
+// +kubebuilder:rbac:groups=trusted-execution-clusters.io,resources=platformprofiles,verbs=get;list;watch
 // +kubebuilder:rbac:groups=trusted-execution-clusters.io,resources=trustedexecutionclusters/finalizers;machines/finalizers;attestationkeys/finalizers;approvedimages/finalizers,verbs=update
-// +kubebuilder:rbac:groups=trusted-execution-clusters.io,resources=trustedexecutionclusters/status;machines/status;approvedimages/status;attestationkeys/status,verbs=get;patch;update
+// +kubebuilder:rbac:groups=trusted-execution-clusters.io,resources=trustedexecutionclusters/status;machines/status;approvedimages/status;approvedimagestreams/status;attestationkeys/status,verbs=get;patch;update
 
 // TrustedExecutionClusterSpec defines the desired state of TrustedExecutionCluster
 // +kubebuilder:validation:XValidation:rule="!has(oldSelf.publicAttestationKeyRegisterAddr) || has(self.publicAttestationKeyRegisterAddr)", message="Value is required once set"
@@ -50,6 +56,8 @@ var (
 // +kubebuilder:validation:XValidation:rule="has(oldSelf.registerServerPort) == has(self.registerServerPort)", message="Value must be set at creation"
 // +kubebuilder:validation:XValidation:rule="!has(oldSelf.attestationKeyRegisterPort) || has(self.attestationKeyRegisterPort)", message="Value is required once set"
 // +kubebuilder:validation:XValidation:rule="has(oldSelf.attestationKeyRegisterPort) == has(self.attestationKeyRegisterPort)", message="Value must be set at creation"
+// +kubebuilder:validation:XValidation:rule="!has(oldSelf.externalTrustee) || has(self.externalTrustee)", message="Value is required once set"
+// +kubebuilder:validation:XValidation:rule="has(oldSelf.externalTrustee) == has(self.externalTrustee)", message="Value must be set at creation"
 type TrustedExecutionClusterSpec struct {
 	// Address where attester can connect to Attestation Key Register
 	// +optional
@@ -90,6 +98,209 @@ type TrustedExecutionClusterSpec struct {
 	// +optional
 	// +kubebuilder:validation:XValidation:rule="self == oldSelf",message="Value is immutable"
 	AttestationKeyRegisterPort int32 `json:"attestationKeyRegisterPort,omitempty"`
+
+	// Enable the Trustee admin API. The operator generates an admin keypair, stores it in the
+	// trustee-admin-keys Secret and configures Trustee to accept tokens signed with it.
+	// Machine secrets are then pushed through the KBS resource API instead of being mounted to
+	// the Trustee deployment, which avoids restarting Trustee on every registration.
+	// +optional
+	TrusteeAdminApi bool `json:"trusteeAdminApi,omitempty"`
+
+	// Days after which the attestation token signing key in the attestation-token-key Secret is
+	// rotated. The previous key stays trusted and published until the next rotation. 0 disables
+	// rotation.
+	// +optional
+	// +kubebuilder:default=30
+	// +kubebuilder:validation:Minimum=0
+	AttestationTokenKeyRotationDays *int32 `json:"attestationTokenKeyRotationDays,omitempty"`
+
+	// Settings of the Trustee deployed by the operator
+	// +optional
+	Trustee *TrusteeConfig `json:"trustee,omitempty"`
+
+	// Version from the reference value history to serve instead of the reference values computed
+	// from ApprovedImages, e.g. to roll back a faulty update. The history is kept in ConfigMaps
+	// labeled trusted-execution-clusters.io/reference-values-history, and
+	// `operator diff-reference-values <from> <to>` in the operator pod prints the changes between
+	// two versions. A pinned version expires along with the ApprovedImages that contributed to it.
+	// Remove to resume serving computed reference values.
+	// +optional
+	// +kubebuilder:validation:Minimum=1
+	ReferenceValuesVersion *int32 `json:"referenceValuesVersion,omitempty"`
+
+	// Ceiling on the estimated number of PCR combinations when computing reference values from
+	// ApprovedImages. Bootloader and kernel components of every image are combined with those of
+	// every other image, so the combinations grow multiplicatively with the number of images.
+	// Beyond the ceiling, reference values only cover the PCRs of each image by itself and the
+	// PcrCombinationsWithinLimit condition is False. Defaults to 10000.
+	// +optional
+	// +kubebuilder:validation:Minimum=1
+	MaxPcrCombinations *int64 `json:"maxPcrCombinations,omitempty"`
+
+	// Use an existing Trustee instead of deploying one. The operator manages machine secrets,
+	// reference values and policies on it through the Trustee admin API, and register-server
+	// points machines to it unless publicTrusteeAddr is set. Public attestation keys are written
+	// to the default/trusted-ak-keys resource directory, which its TPM verifier is to trust.
+	// Cannot be combined with trustee, and can only be set at creation.
+	// +optional
+	// +kubebuilder:validation:XValidation:rule="self == oldSelf",message="Value is immutable"
+	ExternalTrustee *ExternalTrusteeConfig `json:"externalTrustee,omitempty"`
+
+	// Settings of the registry client that the operator reads PCR labels of ApprovedImages with
+	// +optional
+	Registry *RegistryConfig `json:"registry,omitempty"`
+
+	// Require ApprovedImages to carry a valid signature before they are committed
+	// +optional
+	SignaturePolicy *SignaturePolicy `json:"signaturePolicy,omitempty"`
+
+	// Settings of the compute-pcrs Jobs that compute the PCRs of ApprovedImages without a PCR
+	// label
+	// +optional
+	PcrComputation *PcrComputationConfig `json:"pcrComputation,omitempty"`
+}
+
+// PcrComputationConfig defines how compute-pcrs Jobs run
+type PcrComputationConfig struct {
+	// Maximum number of compute-pcrs Jobs that run at once. Further ApprovedImages wait in the
+	// order they were created, with the reason Queued on their Committed condition.
+	// +optional
+	// +kubebuilder:default=3
+	// +kubebuilder:validation:Minimum=1
+	MaxConcurrentJobs *int32 `json:"maxConcurrentJobs,omitempty"`
+
+	// Compute resources of the compute-pcrs container
+	// +optional
+	Resources *corev1.ResourceRequirements `json:"resources,omitempty"`
+
+	// Time after which a compute-pcrs Job that has not completed fails
+	// +optional
+	// +kubebuilder:default=1800
+	// +kubebuilder:validation:Minimum=1
+	ActiveDeadlineSeconds *int64 `json:"activeDeadlineSeconds,omitempty"`
+
+	// Time after which a finished compute-pcrs Job is deleted in case the operator did not
+	// delete it
+	// +optional
+	// +kubebuilder:default=3600
+	// +kubebuilder:validation:Minimum=0
+	TtlSecondsAfterFinished *int32 `json:"ttlSecondsAfterFinished,omitempty"`
+}
+
+// SignaturePolicy defines the keys that ApprovedImages must be signed with. Signatures are
+// cosign simple signing signatures in the sha256-<digest>.sig tag of the image repository.
+// They are verified when an image is first approved.
+type SignaturePolicy struct {
+	// ConfigMap whose entries are PEM-encoded public keys. An image is committed if it has a
+	// signature by any of them.
+	// +required
+	// +kubebuilder:validation:MinLength=1
+	PublicKeysConfigMap string `json:"publicKeysConfigMap"`
+}
+
+// RegistryConfig defines how the operator reaches the registries of ApprovedImages
+type RegistryConfig struct {
+	// ConfigMap with PEM-encoded CA certificates in ca-bundle.crt to trust for registries in
+	// addition to the system CAs
+	// +optional
+	CaBundleConfigMap *string `json:"caBundleConfigMap,omitempty"`
+
+	// Registries to read images from instead of their source. Images are read by digest, so
+	// mirrors serve the same content. Mirrors are tried in order before the source.
+	// +optional
+	// +listType=map
+	// +listMapKey=source
+	Mirrors []RegistryMirror `json:"mirrors,omitempty"`
+
+	// Proxy for HTTPS connections to registries
+	// +optional
+	HttpsProxy *string `json:"httpsProxy,omitempty"`
+
+	// Proxy for HTTP connections to registries
+	// +optional
+	HttpProxy *string `json:"httpProxy,omitempty"`
+
+	// Comma-separated hosts to connect to without a proxy
+	// +optional
+	NoProxy *string `json:"noProxy,omitempty"`
+}
+
+// RegistryMirror defines mirrors for a registry or repository
+type RegistryMirror struct {
+	// Registry, optionally with a repository path, e.g. quay.io or quay.io/fedora
+	// +required
+	// +kubebuilder:validation:MinLength=1
+	Source string `json:"source"`
+
+	// Registries, optionally with a repository path, that mirror the source, e.g.
+	// mirror.example.com/quay.io
+	// +required
+	// +kubebuilder:validation:MinItems=1
+	Mirrors []string `json:"mirrors"`
+}
+
+// ExternalTrusteeConfig defines how to reach a Trustee that is not deployed by the operator
+type ExternalTrusteeConfig struct {
+	// Address of the KBS as host:port, reachable from the operator and attesters
+	// +required
+	// +kubebuilder:validation:MinLength=1
+	Address string `json:"address"`
+
+	// Secret with ca.crt to verify the KBS certificate. Without it, plain HTTP is used.
+	// +optional
+	CaSecret *string `json:"caSecret,omitempty"`
+
+	// Secret with private.key, the PEM-encoded Ed25519 key of an admin persona of the KBS
+	// +required
+	// +kubebuilder:validation:MinLength=1
+	AdminKeySecret string `json:"adminKeySecret"`
+}
+
+// TrusteeConfig defines settings of the Trustee deployed by the operator.
+// Changes are applied to the running Trustee, which is restarted if needed.
+type TrusteeConfig struct {
+	// Validity of attestation tokens in minutes. Defaults to 5.
+	// +optional
+	// +kubebuilder:validation:Minimum=1
+	TokenDurationMinutes *int32 `json:"tokenDurationMinutes,omitempty"`
+
+	// Type of attestation tokens that KBS verifies. Defaults to CoCo.
+	// +optional
+	TokenType *string `json:"tokenType,omitempty"`
+
+	// Path of the local JSON file that the built-in RVPS stores reference values in.
+	// Defaults to /opt/trustee/reference-values.json.
+	// +optional
+	RvpsStoragePath *string `json:"rvpsStoragePath,omitempty"`
+
+	// Additional KBS plugins. The resource plugin is managed by the operator.
+	// +optional
+	// +listType=map
+	// +listMapKey=name
+	Plugins []TrusteePlugin `json:"plugins,omitempty"`
+
+	// Deploy a standalone reference value provider service (RVPS) that Trustee queries over gRPC.
+	// Reference values are then registered with the RVPS through the Trustee admin API, which
+	// takes effect immediately instead of waiting for ConfigMap propagation. Requires
+	// trusteeAdminApi.
+	// +optional
+	// +kubebuilder:validation:XValidation:rule="self == oldSelf",message="Value is immutable"
+	ExternalRvps bool `json:"externalRvps,omitempty"`
+
+	// Value of RUST_LOG for Trustee, e.g. "info" or "info,kbs=debug". Defaults to debug.
+	// +optional
+	LogLevel *string `json:"logLevel,omitempty"`
+}
+
+// TrusteePlugin defines a KBS plugin
+type TrusteePlugin struct {
+	// Name of the plugin, e.g. nebula-ca
+	// +required
+	Name string `json:"name"`
+
+	// Plugin-specific settings, added to the plugin's table in the KBS configuration
+	// +optional
+	Settings map[string]string `json:"settings,omitempty"`
 }
 
 // TrustedExecutionClusterStatus defines the observed state of TrustedExecutionCluster.
@@ -98,6 +309,25 @@ type TrustedExecutionClusterStatus struct {
 	// +listMapKey=type
 	// +optional
 	Conditions []metav1.Condition `json:"conditions,omitempty"`
+
+	// Last combination of image PCRs into reference values
+	// +optional
+	PcrCombination *PcrCombinationStatus `json:"pcrCombination,omitempty"`
+}
+
+// PcrCombinationStatus describes a combination of image PCRs into reference values
+type PcrCombinationStatus struct {
+	// Number of valid ApprovedImages whose PCRs were combined
+	// +required
+	Images int32 `json:"images"`
+
+	// Number of PCR combinations, or their estimate if it exceeded maxPcrCombinations
+	// +required
+	Combinations int64 `json:"combinations"`
+
+	// Time taken to combine the PCRs in milliseconds
+	// +required
+	DurationMillis int64 `json:"durationMillis"`
 }
 
 // +kubebuilder:object:root=true
@@ -174,12 +404,46 @@ type MachineList struct {
 }
 
 // ApprovedImageSpec defines the desired state of ApprovedImage
+// +kubebuilder:validation:XValidation:rule="!has(self.notBefore) || !has(self.notAfter) || self.notBefore < self.notAfter",message="notBefore must be before notAfter"
 type ApprovedImageSpec struct {
 	// Approved image reference, specified with digest
 	// +required
 	// +kubebuilder:validation:XValidation:rule="self == oldSelf",message="Value is immutable"
 	// +kubebuilder:validation:XValidation:rule="self.matches(r'.*@sha256:.*')",message="Image must be provided with a digest"
 	Reference string `json:"image"`
+
+	// Time from which the image is approved. Its reference values are only committed then.
+	// +optional
+	NotBefore *metav1.Time `json:"notBefore,omitempty"`
+
+	// Time until which the image is approved. Its reference values expire then, and the image is
+	// disallowed. Without it, reference values expire one year after the image was first seen.
+	// +optional
+	NotAfter *metav1.Time `json:"notAfter,omitempty"`
+
+	// Secrets of type kubernetes.io/dockerconfigjson in the operator namespace to authenticate to
+	// the registry of the image with, both for reading its PCR label and for the compute-pcrs Job
+	// +optional
+	ImagePullSecrets []corev1.LocalObjectReference `json:"imagePullSecrets,omitempty"`
+
+	// PlatformProfiles in the operator namespace of the platforms that nodes boot the image on.
+	// The compute-pcrs Job computes one set of PCRs per profile, which are verified separately.
+	// Without any, the PCRs are computed once for all platforms.
+	// +listType=set
+	// +kubebuilder:validation:MaxItems=16
+	// +optional
+	PlatformProfiles []string `json:"platformProfiles,omitempty"`
+
+	// Kernel command lines that nodes may boot the image with, as in /proc/cmdline. The image
+	// must boot as a unified kernel image (UKI), for which the compute-pcrs job adds the PCR12
+	// value that systemd-stub measures for each of them to the reference values. GRUB measures the
+	// command line into PCR8 along with its configuration, which cannot be computed, so other
+	// images with command lines are not committed. Without any, PCR12 is not verified. PCR11 is
+	// not verified.
+	// +listType=set
+	// +kubebuilder:validation:MaxItems=32
+	// +optional
+	KernelCommandLines []string `json:"kernelCommandLines,omitempty"`
 }
 
 // ApprovedImageStatus defines the observed state of ApprovedImage.
@@ -188,10 +452,78 @@ type ApprovedImageStatus struct {
 	// +listMapKey=type
 	// +optional
 	Conditions []metav1.Condition `json:"conditions,omitempty"`
+
+	// PCRs of the image, one entry per architecture whose PCRs are known and, for images with
+	// PlatformProfiles, per profile
+	// +optional
+	Pcrs []ImagePcrStatus `json:"pcrs,omitempty"`
+}
+
+// PcrSource tells where the PCRs of an image came from
+// +kubebuilder:validation:Enum=Label;Computed
+type PcrSource string
+
+const (
+	// PCRs were read from the org.coreos.pcrs label of the image
+	PcrSourceLabel PcrSource = "Label"
+	// PCRs were computed by a compute-pcrs Job
+	PcrSourceComputed PcrSource = "Computed"
+)
+
+// ImagePcrStatus describes the PCRs of an image for one architecture
+type ImagePcrStatus struct {
+	// OCI architecture, e.g. amd64. Unset for PCRs of an unknown architecture, as stored by
+	// earlier versions.
+	// +optional
+	Architecture *string `json:"architecture,omitempty"`
+
+	// PlatformProfile that the PCRs were computed for. Unset for PCRs of images without
+	// PlatformProfiles.
+	// +optional
+	Platform *string `json:"platform,omitempty"`
+
+	// Where the PCRs came from. Unset for PCRs stored by earlier versions.
+	// +optional
+	Source *PcrSource `json:"source,omitempty"`
+
+	// ID from /etc/os-release of the image, only known for computed PCRs
+	// +optional
+	OsId *string `json:"osId,omitempty"`
+
+	// VERSION_ID from /etc/os-release of the image, only known for computed PCRs
+	// +optional
+	OsVersionId *string `json:"osVersionId,omitempty"`
+
+	// Time at which the PCRs were computed or read from the label
+	// +optional
+	ComputationTime *metav1.Time `json:"computationTime,omitempty"`
+
+	// PCR values
+	// +required
+	Values []PcrValue `json:"values"`
+}
+
+// PcrValue is the value of a single PCR
+type PcrValue struct {
+	// PCR index
+	// +required
+	Id int32 `json:"id"`
+
+	// Hex-encoded PCR value
+	// +required
+	Value string `json:"value"`
 }
 
 // +kubebuilder:object:root=true
 // +kubebuilder:subresource:status
+// +kubebuilder:printcolumn:name="Committed",type=string,JSONPath=`.status.conditions[?(@.type=="Committed")].status`
+// +kubebuilder:printcolumn:name="Source",type=string,JSONPath=`.status.pcrs[*].source`
+// +kubebuilder:printcolumn:name="OS",type=string,JSONPath=`.status.pcrs[*].osId`
+// +kubebuilder:printcolumn:name="Version",type=string,JSONPath=`.status.pcrs[*].osVersionId`
+// +kubebuilder:printcolumn:name="Architectures",type=string,JSONPath=`.status.pcrs[*].architecture`,priority=1
+// +kubebuilder:printcolumn:name="PCRs",type=string,JSONPath=`.status.pcrs[0].values[*].id`,priority=1
+// +kubebuilder:printcolumn:name="Image",type=string,JSONPath=`.spec.image`,priority=1
+// +kubebuilder:printcolumn:name="Age",type=date,JSONPath=`.metadata.creationTimestamp`
 
 // ApprovedImage is the Schema for the approvedimages API
 type ApprovedImage struct {
@@ -219,6 +551,102 @@ type ApprovedImageList struct {
 	Items           []ApprovedImage `json:"items"`
 }
 
+// ApprovedImageStreamSpec defines the desired state of ApprovedImageStream
+type ApprovedImageStreamSpec struct {
+	// Image reference by tag to track. Whenever the tag points to a new digest, an ApprovedImage
+	// for that digest is created.
+	// +required
+	// +kubebuilder:validation:XValidation:rule="!self.matches(r'.*@sha256:.*')",message="Image must be provided with a tag, not a digest"
+	Reference string `json:"image"`
+
+	// Interval at which the tag is checked for a new digest. Defaults to 300 seconds.
+	// +optional
+	// +kubebuilder:validation:Minimum=30
+	PollIntervalSeconds *int64 `json:"pollIntervalSeconds,omitempty"`
+
+	// Number of generated ApprovedImages to keep, including the one for the current digest. Older
+	// ones are deleted, which withdraws their reference values. Defaults to 3.
+	// +optional
+	// +kubebuilder:validation:Minimum=1
+	RetainCount *int32 `json:"retainCount,omitempty"`
+
+	// Time that a generated ApprovedImage is kept after a newer digest superseded it, giving
+	// machines time to update. Without it, superseded images are only deleted by retainCount.
+	// +optional
+	// +kubebuilder:validation:Minimum=0
+	GracePeriodSeconds *int64 `json:"gracePeriodSeconds,omitempty"`
+
+	// Only create ApprovedImages for digests with a valid signature under the signaturePolicy of
+	// the TrustedExecutionCluster
+	// +optional
+	RequireSignature *bool `json:"requireSignature,omitempty"`
+
+	// Secrets of type kubernetes.io/dockerconfigjson in the operator namespace to resolve the tag
+	// with. They are passed on to the generated ApprovedImages.
+	// +optional
+	ImagePullSecrets []corev1.LocalObjectReference `json:"imagePullSecrets,omitempty"`
+
+	// PlatformProfiles to compute PCRs for, passed on to the generated ApprovedImages
+	// +listType=set
+	// +kubebuilder:validation:MaxItems=16
+	// +optional
+	PlatformProfiles []string `json:"platformProfiles,omitempty"`
+
+	// Kernel command lines that nodes may boot the images with, passed on to the generated
+	// ApprovedImages
+	// +listType=set
+	// +kubebuilder:validation:MaxItems=32
+	// +optional
+	KernelCommandLines []string `json:"kernelCommandLines,omitempty"`
+}
+
+// ApprovedImageStreamStatus defines the observed state of ApprovedImageStream.
+type ApprovedImageStreamStatus struct {
+	// Digest that the tag pointed to when it was last resolved
+	// +optional
+	LatestDigest *string `json:"latestDigest,omitempty"`
+
+	// Name of the ApprovedImage generated for the latest digest
+	// +optional
+	LatestImage *string `json:"latestImage,omitempty"`
+
+	// +listType=map
+	// +listMapKey=type
+	// +optional
+	Conditions []metav1.Condition `json:"conditions,omitempty"`
+}
+
+// +kubebuilder:object:root=true
+// +kubebuilder:subresource:status
+
+// ApprovedImageStream is the Schema for the approvedimagestreams API. It tracks a tag and
+// generates an ApprovedImage for each digest the tag points to.
+// +kubebuilder:validation:XValidation:rule="size(self.metadata.name) <= 63",message="Name must be no more than 63 characters to label generated images with it"
+type ApprovedImageStream struct {
+	metav1.TypeMeta `json:",inline"`
+
+	// metadata is a standard object metadata
+	// +optional
+	metav1.ObjectMeta `json:"metadata,omitempty,omitzero"`
+
+	// spec defines the desired state of ApprovedImageStream
+	// +required
+	Spec ApprovedImageStreamSpec `json:"spec"`
+
+	// status defines the observed state of ApprovedImageStream
+	// +optional
+	Status ApprovedImageStreamStatus `json:"status,omitempty,omitzero"`
+}
+
+// +kubebuilder:object:root=true
+
+// ApprovedImageStreamList contains a list of ApprovedImageStream
+type ApprovedImageStreamList struct {
+	metav1.TypeMeta `json:",inline"`
+	metav1.ListMeta `json:"metadata,omitempty"`
+	Items           []ApprovedImageStream `json:"items"`
+}
+
 // AttestationKeySpec
 type AttestationKeySpec struct {
 	// PublicKey defines the attestation public key to be registered as trusted key.
@@ -266,3 +694,75 @@ type AttestationKeyList struct {
 	metav1.ListMeta `json:"metadata,omitempty"`
 	Items           []AttestationKey `json:"items"`
 }
+
+// EvidenceType is the type of TPM evidence that machines attest with
+// +kubebuilder:validation:Enum=tpm;az-snp-vtpm
+type EvidenceType string
+
+const (
+	// TPM quote, e.g. of QEMU or KubeVirt vTPMs
+	EvidenceTypeTpm EvidenceType = "tpm"
+	// vTPM of Azure confidential VMs with SEV-SNP
+	EvidenceTypeAzSnpVtpm EvidenceType = "az-snp-vtpm"
+)
+
+// PlatformProfileSpec defines the platform-specific inputs of PCR computation
+type PlatformProfileSpec struct {
+	// Type of the evidence that machines of the platform attest with. Reference values of the
+	// profile are only verified against evidence of this type.
+	// +required
+	EvidenceType EvidenceType `json:"evidenceType"`
+
+	// Firmware of the platform
+	// +optional
+	Firmware *PlatformFirmware `json:"firmware,omitempty"`
+
+	// ConfigMap in the operator namespace whose binaryData holds the Secure Boot EFI variables
+	// PK, KEK, db, dbx and SbatLevel of the platform, each keyed by its efivarfs name, e.g.
+	// PK-8be4df61-93ca-11d2-aa0d-00e098032b8c. PCR7 is computed from them. Without it, PCR7 is
+	// not computed.
+	// +optional
+	EfiVariablesConfigMapName *string `json:"efiVariablesConfigMapName,omitempty"`
+
+	// ConfigMap in the operator namespace whose binaryData holds the MOK variables of shim that
+	// PCR14 is computed from, keyed as in the mok-variables of the reference-values repository.
+	// Without it, the variables of the image's OS in that repository are used.
+	// +optional
+	MokVariablesConfigMapName *string `json:"mokVariablesConfigMapName,omitempty"`
+}
+
+// PlatformFirmware describes the UEFI firmware of a platform
+type PlatformFirmware struct {
+	// Whether the firmware boots with Secure Boot enabled, which PCR7 measures. Defaults to true.
+	// +optional
+	SecureBoot *bool `json:"secureBoot,omitempty"`
+}
+
+// +kubebuilder:object:root=true
+// +kubebuilder:printcolumn:name="Evidence",type=string,JSONPath=`.spec.evidenceType`
+// +kubebuilder:printcolumn:name="Age",type=date,JSONPath=`.metadata.creationTimestamp`
+
+// PlatformProfile is the Schema for the platformprofiles API. It describes a platform that
+// machines boot approved images on, such as a hypervisor with its vTPM, for which PCRs are
+// computed and verified separately.
+// +kubebuilder:validation:XValidation:rule="size(self.metadata.name) <= 63",message="Name must be no more than 63 characters to prefix reference values with it"
+type PlatformProfile struct {
+	metav1.TypeMeta `json:",inline"`
+
+	// metadata is a standard object metadata
+	// +optional
+	metav1.ObjectMeta `json:"metadata,omitempty,omitzero"`
+
+	// spec defines the platform
+	// +required
+	Spec PlatformProfileSpec `json:"spec"`
+}
+
+// +kubebuilder:object:root=true
+
+// PlatformProfileList contains a list of PlatformProfile
+type PlatformProfileList struct {
+	metav1.TypeMeta `json:",inline"`
+	metav1.ListMeta `json:"metadata,omitempty"`
+	Items           []PlatformProfile `json:"items"`
+}
@@ -3,79 +3,345 @@
 //
 // SPDX-License-Identifier: MIT
 
-use anyhow::{Context, Result, anyhow};
-use clap::Parser;
+use anyhow::{Context, Result, anyhow, bail};
+use clap::{Parser, Subcommand};
 use compute_pcrs_lib::*;
-use k8s_openapi::{api::core::v1::ConfigMap, jiff::Timestamp};
+use k8s_openapi::jiff::Timestamp;
 use kube::{Api, Client};
-use std::{fs::File, io::Read};
+use std::path::{Path, PathBuf};
+use std::{collections::BTreeMap, fs, fs::File, io::Read};
 
 use trusted_cluster_operator_lib::{conditions::INSTALLED_REASON, reference_values::*, *};
 
+mod oci_archive;
+mod pull;
+mod uki;
+mod unpack;
+
+const REFERENCE_VALUES_DIR: &str = "/reference-values";
+
+// Without a subcommand, computes the PCRs of an ApprovedImage in a compute-pcrs Job and stores
+// them in the cluster
 #[derive(Parser)]
-#[command(version, about)]
+#[command(version, about, subcommand_negates_reqs = true)]
 struct Args {
     /// ApprovedImage resource name
-    #[arg(short, long)]
-    resource_name: String,
+    #[arg(short, long, required = true)]
+    resource_name: Option<String>,
     /// Image reference
+    #[arg(short, long, required = true)]
+    image: Option<String>,
+    /// Pull the image and unpack it to the image volume mountpoint, for clusters without image
+    /// volume support
+    #[arg(long)]
+    pull: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Offline computation, e.g. in image build pipelines, without a Kubernetes client
+#[derive(Subcommand)]
+enum Command {
+    /// Print the computed PCRs as JSON in the compute-pcrs output format
+    Compute(OfflineArgs),
+    /// Print the value of the org.coreos.pcrs image label with the computed PCRs
+    Label(OfflineArgs),
+}
+
+#[derive(clap::Args)]
+struct OfflineArgs {
+    #[command(flatten)]
+    input: Input,
+    /// Directory with the mok-variables of the reference-values repository
+    #[arg(long, default_value = REFERENCE_VALUES_DIR)]
+    reference_values: PathBuf,
+    /// Write to a file instead of stdout
     #[arg(short, long)]
-    image: String,
+    output: Option<PathBuf>,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+#[derive(clap::Args)]
+#[group(required = true, multiple = false)]
+struct Input {
+    /// Root directory of the image, e.g. a mounted or unpacked container image
+    #[arg(long)]
+    dir: Option<PathBuf>,
+    /// OCI archive of the image, e.g. from `podman save --format oci-archive`
+    #[arg(long)]
+    oci_archive: Option<PathBuf>,
+}
+
+/// Inputs of the PCRs specific to a platform profile
+struct ProfileInputs {
+    /// Directory with the Secure Boot variables of the platform, to compute PCR7
+    efi_variables: Option<PathBuf>,
+    /// Directory with the MOK variables of the platform, to compute PCR14 with them rather than
+    /// with those of the reference-values repository
+    mok_variables: Option<PathBuf>,
+    secure_boot: bool,
+}
+
+struct ComputedPcrs {
+    pcrs: Vec<Pcr>,
+    /// PCRs specific to a platform profile by profile
+    profile_pcrs: BTreeMap<String, Vec<Pcr>>,
+    os_id: String,
+    os_version_id: String,
+}
 
-    let kernels = format!("{IMAGE_VOLUME_MOUNTPOINT}/usr/lib/modules");
-    let esp = format!("{IMAGE_VOLUME_MOUNTPOINT}/usr/lib/bootupd/updates");
+/// Compute the PCRs of an image whose files are below `root`. PCR7 and PCR14 depend on the EFI
+/// and MOK variables of the platform and are computed for each of `profiles` that has them.
+fn compute_pcrs(
+    root: &Path,
+    reference_values: &Path,
+    profiles: &BTreeMap<String, ProfileInputs>,
+) -> Result<ComputedPcrs> {
+    let path = |path: &str| root.join(path).to_string_lossy().to_string();
+    let kernels = path("usr/lib/modules");
+    let esp = path("usr/lib/bootupd/updates");
 
-    let mut os_release_file = File::open(format!("{IMAGE_VOLUME_MOUNTPOINT}/etc/os-release"))?;
+    let mut os_release_file = File::open(root.join("etc/os-release"))?;
     let mut os_release_content = String::new();
     os_release_file.read_to_string(&mut os_release_content)?;
     let get_val = |key: &str| {
         os_release_content
             .lines()
             .find_map(|l| l.strip_prefix(&format!("{key}=")))
-            .map(|v| v.trim_matches('"'))
+            .map(|v| v.trim_matches('"').to_string())
             .ok_or(anyhow!("/etc/os-release missed key: {key}"))
     };
     let os_id = get_val("ID")?;
     let os_version_id = get_val("VERSION_ID")?;
 
-    let mokvars = format!("/reference-values/mok-variables/{os_id}-{os_version_id}");
+    let mokvars = reference_values.join(format!("mok-variables/{os_id}-{os_version_id}"));
+    let mokvars = mokvars.to_string_lossy().to_string();
 
     let pcrs = vec![
         compute_pcr4(&kernels, &esp, false, true),
-        // TODO: compute pcr7 when platform EFI vars can be elucidated
         compute_pcr14(&mokvars),
     ];
+    let mut profile_pcrs = BTreeMap::new();
+    for (profile, inputs) in profiles {
+        let mut pcrs = Vec::new();
+        let dirs = [&inputs.efi_variables, &inputs.mok_variables];
+        if let Some(dir) = dirs.into_iter().flatten().find(|dir| !dir.is_dir()) {
+            bail!("{} of {profile} was not found", dir.display());
+        }
+        if let Some(dir) = &inputs.efi_variables {
+            let efivars = dir.to_string_lossy();
+            pcrs.push(compute_pcr7(Some(&efivars), &esp, inputs.secure_boot));
+        }
+        if let Some(dir) = &inputs.mok_variables {
+            pcrs.push(compute_pcr14(&dir.to_string_lossy()));
+        }
+        // Also without specific PCRs, the profile's reference values are those of the image
+        profile_pcrs.insert(profile.clone(), pcrs);
+    }
+    Ok(ComputedPcrs {
+        pcrs,
+        profile_pcrs,
+        os_id,
+        os_version_id,
+    })
+}
 
+fn compute_offline(args: &OfflineArgs) -> Result<ComputePcrsOutput> {
+    let reference_values = &args.reference_values;
+    // PCR7 and PCR14 of platforms are specific to platform profiles, which the label cannot
+    // carry, so only the PCRs of the image are computed
+    let profiles = BTreeMap::new();
+    let compute = |root: &Path| compute_pcrs(root, reference_values, &profiles);
+    let computed = match (&args.input.dir, &args.input.oci_archive) {
+        (Some(dir), _) => compute(dir)?,
+        (None, Some(archive)) => {
+            let root = std::env::temp_dir().join(format!("compute-pcrs-{}", std::process::id()));
+            let unpacked = oci_archive::unpack_oci_archive(archive, &root);
+            let computed = unpacked.and_then(|_| compute(&root));
+            if root.exists() {
+                fs::remove_dir_all(&root)?;
+            }
+            computed?
+        }
+        (None, None) => unreachable!("clap requires an input"),
+    };
+    let (os_id, os_version_id) = (computed.os_id, computed.os_version_id);
+    eprintln!("Computed PCRs of {os_id} {os_version_id}");
+    Ok(ComputePcrsOutput {
+        pcrs: computed.pcrs,
+    })
+}
+
+fn write_output(args: &OfflineArgs, output: &str) -> Result<()> {
+    match &args.output {
+        Some(path) => fs::write(path, format!("{output}\n"))
+            .with_context(|| format!("Writing {} failed", path.display())),
+        None => {
+            println!("{output}");
+            Ok(())
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    match &args.command {
+        Some(Command::Compute(offline)) => {
+            let output = compute_offline(offline)?;
+            write_output(offline, &serde_json::to_string_pretty(&output)?)
+        }
+        // Compact, as label values are single lines
+        Some(Command::Label(offline)) => {
+            let output = compute_offline(offline)?;
+            write_output(offline, &serde_json::to_string(&output)?)
+        }
+        None => {
+            let resource_name = args.resource_name.context("No ApprovedImage name given")?;
+            let image_ref = args.image.context("No image given")?;
+            run_job(&resource_name, image_ref, args.pull).await
+        }
+    }
+}
+
+/// Compute the PCRs of an ApprovedImage for the architecture of this node and store them
+async fn run_job(resource_name: &str, image_ref: String, pull: bool) -> Result<()> {
     let client = Client::try_default().await?;
-    let config_maps: Api<ConfigMap> = Api::default_namespaced(client.clone());
+    let approved_images: Api<ApprovedImage> = Api::default_namespaced(client.clone());
+    let image = approved_images.get(resource_name).await?;
+    let root = Path::new(IMAGE_VOLUME_MOUNTPOINT);
+    if pull {
+        pull::pull_image(client.clone(), &image, &image_ref, root).await?;
+    }
+    let reference_values = Path::new(REFERENCE_VALUES_DIR);
+    let profiles = get_platform_profiles(client.clone(), &image).await?;
+    // Recorded before the computation, so that later changes of the variables compute it again
+    let records = profile_records(client.clone(), &profiles).await?;
+    let mut inputs = BTreeMap::new();
+    for profile in &profiles {
+        let name = profile.metadata.name.as_ref();
+        let name = name.context("PlatformProfile had no name")?;
+        // The operator mounts the variables of each profile at its name
+        let mounted = |mountpoint: &str, config_map: &Option<String>| {
+            config_map
+                .as_ref()
+                .map(|_| Path::new(mountpoint).join(name))
+        };
+        let spec = &profile.spec;
+        let efi_variables = mounted(
+            EFI_VARIABLES_MOUNTPOINT,
+            &spec.efi_variables_config_map_name,
+        );
+        let mok_variables = mounted(
+            MOK_VARIABLES_MOUNTPOINT,
+            &spec.mok_variables_config_map_name,
+        );
+        let firmware = spec.firmware.as_ref();
+        let profile_inputs = ProfileInputs {
+            efi_variables,
+            mok_variables,
+            secure_boot: firmware.and_then(|f| f.secure_boot).unwrap_or(true),
+        };
+        inputs.insert(name.clone(), profile_inputs);
+    }
+    let ComputedPcrs {
+        pcrs,
+        profile_pcrs,
+        os_id,
+        os_version_id,
+    } = compute_pcrs(root, reference_values, &inputs)?;
 
-    let mut image_pcrs_map = config_maps.get(PCR_CONFIG_MAP).await?;
-    let image_pcrs_data = image_pcrs_map
-        .data
-        .context("Image PCRs map existed, but had no data")?;
-    let image_pcrs_str = image_pcrs_data
-        .get(PCR_CONFIG_FILE)
-        .context("Image PCRs data existed, but had no file")?;
-    let mut image_pcrs: ImagePcrs = serde_json::from_str(image_pcrs_str)?;
+    let (_, not_after) = image_validity(&image.spec)?;
+    // systemd-stub measures the command line that a UKI is passed into PCR12. GRUB measures it
+    // into PCR8 along with its configuration, which cannot be computed, so other images with
+    // command lines are not trusted.
+    let kernel_command_lines = image.spec.kernel_command_lines.clone().unwrap_or_default();
+    let kernel_cmdline_pcrs = match uki::is_uki(root)? {
+        true => kernel_cmdline_pcrs(&image.spec),
+        false => Vec::new(),
+    };
 
     let image_pcr = ImagePcr {
         first_seen: Timestamp::now(),
-        reference: args.image,
-        pcrs,
+        reference: image_ref,
+        pcrs: Vec::new(),
+        not_after,
+        architectures: BTreeMap::new(),
+        origins: BTreeMap::new(),
+        profile_pcrs: BTreeMap::new(),
+        profiles: records,
+        kernel_cmdline_pcrs,
+        kernel_command_lines,
+    };
+    let origin = PcrOrigin {
+        computed: true,
+        os_id: Some(os_id),
+        os_version_id: Some(os_version_id),
+        time: Timestamp::now(),
     };
-    image_pcrs.0.insert(args.resource_name.clone(), image_pcr);
-    update_image_pcrs!(config_maps, image_pcrs_map, image_pcrs);
+    let owner_reference = generate_owner_reference(&image)?;
+    // The image was pulled for the architecture of this node, as a volume or with --pull
+    let architecture = oci_architecture();
+    let image_pcr = store_architecture_pcrs(
+        client,
+        resource_name,
+        architecture,
+        ArchitecturePcrs { pcrs, profile_pcrs },
+        origin,
+        image_pcr,
+        owner_reference,
+    );
+    // The Job of the last pending architecture commits the image
+    let image_pcr = image_pcr.await?;
+    if !image_pcr.pending_architectures().is_empty() {
+        return Ok(());
+    }
+    if image_pcr.unmeasured_command_lines() {
+        // The operator reports this as it watches the ConfigMap
+        eprintln!("{resource_name} is no UKI. Its kernel command lines cannot be verified.");
+        return Ok(());
+    }
 
-    let approved_images: Api<ApprovedImage> = Api::default_namespaced(client);
-    let image = approved_images.get(&args.resource_name).await?;
     let committed = committed_condition(INSTALLED_REASON, image.metadata.generation, &None);
     let conditions = Some(vec![committed]);
-    let status = ApprovedImageStatus { conditions };
-    update_status!(approved_images, &args.resource_name, status)?;
+    // The operator reports the PCRs as it watches their ConfigMap
+    let status = ApprovedImageStatus {
+        conditions,
+        pcrs: None,
+    };
+    update_status!(approved_images, resource_name, status)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &str) -> Result<Args, clap::Error> {
+        Args::try_parse_from(args.split(' '))
+    }
+
+    #[test]
+    fn test_args() {
+        Args::command().debug_assert();
+        let args = parse("compute-pcrs --image quay.io/org/image -r image").unwrap();
+        assert!(args.command.is_none() && !args.pull);
+        assert!(parse("compute-pcrs --image quay.io/org/image").is_err());
+    }
+
+    #[test]
+    fn test_args_offline() {
+        let args = parse("compute-pcrs label --oci-archive image.tar -o pcrs").unwrap();
+        let Some(Command::Label(offline)) = args.command else {
+            panic!("label subcommand was not parsed");
+        };
+        assert_eq!(offline.input.oci_archive, Some(PathBuf::from("image.tar")));
+        let reference_values = PathBuf::from(REFERENCE_VALUES_DIR);
+        assert_eq!(offline.reference_values, reference_values);
+        let args = parse("compute-pcrs compute --dir /").unwrap();
+        assert!(matches!(args.command, Some(Command::Compute(_))));
+        assert!(parse("compute-pcrs compute --dir / --efi-variables azure").is_err());
+        assert!(parse("compute-pcrs compute --dir / --oci-archive image.tar").is_err());
+        assert!(parse("compute-pcrs compute").is_err());
+    }
+}
@@ -0,0 +1,193 @@
+// SPDX-FileCopyrightText: Jakob Naucke <jnaucke@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+// Reading of OCI archives, tar archives of an OCI image layout as written by
+// `podman save --format oci-archive` or `skopeo copy ... oci-archive:<file>`. Layers can be
+// large and blobs can be in any order, so the archive is indexed once, and the index, manifests
+// and layers are then read from their offsets.
+
+use anyhow::{Context, Result, ensure};
+use oci_client::manifest::{OciImageManifest, OciManifest};
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::{fs::File, path::Path};
+
+use crate::unpack::{normalize_path, unpack_layer};
+use trusted_cluster_operator_lib::reference_values::oci_architecture;
+
+const INDEX_FILE: &str = "index.json";
+/// Maximum size of the index and manifests of an archive
+const MAX_METADATA_SIZE: u64 = 4 << 20;
+
+fn blob_path(digest: &str) -> Result<String> {
+    let (algorithm, encoded) = digest.split_once(':').context("Digest had no algorithm")?;
+    Ok(format!("blobs/{algorithm}/{encoded}"))
+}
+
+/// Offset and size of the content of the regular files of an archive by path
+fn index_archive(archive: &mut File) -> Result<BTreeMap<String, (u64, u64)>> {
+    let mut files = BTreeMap::new();
+    let mut reader = tar::Archive::new(archive);
+    for entry in reader.entries_with_seek()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_file() {
+            let position = (entry.raw_file_position(), entry.size());
+            files.insert(normalize_path(&entry.path()?), position);
+        }
+    }
+    Ok(files)
+}
+
+/// Reader of the content of the file at `path` of an indexed archive
+fn read_file<'a>(
+    archive: &'a mut File,
+    files: &BTreeMap<String, (u64, u64)>,
+    path: &str,
+) -> Result<impl Read + 'a> {
+    let (offset, size) = files
+        .get(path)
+        .with_context(|| format!("Archive did not contain {path}"))?;
+    archive.seek(SeekFrom::Start(*offset))?;
+    Ok(archive.take(*size))
+}
+
+/// Image manifest of the archive for the architecture of this machine, following image indexes.
+/// An index with a single manifest is followed regardless of its platform.
+fn select_manifest(
+    read: &mut impl FnMut(&str) -> Result<Vec<u8>>,
+    manifest: &[u8],
+) -> Result<OciImageManifest> {
+    let index = match serde_json::from_slice(manifest)? {
+        OciManifest::Image(manifest) => return Ok(manifest),
+        OciManifest::ImageIndex(index) => index,
+    };
+    let architecture = oci_architecture();
+    let entry = match index.manifests.as_slice() {
+        [entry] => entry,
+        entries => {
+            let platform = |p: &oci_client::manifest::Platform| {
+                p.os == "linux" && p.architecture.to_string() == architecture
+            };
+            let entry = entries
+                .iter()
+                .find(|e| e.platform.as_ref().is_some_and(platform));
+            entry.with_context(|| format!("Archive had no image for linux/{architecture}"))?
+        }
+    };
+    let manifest = read(&blob_path(&entry.digest)?)?;
+    select_manifest(read, &manifest)
+}
+
+/// Unpack the paths that PCRs are computed from of the image in an OCI archive to `root`
+pub fn unpack_oci_archive(archive: &Path, root: &Path) -> Result<()> {
+    let mut archive = File::open(archive)?;
+    let files = index_archive(&mut archive)?;
+    let mut read_metadata = |path: &str| {
+        let size = files.get(path).map_or(0, |(_, size)| *size);
+        ensure!(
+            size <= MAX_METADATA_SIZE,
+            "{path} was larger than {MAX_METADATA_SIZE} bytes"
+        );
+        let mut data = Vec::new();
+        read_file(&mut archive, &files, path)?.read_to_end(&mut data)?;
+        Ok(data)
+    };
+    let index = read_metadata(INDEX_FILE)?;
+    let manifest = select_manifest(&mut read_metadata, &index)?;
+    for layer in &manifest.layers {
+        let content = read_file(&mut archive, &files, &blob_path(&layer.digest)?)?;
+        unpack_layer(root, content, &layer.media_type)
+            .with_context(|| format!("Unpacking layer {} failed", layer.digest))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unpack::tests::{finish, push_entry, test_root};
+    use serde_json::json;
+    use std::fs;
+    use tar::EntryType;
+
+    const TAR_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+
+    fn manifest(layer_digest: &str) -> Vec<u8> {
+        let manifest = json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {
+                "mediaType": "application/vnd.oci.image.config.v1+json",
+                "digest": "sha256:c0",
+                "size": 2,
+            },
+            "layers": [{"mediaType": TAR_MEDIA_TYPE, "digest": layer_digest, "size": 1024}],
+        });
+        serde_json::to_vec(&manifest).unwrap()
+    }
+
+    fn index(entries: &[(&str, &str)]) -> Vec<u8> {
+        let manifests: Vec<_> = entries
+            .iter()
+            .map(|(digest, architecture)| {
+                json!({
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "digest": digest,
+                    "size": 1,
+                    "platform": {"architecture": architecture, "os": "linux"},
+                })
+            })
+            .collect();
+        let index = json!({"schemaVersion": 2, "manifests": manifests});
+        serde_json::to_vec(&index).unwrap()
+    }
+
+    fn read(files: &BTreeMap<String, Vec<u8>>) -> impl FnMut(&str) -> Result<Vec<u8>> + '_ {
+        |path| files.get(path).cloned().context("Missing file")
+    }
+
+    #[test]
+    fn test_select_manifest_platform() {
+        let other = match oci_architecture() {
+            "s390x" => "amd64",
+            _ => "s390x",
+        };
+        let files = BTreeMap::from([
+            ("blobs/sha256/m1".to_string(), manifest("sha256:native")),
+            ("blobs/sha256/m2".to_string(), manifest("sha256:other")),
+        ]);
+        let index = index(&[("sha256:m2", other), ("sha256:m1", oci_architecture())]);
+        let manifest = select_manifest(&mut read(&files), &index).unwrap();
+        assert_eq!(manifest.layers[0].digest, "sha256:native");
+    }
+
+    #[test]
+    fn test_select_manifest_missing_platform() {
+        let index = index(&[("sha256:m1", "riscv"), ("sha256:m2", "mips")]);
+        assert!(select_manifest(&mut read(&BTreeMap::new()), &index).is_err());
+    }
+
+    #[test]
+    fn test_unpack_oci_archive() {
+        let root = test_root("archive");
+        let file = EntryType::Regular;
+        let mut layer = tar::Builder::new(Vec::new());
+        push_entry(&mut layer, "usr/lib/os-release", file, "", b"ID=fedora");
+        let layer = finish(layer);
+        let mut archive = tar::Builder::new(Vec::new());
+        push_entry(&mut archive, "blobs/sha256/l1", file, "", &layer);
+        let manifest = manifest("sha256:l1");
+        push_entry(&mut archive, "blobs/sha256/m1", file, "", &manifest);
+        let index = index(&[("sha256:m1", "any")]);
+        push_entry(&mut archive, INDEX_FILE, file, "", &index);
+        let path = root.with_extension("tar");
+        fs::write(&path, finish(archive)).unwrap();
+
+        unpack_oci_archive(&path, &root).unwrap();
+        let os_release = fs::read_to_string(root.join("usr/lib/os-release")).unwrap();
+        assert_eq!(os_release, "ID=fedora");
+        fs::remove_file(&path).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
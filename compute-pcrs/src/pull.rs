@@ -0,0 +1,45 @@
+// SPDX-FileCopyrightText: Jakob Naucke <jnaucke@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+// Pulling of the files that PCRs are computed from, for clusters that cannot mount the image as
+// an image volume. The layers of the image are read with an OCI client and unpacked in order.
+
+use anyhow::{Context, Result};
+use kube::Client;
+use oci_client::{Reference, secrets::RegistryAuth};
+use std::path::Path;
+
+use crate::unpack::unpack_layer;
+use trusted_cluster_operator_lib::{ApprovedImage, registry_auth::image_pull_credentials};
+
+const LAYER_FILE: &str = ".layer";
+
+/// Pull an image for the architecture of this node and unpack the paths that PCRs are computed
+/// from to `root`, with the credentials of the ApprovedImage's image pull secrets
+pub async fn pull_image(
+    client: Client,
+    image: &ApprovedImage,
+    reference: &str,
+    root: &Path,
+) -> Result<()> {
+    let reference: Reference = reference.parse()?;
+    let repository = format!("{}/{}", reference.registry(), reference.repository());
+    let credentials = image_pull_credentials(client, image, &repository).await?;
+    let auth = credentials.map(|(username, password)| RegistryAuth::Basic(username, password));
+    let auth = auth.unwrap_or(RegistryAuth::Anonymous);
+    // The default platform resolver selects the architecture of this node from image indexes
+    let oci_client = oci_client::Client::new(Default::default());
+    let (manifest, _) = oci_client.pull_image_manifest(&reference, &auth).await?;
+    // Layers are downloaded to a file and unpacked from there rather than kept in memory
+    let download = root.join(LAYER_FILE);
+    for layer in &manifest.layers {
+        let mut file = tokio::fs::File::create(&download).await?;
+        oci_client.pull_blob(&reference, layer, &mut file).await?;
+        file.sync_all().await?;
+        let unpacked = unpack_layer(root, std::fs::File::open(&download)?, &layer.media_type);
+        unpacked.with_context(|| format!("Unpacking layer {} failed", layer.digest))?;
+    }
+    tokio::fs::remove_file(&download).await?;
+    Ok(())
+}
@@ -0,0 +1,143 @@
+// SPDX-FileCopyrightText: Jakob Naucke <jnaucke@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+// Detection of unified kernel images (UKIs). A UKI is a PE binary of systemd-stub that carries
+// the kernel in its .linux section, which boot loaders find in the EFI/Linux directory of the ESP
+// and kernel-install places next to the kernel modules.
+
+use anyhow::Result;
+use std::io::{self, Read};
+use std::{fs, path::Path};
+
+/// Directories of the image that UKIs are installed to, and whether their UKIs are one level
+/// further down, per kernel version
+const UKI_DIRS: [(&str, bool); 2] = [
+    ("usr/lib/bootupd/updates/EFI/Linux", false),
+    ("usr/lib/modules", true),
+];
+/// PE headers and section tables lie well within this size
+const MAX_HEADER_SIZE: u64 = 64 << 10;
+const UKI_SECTION: &str = ".linux";
+
+/// Section names of a PE binary, None if `data` does not start with one
+fn pe_sections(data: &[u8]) -> Option<Vec<String>> {
+    let u16_at = |offset: usize| {
+        Some(u16::from_le_bytes(
+            data.get(offset..offset + 2)?.try_into().ok()?,
+        ))
+    };
+    let u32_at = |offset: usize| {
+        Some(u32::from_le_bytes(
+            data.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+    if data.get(..2)? != b"MZ" {
+        return None;
+    }
+    let pe = usize::try_from(u32_at(0x3c)?).ok()?;
+    if data.get(pe..pe + 4)? != b"PE\0\0" {
+        return None;
+    }
+    let sections = usize::from(u16_at(pe + 6)?);
+    let table = pe + 24 + usize::from(u16_at(pe + 20)?);
+    let name = |i: usize| {
+        let name = data.get(table + i * 40..table + i * 40 + 8)?;
+        let end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+        Some(String::from_utf8_lossy(&name[..end]).to_string())
+    };
+    (0..sections).map(name).collect()
+}
+
+fn is_uki_file(path: &Path) -> Result<bool> {
+    if path.extension().is_none_or(|e| e != "efi") || !path.is_file() {
+        return Ok(false);
+    }
+    let mut data = Vec::new();
+    fs::File::open(path)?
+        .take(MAX_HEADER_SIZE)
+        .read_to_end(&mut data)?;
+    let sections = pe_sections(&data).unwrap_or_default();
+    Ok(sections.iter().any(|s| s == UKI_SECTION))
+}
+
+/// Entries of a directory, none if it does not exist
+fn dir_entries(dir: &Path) -> Result<Vec<std::path::PathBuf>> {
+    match fs::read_dir(dir) {
+        Ok(entries) => Ok(entries
+            .map(|e| e.map(|e| e.path()))
+            .collect::<io::Result<_>>()?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Whether the image whose files are below `root` boots as a UKI
+pub fn is_uki(root: &Path) -> Result<bool> {
+    for (dir, per_version) in UKI_DIRS {
+        let mut candidates = dir_entries(&root.join(dir))?;
+        if per_version {
+            let versions = candidates.iter().filter(|p| p.is_dir());
+            let entries = versions
+                .map(|v| dir_entries(v))
+                .collect::<Result<Vec<_>>>()?;
+            candidates = entries.into_iter().flatten().collect();
+        }
+        for candidate in candidates {
+            if is_uki_file(&candidate)? {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unpack::tests::test_root;
+
+    /// PE binary with the sections of the given names and no content
+    fn pe(sections: &[&str]) -> Vec<u8> {
+        let mut data = vec![0; 0x40];
+        data[..2].copy_from_slice(b"MZ");
+        data[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        data.extend_from_slice(b"PE\0\0");
+        let mut coff = [0; 20];
+        coff[2..4].copy_from_slice(&(sections.len() as u16).to_le_bytes());
+        data.extend_from_slice(&coff);
+        for section in sections {
+            let mut header = [0; 40];
+            header[..section.len()].copy_from_slice(section.as_bytes());
+            data.extend_from_slice(&header);
+        }
+        data
+    }
+
+    #[test]
+    fn test_pe_sections() {
+        let sections = pe_sections(&pe(&[".text", ".linux"])).unwrap();
+        assert_eq!(sections, vec![".text", ".linux"]);
+        assert!(pe_sections(b"\x7fELF").is_none());
+    }
+
+    #[test]
+    fn test_is_uki() {
+        let root = test_root("uki");
+        let modules = root.join("usr/lib/modules/6.0");
+        fs::create_dir_all(&modules).unwrap();
+        fs::write(modules.join("vmlinuz"), b"kernel").unwrap();
+        let esp = root.join("usr/lib/bootupd/updates/EFI/fedora");
+        fs::create_dir_all(&esp).unwrap();
+        fs::write(esp.join("shimx64.efi"), pe(&[".text", ".sbat"])).unwrap();
+        assert!(!is_uki(&root).unwrap());
+
+        fs::write(
+            modules.join("6.0.efi"),
+            pe(&[".osrel", ".cmdline", ".linux"]),
+        )
+        .unwrap();
+        assert!(is_uki(&root).unwrap());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,285 @@
+// SPDX-FileCopyrightText: Jakob Naucke <jnaucke@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+// Unpacking of the files that PCRs are computed from out of image layers. Only the paths below
+// are unpacked, applying the whiteouts of upper layers. Layers are streamed through their
+// decompressor and read once.
+
+use anyhow::{Context, Result, bail};
+use flate2::read::GzDecoder;
+use std::io::{self, Read};
+use std::{fs, os::unix, path::Path};
+
+/// Paths of the image that PCRs are computed from. /etc/os-release commonly links to
+/// /usr/lib/os-release.
+const PATHS: [&str; 4] = [
+    "usr/lib/modules",
+    "usr/lib/bootupd/updates",
+    "usr/lib/os-release",
+    "etc/os-release",
+];
+/// Directory below the root that the other regular files of a layer are unpacked to while it is
+/// read, as wanted hardlinks may point to them
+const STAGING_DIR: &str = ".staging";
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// Path of an entry relative to the archive root, without leading `./` or `/`
+pub fn normalize_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    let components = path.split('/').filter(|c| !c.is_empty() && *c != ".");
+    components.collect::<Vec<_>>().join("/")
+}
+
+/// Whether `path` is strictly below `dir`, with the root as the empty path
+fn is_below(path: &str, dir: &str) -> bool {
+    dir.is_empty() || path.strip_prefix(dir).is_some_and(|r| r.starts_with('/'))
+}
+
+fn wanted(path: &str) -> bool {
+    PATHS.iter().any(|p| path == *p || is_below(path, p))
+}
+
+/// Root-relative path that a symlink at `path` points to, None if it leaves the root. Absolute
+/// targets are relative to the image root, not the root of this container.
+fn symlink_target(path: &str, link: &str) -> Option<String> {
+    let mut components = Vec::new();
+    if !link.starts_with('/') {
+        components.extend(path.split('/'));
+        components.pop();
+    }
+    for component in link.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop()?;
+            }
+            component => components.push(component),
+        }
+    }
+    Some(components.join("/"))
+}
+
+fn remove_path(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path)?,
+        Ok(_) => fs::remove_file(path)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+/// Remove whatever is at `path` and create its parent directories
+fn prepare_path(path: &Path) -> Result<()> {
+    remove_path(path)?;
+    fs::create_dir_all(path.parent().context("Unpacked path had no parent")?)?;
+    Ok(())
+}
+
+/// Remove a path that a whiteout hides from lower layers, or the unpacked paths below it
+fn remove_hidden(root: &Path, path: &str) -> Result<()> {
+    for below in PATHS.into_iter().filter(|p| is_below(p, path)) {
+        remove_path(&root.join(below))?;
+    }
+    if wanted(path) {
+        remove_path(&root.join(path))?;
+    }
+    Ok(())
+}
+
+fn unpack_entry(root: &Path, entry: &mut tar::Entry<'_, impl Read>) -> Result<()> {
+    let path = normalize_path(&entry.path()?);
+    let (parent, name) = path.rsplit_once('/').unwrap_or(("", &path));
+    if path.split('/').any(|c| c == "..") {
+        bail!("Tar entry {path} left the archive root");
+    }
+    if name == OPAQUE_WHITEOUT {
+        return remove_hidden(root, parent);
+    }
+    if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+        let hidden = if parent.is_empty() {
+            hidden.to_string()
+        } else {
+            format!("{parent}/{hidden}")
+        };
+        return remove_hidden(root, &hidden);
+    }
+    let staging = root.join(STAGING_DIR);
+    let staging = staging.as_path();
+    let link = entry.link_name()?.map(|l| l.to_string_lossy().to_string());
+    let link = link.unwrap_or_default();
+    match entry.header().entry_type() {
+        tar::EntryType::Directory if wanted(&path) => fs::create_dir_all(root.join(&path))?,
+        tar::EntryType::Regular | tar::EntryType::Continuous => {
+            let dest = if wanted(&path) { root } else { staging }.join(&path);
+            prepare_path(&dest)?;
+            io::copy(entry, &mut fs::File::create(&dest)?)?;
+        }
+        tar::EntryType::Link if wanted(&path) => {
+            let target = normalize_path(Path::new(&link));
+            let source = if wanted(&target) { root } else { staging }.join(&target);
+            if !source.exists() {
+                bail!("Hardlink {path} pointed to {target}, which the layer did not contain");
+            }
+            let dest = root.join(&path);
+            prepare_path(&dest)?;
+            fs::hard_link(source, dest)?;
+        }
+        tar::EntryType::Symlink if wanted(&path) => {
+            let target = symlink_target(&path, &link);
+            let target = target.with_context(|| format!("Symlink {path} left the image root"))?;
+            let dest = root.join(&path);
+            prepare_path(&dest)?;
+            unix::fs::symlink(root.join(target), dest)?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn layer_reader<'a>(layer: impl Read + 'a, media_type: &str) -> Result<Box<dyn Read + 'a>> {
+    if media_type.ends_with("gzip") {
+        Ok(Box::new(GzDecoder::new(layer)))
+    } else if media_type.ends_with("zstd") {
+        Ok(Box::new(zstd::stream::read::Decoder::new(layer)?))
+    } else if media_type.ends_with("tar") {
+        Ok(Box::new(layer))
+    } else {
+        bail!("Unsupported layer media type {media_type}")
+    }
+}
+
+/// Unpack the wanted paths of a layer. Hardlinks may point to files outside of the wanted paths,
+/// e.g. to the object store of ostree-based images, so other regular files are staged until the
+/// layer has been read.
+pub fn unpack_layer(root: &Path, layer: impl Read, media_type: &str) -> Result<()> {
+    let mut archive = tar::Archive::new(layer_reader(layer, media_type)?);
+    let unpacked = archive
+        .entries()?
+        .try_for_each(|entry| unpack_entry(root, &mut entry?));
+    remove_path(&root.join(STAGING_DIR))?;
+    unpacked
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use tar::EntryType;
+
+    const TAR_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+
+    pub fn push_entry(
+        archive: &mut tar::Builder<Vec<u8>>,
+        path: &str,
+        entry_type: EntryType,
+        link: &str,
+        data: &[u8],
+    ) {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(0o644);
+        header.set_size(data.len() as u64);
+        if link.is_empty() {
+            archive.append_data(&mut header, path, data).unwrap();
+        } else {
+            archive.append_link(&mut header, path, link).unwrap();
+        }
+    }
+
+    pub fn finish(archive: tar::Builder<Vec<u8>>) -> Vec<u8> {
+        archive.into_inner().unwrap()
+    }
+
+    pub fn test_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("compute-pcrs-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn test_symlink_target() {
+        let target = symlink_target("etc/os-release", "../usr/lib/os-release");
+        assert_eq!(target.as_deref(), Some("usr/lib/os-release"));
+        let target = symlink_target("etc/os-release", "/usr/lib/os-release");
+        assert_eq!(target.as_deref(), Some("usr/lib/os-release"));
+        assert_eq!(symlink_target("etc/os-release", "../../host"), None);
+    }
+
+    #[test]
+    fn test_unpack_layer() {
+        let root = test_root("unpack");
+        let mut lower = tar::Builder::new(Vec::new());
+        let file = EntryType::Regular;
+        push_entry(&mut lower, "usr/lib/modules/5.0/vmlinuz", file, "", b"old");
+        push_entry(
+            &mut lower,
+            "usr/lib/modules/4.0/vmlinuz",
+            file,
+            "",
+            b"older",
+        );
+        push_entry(&mut lower, "usr/bin/bash", file, "", b"bash");
+        unpack_layer(&root, finish(lower).as_slice(), TAR_MEDIA_TYPE).unwrap();
+
+        let mut upper = tar::Builder::new(Vec::new());
+        push_entry(&mut upper, "usr/lib/modules/.wh.4.0", file, "", b"");
+        let object = "sysroot/ostree/repo/objects/ab/cdef.file";
+        push_entry(&mut upper, object, file, "", b"new");
+        let link = EntryType::Link;
+        push_entry(&mut upper, "usr/lib/modules/5.0/vmlinuz", link, object, b"");
+        push_entry(&mut upper, "usr/lib/os-release", file, "", b"ID=fedora");
+        let symlink = EntryType::Symlink;
+        push_entry(
+            &mut upper,
+            "etc/os-release",
+            symlink,
+            "../usr/lib/os-release",
+            b"",
+        );
+        unpack_layer(&root, finish(upper).as_slice(), TAR_MEDIA_TYPE).unwrap();
+
+        let read = |path: &str| fs::read_to_string(root.join(path)).unwrap();
+        assert_eq!(read("usr/lib/modules/5.0/vmlinuz"), "new");
+        assert_eq!(read("etc/os-release"), "ID=fedora");
+        assert!(!root.join("usr/lib/modules/4.0").exists());
+        assert!(!root.join("usr/bin/bash").exists());
+        assert!(!root.join(STAGING_DIR).exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_unpack_layer_opaque_whiteout() {
+        let root = test_root("opaque");
+        let file = EntryType::Regular;
+        let mut lower = tar::Builder::new(Vec::new());
+        push_entry(&mut lower, "usr/lib/modules/5.0/vmlinuz", file, "", b"old");
+        unpack_layer(&root, finish(lower).as_slice(), TAR_MEDIA_TYPE).unwrap();
+
+        let mut upper = tar::Builder::new(Vec::new());
+        push_entry(&mut upper, "usr/lib/.wh..wh..opq", file, "", b"");
+        push_entry(&mut upper, "usr/lib/modules/6.0/vmlinuz", file, "", b"new");
+        unpack_layer(&root, finish(upper).as_slice(), TAR_MEDIA_TYPE).unwrap();
+
+        assert!(!root.join("usr/lib/modules/5.0").exists());
+        assert!(root.join("usr/lib/modules/6.0/vmlinuz").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_unpack_layer_zstd_long_path() {
+        let root = test_root("zstd");
+        let long = format!("usr/lib/modules/{}/vmlinuz", "a".repeat(120));
+        let mut layer = tar::Builder::new(Vec::new());
+        push_entry(&mut layer, &long, EntryType::Regular, "", b"kernel");
+        let layer = zstd::encode_all(finish(layer).as_slice(), 0).unwrap();
+        let media_type = "application/vnd.oci.image.layer.v1.tar+zstd";
+        unpack_layer(&root, layer.as_slice(), media_type).unwrap();
+
+        assert_eq!(fs::read_to_string(root.join(long)).unwrap(), "kernel");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
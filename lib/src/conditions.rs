@@ -7,6 +7,7 @@ pub const INSTALLED_REASON: &str = "InstallationCompleted";
 pub const NOT_INSTALLED_REASON_NON_UNIQUE: &str = "NonUnique";
 pub const NOT_INSTALLED_REASON_INSTALLING: &str = "Installing";
 pub const NOT_INSTALLED_REASON_UNINSTALLING: &str = "Uninstalling";
+pub const NOT_INSTALLED_REASON_INVALID: &str = "InvalidConfiguration";
 
 pub const KNOWN_TRUSTEE_ADDRESS_CONDITION: &str = "KnownTrusteeAddress";
 pub const KNOWN_TRUSTEE_ADDRESS_REASON: &str = "AddressFound";
@@ -18,6 +19,25 @@ pub const NOT_COMMITTED_REASON_COMPUTING: &str = "Computing";
 pub const NOT_COMMITTED_REASON_NO_DIGEST: &str = "NoDigestGiven";
 pub const NOT_COMMITTED_REASON_FAILED: &str = "ComputationFailed";
 pub const NOT_COMMITTED_REASON_PENDING: &str = "PodPending";
+pub const NOT_COMMITTED_REASON_NOT_YET: &str = "NotYetValid";
+pub const NOT_COMMITTED_REASON_EXPIRED: &str = "Expired";
+pub const NOT_COMMITTED_REASON_SIGNATURE_INVALID: &str = "SignatureInvalid";
+pub const NOT_COMMITTED_REASON_QUEUED: &str = "Queued";
+pub const NOT_COMMITTED_REASON_PROFILE_MISSING: &str = "PlatformProfileMissing";
+pub const NOT_COMMITTED_REASON_CMDLINE_UNMEASURED: &str = "KernelCommandLinesUnmeasured";
+
+pub const PCR_COMBINATIONS_WITHIN_LIMIT_CONDITION: &str = "PcrCombinationsWithinLimit";
+pub const PCR_COMBINATIONS_WITHIN_LIMIT_REASON: &str = "WithinLimit";
+pub const PCR_COMBINATIONS_LIMIT_EXCEEDED_REASON: &str = "LimitExceeded";
+
+pub const TAG_RESOLVED_CONDITION: &str = "TagResolved";
+pub const TAG_RESOLVED_REASON: &str = "DigestResolved";
+pub const TAG_NOT_RESOLVED_REASON_FAILED: &str = "ResolutionFailed";
+pub const TAG_NOT_RESOLVED_REASON_SIGNATURE_MISSING: &str = "SignatureMissing";
+
+pub const EXPIRING_CONDITION: &str = "Expiring";
+pub const EXPIRING_REASON: &str = "ExpiresSoon";
+pub const NOT_EXPIRING_REASON: &str = "NotExpiringSoon";
 
 pub const ATTESTATION_KEY_APPROVED_CONDITION: &str = "Approved";
 pub const ATTESTATION_KEY_REGISTRATION_REASON: &str = "Registration";
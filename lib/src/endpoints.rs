@@ -6,6 +6,10 @@ pub const TRUSTEE_SERVICE: &str = "kbs-service";
 pub const TRUSTEE_DEPLOYMENT: &str = "trustee-deployment";
 pub const TRUSTEE_PORT: i32 = 8080;
 pub const TRUSTEE_APP_LABEL: &str = "kbs";
+pub const RVPS_SERVICE: &str = "rvps-service";
+pub const RVPS_DEPLOYMENT: &str = "rvps-deployment";
+pub const RVPS_PORT: i32 = 50003;
+pub const RVPS_APP_LABEL: &str = "rvps";
 pub const REGISTER_SERVER_SERVICE: &str = "register-server";
 pub const REGISTER_SERVER_DEPLOYMENT: &str = "register-server";
 pub const REGISTER_SERVER_PORT: i32 = 8000;
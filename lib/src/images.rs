@@ -4,5 +4,6 @@
 
 pub const RELATED_IMAGE_COMPUTE_PCRS: &str = "RELATED_IMAGE_COMPUTE_PCRS";
 pub const RELATED_IMAGE_TRUSTEE: &str = "RELATED_IMAGE_TRUSTEE";
+pub const RELATED_IMAGE_RVPS: &str = "RELATED_IMAGE_RVPS";
 pub const RELATED_IMAGE_REGISTRATION_SERVER: &str = "RELATED_IMAGE_REGISTRATION_SERVER";
 pub const RELATED_IMAGE_ATTESTATION_KEY_REGISTER: &str = "RELATED_IMAGE_ATTESTATION_KEY_REGISTER";
@@ -3,10 +3,12 @@
 // SPDX-License-Identifier: MIT
 
 pub mod approvedimages;
+pub mod approvedimagestreams;
 pub mod attestationkeys;
 pub mod certificaterequests;
 pub mod certificates;
 pub mod clusterissuers;
 pub mod issuers;
 pub mod machines;
+pub mod platformprofiles;
 pub mod trustedexecutionclusters;
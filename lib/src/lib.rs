@@ -6,14 +6,17 @@ pub mod conditions;
 pub mod endpoints;
 pub mod images;
 pub mod reference_values;
+pub mod registry_auth;
 
 mod kopium;
 #[allow(clippy::all)]
 mod vendor_kopium;
 use k8s_openapi::jiff::Timestamp;
 pub use kopium::approvedimages::*;
+pub use kopium::approvedimagestreams::*;
 pub use kopium::attestationkeys::*;
 pub use kopium::machines::*;
+pub use kopium::platformprofiles::*;
 pub use kopium::trustedexecutionclusters::*;
 
 pub use kopium::certificaterequests;
@@ -28,6 +31,26 @@ use conditions::*;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, OwnerReference, Time};
 use kube::{Api, Client, Resource};
 
+/// Attempts at an update that conflicts with concurrent writers
+pub const CONFLICT_ATTEMPTS: u32 = 5;
+
+fn is_conflict(error: &anyhow::Error) -> bool {
+    let kube_error = error.downcast_ref::<kube::Error>();
+    matches!(kube_error, Some(kube::Error::Api(ae)) if ae.code == 409)
+}
+
+/// Run an update that reads an object and replaces it at the read resourceVersion again when the
+/// object was changed in between, up to CONFLICT_ATTEMPTS times
+pub async fn retry_on_conflict<T>(mut update: impl AsyncFnMut() -> Result<T>) -> Result<T> {
+    let mut attempt = 1;
+    loop {
+        match update().await {
+            Err(e) if attempt < CONFLICT_ATTEMPTS && is_conflict(&e) => attempt += 1,
+            result => return result,
+        }
+    }
+}
+
 #[macro_export]
 macro_rules! update_status {
     ($api:ident, $name:expr, $status:expr) => {{
@@ -66,6 +89,12 @@ impl Conditions for ApprovedImageStatus {
     }
 }
 
+impl Conditions for ApprovedImageStreamStatus {
+    fn conditions(&self) -> &Option<Vec<Condition>> {
+        &self.conditions
+    }
+}
+
 pub fn transition_time<S: Conditions>(
     existing_status: &Option<S>,
     type_: &str,
@@ -97,6 +126,26 @@ pub fn committed_condition(
             }
             NOT_COMMITTED_REASON_PENDING => "Pod is pending, check pods for details",
             NOT_COMMITTED_REASON_FAILED => "Computation failed, check operator log for details",
+            NOT_COMMITTED_REASON_NOT_YET => "Image is not approved before its notBefore time",
+            NOT_COMMITTED_REASON_EXPIRED => {
+                "Image approval expired at its notAfter time and was withdrawn"
+            }
+            NOT_COMMITTED_REASON_SIGNATURE_INVALID => {
+                "Image had no valid signature by a key of the signature policy, \
+                 check operator log for details"
+            }
+            NOT_COMMITTED_REASON_QUEUED => {
+                "Waiting for other compute-pcrs jobs to finish, \
+                 check pcrComputation.maxConcurrentJobs of the TrustedExecutionCluster"
+            }
+            NOT_COMMITTED_REASON_PROFILE_MISSING => {
+                "A PlatformProfile of spec.platformProfiles did not exist, \
+                 check operator log for details"
+            }
+            NOT_COMMITTED_REASON_CMDLINE_UNMEASURED => {
+                "Image did not boot as a unified kernel image (UKI), \
+                 so spec.kernelCommandLines cannot be verified"
+            }
             _ => "",
         }
         .to_string(),
@@ -106,6 +155,28 @@ pub fn committed_condition(
     }
 }
 
+pub fn expiring_condition(
+    not_after: Option<Timestamp>,
+    expiring: bool,
+    generation: Option<i64>,
+    existing_status: &Option<ApprovedImageStatus>,
+) -> Condition {
+    let status = condition_status(expiring);
+    let type_ = EXPIRING_CONDITION;
+    let (reason, message) = match (expiring, not_after) {
+        (true, Some(not_after)) => (EXPIRING_REASON, format!("Image expires at {not_after}")),
+        _ => (NOT_EXPIRING_REASON, String::new()),
+    };
+    Condition {
+        type_: type_.to_string(),
+        reason: reason.to_string(),
+        message,
+        last_transition_time: transition_time(existing_status, type_, &status),
+        status,
+        observed_generation: generation,
+    }
+}
+
 /// Generate an OwnerReference for any Kubernetes resource
 pub fn generate_owner_reference<T: Resource<DynamicType = ()>>(
     object: &T,
@@ -3,33 +3,759 @@
 //
 // SPDX-License-Identifier: MIT
 
+use anyhow::{Context, Result, anyhow};
 use compute_pcrs_lib::Pcr;
-use k8s_openapi::jiff::Timestamp;
+use k8s_openapi::api::core::v1::ConfigMap;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use k8s_openapi::jiff::{SignedDuration, Timestamp};
+use kube::api::{DeleteParams, ListParams, ObjectMeta};
+use kube::{Api, Client};
+use openssl::sha::sha256;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
+use crate::{ApprovedImage, ApprovedImageSpec, retry_on_conflict};
+use crate::{PlatformProfile, PlatformProfileEvidenceType};
+
+/// ConfigMap that held the PCRs of all images in earlier versions. Per-image ConfigMaps are named
+/// with this prefix.
 pub const PCR_CONFIG_MAP: &str = "image-pcrs";
 pub const PCR_CONFIG_FILE: &str = "image-pcrs.json";
+pub const IMAGE_PCR_FILE: &str = "image-pcr.json";
+/// Label of per-image PCR ConfigMaps
+pub const IMAGE_PCR_LABEL: &str = "trusted-execution-clusters.io/image-pcrs";
+const APPROVED_IMAGE_ANNOTATION: &str = "trusted-execution-clusters.io/approved-image";
 pub const IMAGE_VOLUME_MOUNTPOINT: &str = "/image";
+/// EFI variables of platform profiles are mounted below this directory, one directory per profile
+pub const EFI_VARIABLES_MOUNTPOINT: &str = "/efivars";
+/// MOK variables of platform profiles are mounted below this directory, one directory per profile
+pub const MOK_VARIABLES_MOUNTPOINT: &str = "/mok-variables";
+/// Validity of reference values of images without notAfter, counted from when they were first seen
+pub const DEFAULT_IMAGE_VALIDITY: SignedDuration = SignedDuration::from_hours(365 * 24);
 
+/// Output of compute-pcrs, and the value of the org.coreos.pcrs image label. Synchronize with
+/// compute_pcrs_cli::Output.
 #[derive(Deserialize, Serialize)]
+pub struct ComputePcrsOutput {
+    pub pcrs: Vec<Pcr>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct ImagePcr {
     pub first_seen: Timestamp,
+    /// PCRs of an unknown architecture, as stored by earlier versions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub pcrs: Vec<Pcr>,
     pub reference: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_after: Option<Timestamp>,
+    /// PCRs by OCI architecture of the image, e.g. amd64. Architectures whose PCRs are still
+    /// being computed have none.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub architectures: BTreeMap<String, Vec<Pcr>>,
+    /// Origins of the PCRs by OCI architecture. Unknown for PCRs stored by earlier versions.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub origins: BTreeMap<String, PcrOrigin>,
+    /// PCRs specific to a platform profile, such as PCR7, by OCI architecture and profile. The
+    /// profiles of an architecture are alternatives that each replace the PCRs of the same ID.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profile_pcrs: BTreeMap<String, BTreeMap<String, Vec<Pcr>>>,
+    /// Platform profiles that the PCRs were computed for by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, ProfileRecord>,
+    /// PCRs of the kernel command lines of the image, which are alternatives that apply to all
+    /// architectures. They have no events and are not combined. Only images that boot as a UKI
+    /// have them, see `unmeasured_command_lines`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kernel_cmdline_pcrs: Vec<Pcr>,
+    /// Kernel command lines of the ApprovedImage as of the computation of the PCRs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kernel_command_lines: Vec<String>,
+}
+
+/// PCRs of one architecture of an image
+pub struct ArchitecturePcrs {
+    pub pcrs: Vec<Pcr>,
+    /// PCRs specific to a platform profile by profile
+    pub profile_pcrs: BTreeMap<String, Vec<Pcr>>,
+}
+
+/// Platform profile as of the computation of an image's PCRs
+#[derive(Clone, Deserialize, Serialize, PartialEq)]
+pub struct ProfileRecord {
+    /// Evidence type that the reference values of the profile are verified against
+    pub evidence_type: String,
+    /// Generation of the PlatformProfile, to compute the PCRs again when it changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation: Option<i64>,
+    /// Hash of the contents of the EFI and MOK variable ConfigMaps of the PlatformProfile, to
+    /// compute the PCRs again when they change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variables_hash: Option<String>,
+}
+
+impl ProfileRecord {
+    pub fn new(profile: &PlatformProfile, variables_hash: String) -> Self {
+        let evidence_type = match profile.spec.evidence_type {
+            PlatformProfileEvidenceType::Tpm => "tpm",
+            PlatformProfileEvidenceType::AzSnpVtpm => "az-snp-vtpm",
+        };
+        Self {
+            evidence_type: evidence_type.to_string(),
+            generation: profile.metadata.generation,
+            variables_hash: Some(variables_hash),
+        }
+    }
+}
+
+/// Where and when the PCRs of an architecture were obtained
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PcrOrigin {
+    /// Computed by a compute-pcrs Job rather than read from the org.coreos.pcrs label
+    pub computed: bool,
+    /// ID from /etc/os-release of the image, only known when computed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_id: Option<String>,
+    /// VERSION_ID from /etc/os-release of the image, only known when computed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_version_id: Option<String>,
+    pub time: Timestamp,
+}
+
+impl PcrOrigin {
+    /// Origin of PCRs read from the label now
+    pub fn label() -> Self {
+        Self {
+            computed: false,
+            os_id: None,
+            os_version_id: None,
+            time: Timestamp::now(),
+        }
+    }
+}
+
+impl ImagePcr {
+    /// Time at which reference values of this image expire
+    pub fn expiration(&self) -> Timestamp {
+        let default = self.first_seen + DEFAULT_IMAGE_VALIDITY;
+        self.not_after.unwrap_or(default)
+    }
+
+    /// PCRs by architecture, with those of an unknown architecture under None
+    pub fn architecture_pcrs(&self) -> impl Iterator<Item = (Option<&str>, &[Pcr])> {
+        let unknown = Some((None, self.pcrs.as_slice())).filter(|(_, p)| !p.is_empty());
+        let architectures = self.architectures.iter();
+        let known = architectures.map(|(arch, pcrs)| (Some(arch.as_str()), pcrs.as_slice()));
+        unknown.into_iter().chain(known)
+    }
+
+    /// PCRs as of `architecture_pcrs`, once per platform profile of an architecture with profiles,
+    /// where the PCRs specific to the profile replace those of the same ID
+    pub fn platform_pcrs(&self) -> Vec<(Option<&str>, Option<&str>, Vec<Pcr>)> {
+        let mut platform_pcrs = Vec::new();
+        for (architecture, pcrs) in self.architecture_pcrs() {
+            let platforms = architecture.and_then(|a| self.profile_pcrs.get(a));
+            let platforms = platforms.filter(|p| !p.is_empty() && !pcrs.is_empty());
+            let Some(platforms) = platforms else {
+                platform_pcrs.push((architecture, None, pcrs.to_vec()));
+                continue;
+            };
+            for (platform, specific) in platforms {
+                let replaced = |pcr: &&Pcr| specific.iter().any(|s| s.id == pcr.id);
+                let base = pcrs.iter().filter(|pcr| !replaced(pcr));
+                let mut combined: Vec<_> = base.chain(specific).cloned().collect();
+                combined.sort_by_key(|pcr| pcr.id);
+                platform_pcrs.push((architecture, Some(platform.as_str()), combined));
+            }
+        }
+        platform_pcrs
+    }
+
+    /// Whether the ApprovedImage had kernel command lines, but the image did not boot as a UKI
+    /// and no PCR12 values were computed for them. Such PCRs are not trusted.
+    pub fn unmeasured_command_lines(&self) -> bool {
+        !self.kernel_command_lines.is_empty() && self.kernel_cmdline_pcrs.is_empty()
+    }
+
+    /// Architectures whose PCRs are still being computed
+    pub fn pending_architectures(&self) -> Vec<&str> {
+        let architectures = self.architectures.iter();
+        let pending = architectures.filter(|(_, pcrs)| pcrs.is_empty());
+        pending.map(|(arch, _)| arch.as_str()).collect()
+    }
+}
+
+/// OCI architecture of this process, as nodes are labeled with in kubernetes.io/arch
+pub fn oci_architecture() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "powerpc64" => "ppc64le",
+        arch => arch,
+    }
+}
+
+/// PCR12 of a unified kernel image (UKI) that the boot loader passed `cmdline` to. systemd-stub
+/// measures the command line as UTF-16 with its terminating NUL, and PCR12 has no other events
+/// unless credentials or system extensions are passed as well.
+pub fn kernel_cmdline_pcr(cmdline: &str) -> Pcr {
+    let utf16 = cmdline.encode_utf16().chain([0]);
+    let measured: Vec<u8> = utf16.flat_map(u16::to_le_bytes).collect();
+    let mut extended = [0; 32].to_vec();
+    extended.extend(sha256(&measured));
+    Pcr {
+        id: 12,
+        value: sha256(&extended).to_vec(),
+        events: Vec::new(),
+    }
+}
+
+/// PCRs of the kernel command lines of an ApprovedImage that boots as a UKI
+pub fn kernel_cmdline_pcrs(spec: &ApprovedImageSpec) -> Vec<Pcr> {
+    let cmdlines = spec.kernel_command_lines.iter().flatten();
+    cmdlines.map(|c| kernel_cmdline_pcr(c)).collect()
+}
+
+/// PCR12 of a machine that no kernel command line was measured into, e.g. one that GRUB booted
+pub fn unmeasured_cmdline_pcr() -> Pcr {
+    Pcr {
+        id: 12,
+        value: [0; 32].to_vec(),
+        events: Vec::new(),
+    }
+}
+
+/// PlatformProfiles that an ApprovedImage references by name, None for those that do not exist
+pub async fn get_opt_platform_profiles(
+    client: Client,
+    image: &ApprovedImage,
+) -> Result<Vec<(String, Option<PlatformProfile>)>> {
+    let profiles: Api<PlatformProfile> = Api::default_namespaced(client);
+    let mut referenced = Vec::new();
+    for name in image.spec.platform_profiles.iter().flatten() {
+        referenced.push((name.clone(), profiles.get_opt(name).await?));
+    }
+    Ok(referenced)
+}
+
+/// PlatformProfiles that an ApprovedImage references
+pub async fn get_platform_profiles(
+    client: Client,
+    image: &ApprovedImage,
+) -> Result<Vec<PlatformProfile>> {
+    let profiles = get_opt_platform_profiles(client, image).await?;
+    let profiles = profiles.into_iter().map(|(name, profile)| {
+        profile.with_context(|| format!("PlatformProfile {name} did not exist"))
+    });
+    profiles.collect()
 }
 
+/// Hash of the contents of the EFI and MOK variable ConfigMaps of a PlatformProfile
+pub async fn profile_variables_hash(client: Client, profile: &PlatformProfile) -> Result<String> {
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client);
+    let spec = &profile.spec;
+    let names = [
+        &spec.efi_variables_config_map_name,
+        &spec.mok_variables_config_map_name,
+    ];
+    let mut contents = Vec::new();
+    for name in names.into_iter().flatten() {
+        let config_map = config_maps.get_opt(name).await?;
+        contents.push(config_map.map(|c| (c.data, c.binary_data)));
+    }
+    Ok(hex::encode(sha256(&serde_json::to_vec(&contents)?)))
+}
+
+/// Records of `profiles` by name, as stored with the PCRs computed for them
+pub async fn profile_records(
+    client: Client,
+    profiles: &[PlatformProfile],
+) -> Result<BTreeMap<String, ProfileRecord>> {
+    let mut records = BTreeMap::new();
+    for profile in profiles {
+        let name = profile.metadata.name.clone();
+        let name = name.context("PlatformProfile had no name")?;
+        let variables_hash = profile_variables_hash(client.clone(), profile).await?;
+        records.insert(name, ProfileRecord::new(profile, variables_hash));
+    }
+    Ok(records)
+}
+
+/// Validity window of an ApprovedImage as (notBefore, notAfter)
+pub fn image_validity(spec: &ApprovedImageSpec) -> Result<(Option<Timestamp>, Option<Timestamp>)> {
+    let parse = |time: &Option<String>| time.as_deref().map(str::parse::<Timestamp>).transpose();
+    Ok((parse(&spec.not_before)?, parse(&spec.not_after)?))
+}
+
+/// PCRs of images by ApprovedImage name
 #[derive(Default, Deserialize, Serialize)]
 pub struct ImagePcrs(pub BTreeMap<String, ImagePcr>);
 
-#[macro_export]
-macro_rules! update_image_pcrs {
-    ($api:ident, $map:ident, $pcrs:ident) => {
-        let image_pcrs_json = serde_json::to_string(&$pcrs)?;
-        let map = (PCR_CONFIG_FILE.to_string(), image_pcrs_json.to_string());
-        let data = std::collections::BTreeMap::from([map]);
-        $map.data = Some(data);
-        $api.replace(PCR_CONFIG_MAP, &Default::default(), &$map)
-            .await?
-    };
+/// Image PCRs from the single ConfigMap of earlier versions
+pub fn get_image_pcrs(image_pcrs_map: ConfigMap) -> Result<ImagePcrs> {
+    let err = "Image PCRs map existed, but had no data";
+    let image_pcrs_data = image_pcrs_map.data.context(err)?;
+    let err = "Image PCRs data existed, but had no file";
+    let image_pcrs_str = image_pcrs_data.get(PCR_CONFIG_FILE).context(err)?;
+    serde_json::from_str(image_pcrs_str).map_err(Into::into)
+}
+
+/// Name of the ConfigMap with the PCRs of an ApprovedImage, within the length limit of names.
+/// Names that are too long are truncated, and their tail replaced by a hash of the full name to
+/// keep the names of different images apart.
+pub fn image_pcr_map_name(image_name: &str) -> String {
+    const MAX_LENGTH: usize = 253;
+    let name = format!("{PCR_CONFIG_MAP}-{image_name}");
+    if name.len() <= MAX_LENGTH {
+        return name;
+    }
+    let hash = sha256(name.as_bytes());
+    let hash: String = hash[..5].iter().map(|b| format!("{b:02x}")).collect();
+    let trimmed: String = name.chars().take(MAX_LENGTH - hash.len() - 1).collect();
+    format!("{}-{hash}", trimmed.trim_end_matches(['-', '.']))
+}
+
+pub fn image_pcr_map(
+    image_name: &str,
+    image_pcr: &ImagePcr,
+    owner_reference: OwnerReference,
+) -> Result<ConfigMap> {
+    let image_pcr_json = serde_json::to_string(image_pcr)?;
+    Ok(ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(image_pcr_map_name(image_name)),
+            labels: Some(BTreeMap::from([(
+                IMAGE_PCR_LABEL.to_string(),
+                "true".to_string(),
+            )])),
+            annotations: Some(BTreeMap::from([(
+                APPROVED_IMAGE_ANNOTATION.to_string(),
+                image_name.to_string(),
+            )])),
+            owner_references: Some(vec![owner_reference]),
+            ..Default::default()
+        },
+        data: Some(BTreeMap::from([(
+            IMAGE_PCR_FILE.to_string(),
+            image_pcr_json,
+        )])),
+        ..Default::default()
+    })
+}
+
+/// ApprovedImage name and PCRs of a per-image ConfigMap
+fn parse_image_pcr_map(config_map: &ConfigMap) -> Result<(String, ImagePcr)> {
+    let name = config_map.metadata.name.as_deref().unwrap_or("<no name>");
+    let annotations = config_map.metadata.annotations.as_ref();
+    let image_name = annotations.and_then(|a| a.get(APPROVED_IMAGE_ANNOTATION));
+    let err = format!("ConfigMap {name} had no {APPROVED_IMAGE_ANNOTATION} annotation");
+    let image_name = image_name.context(err)?;
+    let data = config_map.data.as_ref().and_then(|d| d.get(IMAGE_PCR_FILE));
+    let data = data.context(format!("ConfigMap {name} had no {IMAGE_PCR_FILE}"))?;
+    Ok((image_name.clone(), serde_json::from_str(data)?))
+}
+
+/// PCRs of all images with a per-image ConfigMap
+pub async fn list_image_pcrs(config_maps: &Api<ConfigMap>) -> Result<ImagePcrs> {
+    let params = ListParams::default().labels(IMAGE_PCR_LABEL);
+    let list = config_maps.list(&params).await?;
+    let image_pcrs = list.items.iter().map(parse_image_pcr_map);
+    Ok(ImagePcrs(image_pcrs.collect::<Result<_>>()?))
+}
+
+/// PCRs of an ApprovedImage, if any were stored
+pub async fn get_image_pcr(client: Client, image_name: &str) -> Result<Option<ImagePcr>> {
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client);
+    let config_map = config_maps.get_opt(&image_pcr_map_name(image_name)).await?;
+    let image_pcr = config_map.as_ref().map(parse_image_pcr_map).transpose()?;
+    Ok(image_pcr.map(|(_, image_pcr)| image_pcr))
+}
+
+/// Store the PCRs of an ApprovedImage, replacing any stored earlier. A replacement that raced
+/// with another writer is retried at the latest resourceVersion.
+pub async fn store_image_pcr(
+    client: Client,
+    image_name: &str,
+    image_pcr: &ImagePcr,
+    owner_reference: OwnerReference,
+) -> Result<()> {
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client);
+    let mut config_map = image_pcr_map(image_name, image_pcr, owner_reference)?;
+    let name = image_pcr_map_name(image_name);
+    match config_maps.create(&Default::default(), &config_map).await {
+        Err(kube::Error::Api(ae)) if ae.code == 409 => {}
+        result => return result.map(|_| ()).map_err(Into::into),
+    }
+    retry_on_conflict(async || {
+        let existing = config_maps.get(&name).await?;
+        config_map.metadata.resource_version = existing.metadata.resource_version;
+        config_maps
+            .replace(&name, &Default::default(), &config_map)
+            .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Store the PCRs of one architecture of an ApprovedImage and their origin. The PCRs of its other
+/// architectures are kept if the stored PCRs are of the same image reference, otherwise
+/// `image_pcr` replaces them. Returns the PCRs as stored.
+pub async fn store_architecture_pcrs(
+    client: Client,
+    image_name: &str,
+    architecture: &str,
+    pcrs: ArchitecturePcrs,
+    origin: PcrOrigin,
+    image_pcr: ImagePcr,
+    owner_reference: OwnerReference,
+) -> Result<ImagePcr> {
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client);
+    let name = image_pcr_map_name(image_name);
+    retry_on_conflict(async || {
+        let existing = config_maps.get_opt(&name).await?;
+        let stored = existing.as_ref().map(parse_image_pcr_map).transpose()?;
+        let mut merged = match stored {
+            Some((_, stored)) if stored.reference == image_pcr.reference => stored,
+            _ => image_pcr.clone(),
+        };
+        let architecture = architecture.to_string();
+        merged
+            .architectures
+            .insert(architecture.clone(), pcrs.pcrs.clone());
+        // Replaces the profiles of an earlier computation, e.g. before a profile was removed
+        merged.profile_pcrs.remove(&architecture);
+        if !pcrs.profile_pcrs.is_empty() {
+            let profile_pcrs = pcrs.profile_pcrs.clone();
+            merged
+                .profile_pcrs
+                .insert(architecture.clone(), profile_pcrs);
+        }
+        merged.origins.insert(architecture, origin.clone());
+        merged.kernel_cmdline_pcrs = image_pcr.kernel_cmdline_pcrs.clone();
+        merged.kernel_command_lines = image_pcr.kernel_command_lines.clone();
+        merged.profiles = image_pcr.profiles.clone();
+        let mut config_map = image_pcr_map(image_name, &merged, owner_reference.clone())?;
+        let params = Default::default();
+        match existing {
+            Some(existing) => {
+                config_map.metadata.resource_version = existing.metadata.resource_version;
+                config_maps.replace(&name, &params, &config_map).await?;
+            }
+            // Another writer creating first is a conflict and retried as well
+            None => _ = config_maps.create(&params, &config_map).await?,
+        }
+        Ok(merged)
+    })
+    .await
+}
+
+/// Remove the PCRs of an ApprovedImage. Returns whether there were any.
+pub async fn remove_image_pcr(client: Client, image_name: &str) -> Result<bool> {
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client);
+    let name = image_pcr_map_name(image_name);
+    match config_maps.delete(&name, &DeleteParams::default()).await {
+        Ok(_) => Ok(true),
+        Err(kube::Error::Api(ae)) if ae.code == 404 => Ok(false),
+        Err(e) => Err(anyhow!("Failed to remove PCRs of image {image_name}: {e}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{Method, Request, StatusCode};
+    use kube::api::ObjectList;
+    use kube::client::Body;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    use trusted_cluster_operator_test_utils::mock_client::*;
+
+    fn dummy_image_pcr(reference: &str) -> ImagePcr {
+        ImagePcr {
+            first_seen: Timestamp::now(),
+            pcrs: Vec::new(),
+            reference: reference.to_string(),
+            not_after: None,
+            architectures: BTreeMap::new(),
+            origins: BTreeMap::new(),
+            profile_pcrs: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            kernel_cmdline_pcrs: Vec::new(),
+            kernel_command_lines: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_kernel_cmdline_pcr() {
+        let pcr = kernel_cmdline_pcr("root=UUID=1234 rw console=ttyS0");
+        assert_eq!(pcr.id, 12);
+        let expected = "62e4a47cc88601a68b73700b2b4ef6fc85dc2576324b49b0d525f158c36060a1";
+        assert_eq!(hex::encode(&pcr.value), expected);
+        assert!(pcr.events.is_empty());
+    }
+
+    #[test]
+    fn test_image_pcr_map_name_length() {
+        let name = image_pcr_map_name(&"a".repeat(253));
+        assert_eq!(name.len(), 253);
+        assert!(name.starts_with("image-pcrs-a"));
+        // Names that only differ beyond the length limit are kept apart
+        let other = image_pcr_map_name(&format!("{}b", "a".repeat(253)));
+        assert_eq!(other.len(), 253);
+        assert_ne!(name, other);
+        assert_eq!(image_pcr_map_name("short"), "image-pcrs-short");
+    }
+
+    #[tokio::test]
+    async fn test_list_image_pcrs() {
+        let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
+            (0, &Method::GET) => {
+                let owner = OwnerReference::default();
+                let items = ["first", "second"]
+                    .map(|name| image_pcr_map(name, &dummy_image_pcr(name), owner.clone()))
+                    .map(Result::unwrap);
+                let list = ObjectList {
+                    items: items.to_vec(),
+                    types: Default::default(),
+                    metadata: Default::default(),
+                };
+                Ok(serde_json::to_string(&list).unwrap())
+            }
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(1, clos, |client| {
+            let config_maps = Api::default_namespaced(client);
+            let image_pcrs = list_image_pcrs(&config_maps).await.unwrap();
+            assert_eq!(image_pcrs.0["first"].reference, "first");
+            assert_eq!(image_pcrs.0["second"].reference, "second");
+        });
+    }
+
+    #[tokio::test]
+    async fn test_store_image_pcr_concurrent_writers() {
+        // API server that only accepts replacements at the current resourceVersion
+        let image_pcr = dummy_image_pcr("initial");
+        let initial = image_pcr_map("test", &image_pcr, Default::default()).unwrap();
+        let stored = Arc::new(Mutex::new((1, initial)));
+        let conflicted = Arc::new(Mutex::new(None));
+        let gets = Arc::new(AtomicU32::new(0));
+        let (server, server_conflicted) = (stored.clone(), conflicted.clone());
+        let clos = move |req: Request<Body>, ctr| {
+            let (stored, conflicted) = (server.clone(), server_conflicted.clone());
+            let gets = gets.clone();
+            async move {
+                match *req.method() {
+                    Method::POST => Err(StatusCode::CONFLICT),
+                    Method::GET => {
+                        // Both writers read before either writes
+                        gets.fetch_add(1, Ordering::AcqRel);
+                        while gets.load(Ordering::Acquire) < 2 {
+                            tokio::task::yield_now().await;
+                        }
+                        let mut stored = stored.lock().unwrap();
+                        let (version, config_map) = &mut *stored;
+                        config_map.metadata.resource_version = Some(version.to_string());
+                        Ok(serde_json::to_string(config_map).unwrap())
+                    }
+                    Method::PUT => {
+                        let body = get_body_string(req).await;
+                        let config_map: ConfigMap = serde_json::from_str(&body).unwrap();
+                        let mut stored = stored.lock().unwrap();
+                        let version = Some(stored.0.to_string());
+                        if config_map.metadata.resource_version != version {
+                            let (_, image_pcr) = parse_image_pcr_map(&config_map).unwrap();
+                            *conflicted.lock().unwrap() = Some(image_pcr.reference);
+                            return Err(StatusCode::CONFLICT);
+                        }
+                        *stored = (stored.0 + 1, config_map);
+                        Ok(body)
+                    }
+                    _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+                }
+            }
+        };
+        // Both create, read and replace, then the losing writer reads and replaces again
+        count_check!(8, clos, |client| {
+            let (first, second) = (dummy_image_pcr("first"), dummy_image_pcr("second"));
+            let (first, second) = tokio::join!(
+                store_image_pcr(client.clone(), "test", &first, Default::default()),
+                store_image_pcr(client, "test", &second, Default::default()),
+            );
+            assert!(first.is_ok());
+            assert!(second.is_ok());
+        });
+        let stored = stored.lock().unwrap();
+        let (version, config_map) = &*stored;
+        assert_eq!(*version, 3);
+        let (_, image_pcr) = parse_image_pcr_map(config_map).unwrap();
+        // The writer that lost the race replaced the winner's PCRs after rereading
+        let conflicted = conflicted.lock().unwrap().clone();
+        assert!(matches!(conflicted.as_deref(), Some("first" | "second")));
+        assert_eq!(Some(image_pcr.reference), conflicted);
+    }
+
+    #[tokio::test]
+    async fn test_store_architecture_pcrs_concurrent_writers() {
+        // API server that only accepts replacements at the current resourceVersion
+        let image_pcr = dummy_image_pcr("multi-arch");
+        let initial = image_pcr_map("test", &image_pcr, Default::default()).unwrap();
+        let stored = Arc::new(Mutex::new((1, initial)));
+        let gets = Arc::new(AtomicU32::new(0));
+        let server = stored.clone();
+        let clos = move |req: Request<Body>, ctr| {
+            let (stored, gets) = (server.clone(), gets.clone());
+            async move {
+                match *req.method() {
+                    Method::GET => {
+                        // Both writers read before either writes
+                        gets.fetch_add(1, Ordering::AcqRel);
+                        while gets.load(Ordering::Acquire) < 2 {
+                            tokio::task::yield_now().await;
+                        }
+                        let mut stored = stored.lock().unwrap();
+                        let (version, config_map) = &mut *stored;
+                        config_map.metadata.resource_version = Some(version.to_string());
+                        Ok(serde_json::to_string(config_map).unwrap())
+                    }
+                    Method::PUT => {
+                        let body = get_body_string(req).await;
+                        let config_map: ConfigMap = serde_json::from_str(&body).unwrap();
+                        let mut stored = stored.lock().unwrap();
+                        if config_map.metadata.resource_version != Some(stored.0.to_string()) {
+                            return Err(StatusCode::CONFLICT);
+                        }
+                        *stored = (stored.0 + 1, config_map);
+                        Ok(body)
+                    }
+                    _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+                }
+            }
+        };
+        // Both read and replace, then the losing writer merges into the winner's PCRs
+        count_check!(6, clos, |client| {
+            let store = |architecture| {
+                let pcrs = ArchitecturePcrs {
+                    pcrs: Vec::new(),
+                    profile_pcrs: BTreeMap::new(),
+                };
+                let image_pcr = dummy_image_pcr("multi-arch");
+                let origin = PcrOrigin::label();
+                let client = client.clone();
+                store_architecture_pcrs(
+                    client,
+                    "test",
+                    architecture,
+                    pcrs,
+                    origin,
+                    image_pcr,
+                    Default::default(),
+                )
+            };
+            let (amd64, arm64) = tokio::join!(store("amd64"), store("arm64"));
+            assert!(amd64.is_ok());
+            assert!(arm64.is_ok());
+        });
+        let stored = stored.lock().unwrap();
+        let (version, config_map) = &*stored;
+        assert_eq!(*version, 3);
+        let (_, image_pcr) = parse_image_pcr_map(config_map).unwrap();
+        let architectures: Vec<_> = image_pcr.architectures.keys().collect();
+        assert_eq!(architectures, vec!["amd64", "arm64"]);
+    }
+
+    #[tokio::test]
+    async fn test_store_architecture_pcrs() {
+        let mut image_pcr = dummy_image_pcr("multi-arch");
+        image_pcr.architectures = BTreeMap::from([
+            ("amd64".to_string(), Vec::new()),
+            ("arm64".to_string(), Vec::new()),
+        ]);
+        let stored = image_pcr_map("test", &image_pcr, Default::default()).unwrap();
+        let stored = serde_json::to_string(&stored).unwrap();
+        let clos = move |req: Request<Body>, ctr| {
+            let stored = stored.clone();
+            async move {
+                match (ctr, req.method()) {
+                    (0, &Method::GET) => Ok(stored),
+                    (1, &Method::PUT) => Ok(get_body_string(req).await),
+                    _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+                }
+            }
+        };
+        count_check!(2, clos, |client| {
+            let pcr = |id| Pcr {
+                id,
+                value: vec![id as u8],
+                events: Vec::new(),
+            };
+            let pcrs = ArchitecturePcrs {
+                pcrs: vec![pcr(4)],
+                profile_pcrs: BTreeMap::from([("platform".to_string(), vec![pcr(7)])]),
+            };
+            let image_pcr = dummy_image_pcr("multi-arch");
+            let owner = OwnerReference::default();
+            let origin = PcrOrigin::label();
+            let stored =
+                store_architecture_pcrs(client, "test", "arm64", pcrs, origin, image_pcr, owner);
+            let stored = stored.await.unwrap();
+            // amd64 is kept
+            assert_eq!(stored.pending_architectures(), vec!["amd64"]);
+            assert_eq!(stored.architectures["arm64"].len(), 1);
+            assert_eq!(stored.profile_pcrs["arm64"]["platform"][0].id, 7);
+            assert!(!stored.origins["arm64"].computed);
+        });
+    }
+
+    #[test]
+    fn test_platform_pcrs() {
+        let pcr = |id| Pcr {
+            id,
+            value: vec![id as u8],
+            events: Vec::new(),
+        };
+        let mut image_pcr = dummy_image_pcr("multi-arch");
+        image_pcr.architectures = BTreeMap::from([
+            ("amd64".to_string(), vec![pcr(4)]),
+            ("arm64".to_string(), vec![pcr(4)]),
+        ]);
+        // PCR14 of the second profile replaces that of the architecture
+        let mut pcr14 = pcr(14);
+        pcr14.value = vec![0];
+        image_pcr
+            .architectures
+            .get_mut("amd64")
+            .unwrap()
+            .push(pcr(14));
+        let platforms = BTreeMap::from([
+            ("first".to_string(), vec![pcr(7)]),
+            ("second".to_string(), vec![pcr(7), pcr14]),
+        ]);
+        image_pcr.profile_pcrs = BTreeMap::from([("amd64".to_string(), platforms)]);
+        let platform_pcrs = image_pcr.platform_pcrs();
+        let summary: Vec<_> = platform_pcrs
+            .iter()
+            .map(|(arch, platform, pcrs)| (*arch, *platform, pcrs.len()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Some("amd64"), Some("first"), 3),
+                (Some("amd64"), Some("second"), 3),
+                (Some("arm64"), None, 1),
+            ]
+        );
+        assert_eq!(platform_pcrs[0].2[2].value, vec![14]);
+        assert_eq!(platform_pcrs[1].2[2].value, vec![0]);
+    }
+
+    #[tokio::test]
+    async fn test_remove_image_pcr_absent() {
+        let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
+            (0, &Method::DELETE) => Err(StatusCode::NOT_FOUND),
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(1, clos, |client| {
+            assert!(!remove_image_pcr(client, "test").await.unwrap());
+        });
+    }
 }
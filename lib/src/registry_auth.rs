@@ -0,0 +1,169 @@
+// SPDX-FileCopyrightText: Jakob Naucke <jnaucke@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+// Registry credentials of ApprovedImages from the image pull secrets that they reference, in the
+// kubernetes.io/dockerconfigjson format. Shared by the operator and compute-pcrs, which may pull
+// images itself.
+
+use anyhow::{Context, Result};
+use base64::{Engine as _, engine::general_purpose};
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client};
+use log::warn;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use crate::ApprovedImage;
+
+pub const DOCKER_CONFIG_KEY: &str = ".dockerconfigjson";
+const DOCKER_HUB_REGISTRY: &str = "docker.io";
+const DOCKER_HUB_LEGACY_REGISTRY: &str = "index.docker.io";
+
+#[derive(Deserialize)]
+struct DockerConfig {
+    #[serde(default)]
+    auths: BTreeMap<String, DockerAuth>,
+}
+
+#[derive(Deserialize)]
+struct DockerAuth {
+    username: Option<String>,
+    password: Option<String>,
+    /// base64 of username:password
+    auth: Option<String>,
+}
+
+impl DockerAuth {
+    fn credentials(&self) -> Result<(String, String)> {
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            return Ok((username.clone(), password.clone()));
+        }
+        let auth = self
+            .auth
+            .as_ref()
+            .context("Registry auth had no credentials")?;
+        let auth = String::from_utf8(general_purpose::STANDARD.decode(auth)?)?;
+        let (username, password) = auth.split_once(':').context("Registry auth had no colon")?;
+        Ok((username.to_string(), password.to_string()))
+    }
+}
+
+/// Registry and optional repository path that a dockerconfigjson key applies to, e.g.
+/// `https://index.docker.io/v1/` to `docker.io`
+fn normalize_auth_key(key: &str) -> String {
+    let key = key.split_once("://").map_or(key, |(_, rest)| rest);
+    let key = key.trim_end_matches('/');
+    let key = key
+        .strip_suffix("/v1")
+        .or(key.strip_suffix("/v2"))
+        .unwrap_or(key);
+    match key.split_once('/') {
+        Some((DOCKER_HUB_LEGACY_REGISTRY, path)) => format!("{DOCKER_HUB_REGISTRY}/{path}"),
+        _ if key == DOCKER_HUB_LEGACY_REGISTRY => DOCKER_HUB_REGISTRY.to_string(),
+        _ => key.to_string(),
+    }
+}
+
+/// Credentials for a repository (`registry/repository`) from a dockerconfigjson. The most
+/// specific key wins, so a key for a repository takes precedence over one for its whole registry.
+fn docker_config_auth(docker_config: &[u8], repository: &str) -> Result<Option<(String, String)>> {
+    let config: DockerConfig = serde_json::from_slice(docker_config)?;
+    let applies = |key: &String| repository == key || repository.starts_with(&format!("{key}/"));
+    let auth = config
+        .auths
+        .iter()
+        .map(|(key, auth)| (normalize_auth_key(key), auth))
+        .filter(|(key, _)| applies(key))
+        .max_by_key(|(key, _)| key.len());
+    auth.map(|(_, auth)| auth.credentials()).transpose()
+}
+
+pub fn image_pull_secret_names(image: &ApprovedImage) -> Vec<String> {
+    let secrets = image.spec.image_pull_secrets.iter().flatten();
+    secrets.filter_map(|s| s.name.clone()).collect()
+}
+
+/// Username and password for a repository (`registry/repository`) from the image pull secrets of
+/// an ApprovedImage, None if none apply. Secrets that cannot be read are skipped, as a pull may
+/// still succeed without them.
+pub async fn image_pull_credentials(
+    client: Client,
+    image: &ApprovedImage,
+    repository: &str,
+) -> Result<Option<(String, String)>> {
+    let secrets: Api<Secret> = Api::default_namespaced(client);
+    for name in image_pull_secret_names(image) {
+        let Some(secret) = secrets.get_opt(&name).await? else {
+            warn!("Image pull secret {name} does not exist");
+            continue;
+        };
+        let data = secret.data.as_ref().and_then(|d| d.get(DOCKER_CONFIG_KEY));
+        let Some(data) = data else {
+            warn!("Image pull secret {name} does not contain {DOCKER_CONFIG_KEY}");
+            continue;
+        };
+        match docker_config_auth(&data.0, repository) {
+            Ok(Some(credentials)) => return Ok(Some(credentials)),
+            Ok(None) => {}
+            Err(e) => warn!("Image pull secret {name} could not be parsed: {e}"),
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPOSITORY: &str = "quay.io/org/image";
+
+    fn docker_config(auths: &[(&str, &str)]) -> Vec<u8> {
+        let auths: BTreeMap<_, _> = auths
+            .iter()
+            .map(|(key, creds)| {
+                let auth = general_purpose::STANDARD.encode(creds);
+                (*key, serde_json::json!({"auth": auth}))
+            })
+            .collect();
+        serde_json::to_vec(&serde_json::json!({"auths": auths})).unwrap()
+    }
+
+    #[test]
+    fn test_normalize_auth_key() {
+        assert_eq!(
+            normalize_auth_key("https://index.docker.io/v1/"),
+            "docker.io"
+        );
+        assert_eq!(normalize_auth_key("quay.io"), "quay.io");
+        assert_eq!(normalize_auth_key("https://quay.io/org/"), "quay.io/org");
+        assert_eq!(
+            normalize_auth_key("index.docker.io/library"),
+            "docker.io/library"
+        );
+    }
+
+    #[test]
+    fn test_docker_config_auth_most_specific() {
+        let config = docker_config(&[
+            ("quay.io", "registry:pass"),
+            ("quay.io/org", "org:pass"),
+            ("quay.io/other", "other:pass"),
+        ]);
+        let auth = docker_config_auth(&config, REPOSITORY).unwrap();
+        assert_eq!(auth, Some(("org".to_string(), "pass".to_string())));
+    }
+
+    #[test]
+    fn test_docker_config_auth_username_password() {
+        let config = r#"{"auths": {"quay.io": {"username": "user", "password": "a:b"}}}"#;
+        let auth = docker_config_auth(config.as_bytes(), REPOSITORY).unwrap();
+        assert_eq!(auth, Some(("user".to_string(), "a:b".to_string())));
+    }
+
+    #[test]
+    fn test_docker_config_auth_other_registry() {
+        let config = docker_config(&[("registry.example.com", "user:pass")]);
+        assert_eq!(docker_config_auth(&config, REPOSITORY).unwrap(), None);
+    }
+}
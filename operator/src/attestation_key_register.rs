@@ -31,7 +31,9 @@ use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
 use trusted_cluster_operator_lib::conditions::ATTESTATION_KEY_MACHINE_APPROVE;
 use trusted_cluster_operator_lib::endpoints::*;
-use trusted_cluster_operator_lib::{AttestationKey, AttestationKeyStatus, Machine, update_status};
+use trusted_cluster_operator_lib::{
+    AttestationKey, AttestationKeyStatus, Machine, get_opt_trusted_execution_cluster, update_status,
+};
 
 use crate::conditions::attestation_key_approved_condition;
 use crate::trustee;
@@ -323,11 +325,34 @@ async fn secret_reconcile(
 
     info!("Secret reconciliation for AttestationKey secret: {secret_name}");
 
+    // An external Trustee gets attestation keys through its admin API instead of volumes
+    let cluster = get_opt_trusted_execution_cluster(ctx.client.clone()).await?;
+    let external = cluster.filter(|c| c.spec.external_trustee.is_some());
+
     let secrets: Api<Secret> = Api::default_namespaced(ctx.client.clone());
     let ctx = ctx.clone();
     finalizer(&secrets, ATTESTATION_KEY_SECRET_FINALIZER, secret, |ev| async move {
-        match ev {
-            Event::Apply(_secret) => {
+        match (ev, &external) {
+            (Event::Apply(secret), Some(cluster)) => {
+                trustee::deliver_attestation_key(ctx.client.clone(), cluster, &secret)
+                    .await
+                    .map(|_| LONG_REQUEUE)
+                    .map_err(|e| {
+                        warn!("Error delivering attestation key on secret apply: {e}");
+                        finalizer::Error::<ControllerError>::ApplyFailed(e.into())
+                    })
+            }
+            (Event::Cleanup(secret), Some(cluster)) => {
+                let secret_name = secret.metadata.name.clone().unwrap_or_default();
+                trustee::withdraw_attestation_key(ctx.client.clone(), cluster, &secret_name)
+                    .await
+                    .map(|_| LONG_REQUEUE)
+                    .map_err(|e| {
+                        warn!("Error withdrawing attestation key during secret deletion: {e}");
+                        finalizer::Error::<ControllerError>::CleanupFailed(e.into())
+                    })
+            }
+            (Event::Apply(_secret), None) => {
                 // On creation/update, just update the trustee deployment volumes
                 trustee::update_attestation_keys(&ctx)
                     .await
@@ -337,7 +362,7 @@ async fn secret_reconcile(
                         finalizer::Error::<ControllerError>::ApplyFailed(e.into())
                     })
             }
-            Event::Cleanup(secret) => {
+            (Event::Cleanup(secret), None) => {
                 let secret_name = secret.metadata.name.clone().unwrap_or_default();
                 info!(
                     "AttestationKey secret {secret_name} is being deleted, updating trustee deployment volumes"
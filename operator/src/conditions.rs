@@ -3,7 +3,9 @@
 // SPDX-License-Identifier: MIT
 
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition;
-use trusted_cluster_operator_lib::{AttestationKeyStatus, TrustedExecutionClusterStatus};
+use trusted_cluster_operator_lib::{
+    ApprovedImageStreamStatus, AttestationKeyStatus, TrustedExecutionClusterStatus,
+};
 use trusted_cluster_operator_lib::{condition_status, conditions::*, transition_time};
 
 pub fn known_trustee_address_condition(
@@ -77,3 +79,51 @@ pub fn attestation_key_approved_condition(
         observed_generation: generation,
     }
 }
+
+pub fn pcr_combinations_condition(
+    within_limit: bool,
+    message: String,
+    generation: Option<i64>,
+    existing_status: &Option<TrustedExecutionClusterStatus>,
+) -> Condition {
+    let reason = match within_limit {
+        true => PCR_COMBINATIONS_WITHIN_LIMIT_REASON,
+        false => PCR_COMBINATIONS_LIMIT_EXCEEDED_REASON,
+    };
+    let type_ = PCR_COMBINATIONS_WITHIN_LIMIT_CONDITION;
+    let status = condition_status(within_limit);
+    Condition {
+        type_: type_.to_string(),
+        reason: reason.to_string(),
+        message,
+        last_transition_time: transition_time(existing_status, type_, &status),
+        status,
+        observed_generation: generation,
+    }
+}
+
+pub fn tag_resolved_condition(
+    reason: &str,
+    message: String,
+    generation: Option<i64>,
+    existing_status: &Option<ApprovedImageStreamStatus>,
+) -> Condition {
+    let status = condition_status(reason == TAG_RESOLVED_REASON);
+    let type_ = TAG_RESOLVED_CONDITION;
+    let message = match reason {
+        TAG_NOT_RESOLVED_REASON_SIGNATURE_MISSING => {
+            "The current digest had no valid signature by a key of the signature policy, \
+             no ApprovedImage was created for it"
+                .to_string()
+        }
+        _ => message,
+    };
+    Condition {
+        type_: type_.to_string(),
+        reason: reason.to_string(),
+        message,
+        last_transition_time: transition_time(existing_status, type_, &status),
+        status,
+        observed_generation: generation,
+    }
+}
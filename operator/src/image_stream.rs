@@ -0,0 +1,387 @@
+// SPDX-FileCopyrightText: Jakob Naucke <jnaucke@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+// Generation of ApprovedImages from ApprovedImageStreams. The tag of a stream is resolved
+// periodically, and every digest it points to becomes an ApprovedImage labeled with the stream.
+// Superseded images are deleted beyond the retain count or after the grace period. Deleting a
+// stream leaves its images approved.
+
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use k8s_openapi::jiff::{SignedDuration, Timestamp};
+use kube::api::{DeleteParams, ListParams, ObjectMeta};
+use kube::runtime::controller::{Action, Controller};
+use kube::{Api, Client, Resource};
+use log::{info, warn};
+use oci_client::{Reference, secrets::RegistryAuth};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use crate::conditions::tag_resolved_condition;
+use crate::{registry, signature};
+use operator::{ControllerError, controller_error_policy, controller_info};
+use operator::{create_or_info_if_exists, upsert_condition};
+use trusted_cluster_operator_lib::{conditions::*, *};
+
+/// Label of generated ApprovedImages with the name of their stream
+const IMAGE_STREAM_LABEL: &str = "trusted-execution-clusters.io/approved-image-stream";
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(300);
+const DEFAULT_RETAIN_COUNT: usize = 3;
+
+/// Name of the ApprovedImage for a digest of a stream, within the length limit of names
+fn generated_image_name(stream_name: &str, digest: &str) -> String {
+    let digest = digest.trim_start_matches("sha256:");
+    let digest: String = digest.chars().take(12).collect();
+    let prefix: String = stream_name.chars().take(253 - 13).collect();
+    format!("{}-{digest}", prefix.trim_end_matches(['-', '.']))
+}
+
+/// ApprovedImage of a stream for an image reference. It is only named for a reference by digest.
+fn generated_image(stream: &ApprovedImageStream, image_ref: &Reference) -> Result<ApprovedImage> {
+    let err = "ApprovedImageStream had no name";
+    let stream_name = stream.metadata.name.as_ref().context(err)?;
+    let name = image_ref
+        .digest()
+        .map(|d| generated_image_name(stream_name, d));
+    let secrets = stream.spec.image_pull_secrets.iter().flatten();
+    let secrets = secrets.map(|s| ApprovedImageImagePullSecrets {
+        name: s.name.clone(),
+    });
+    Ok(ApprovedImage {
+        metadata: ObjectMeta {
+            name,
+            labels: Some(BTreeMap::from([(
+                IMAGE_STREAM_LABEL.to_string(),
+                stream_name.clone(),
+            )])),
+            ..Default::default()
+        },
+        spec: ApprovedImageSpec {
+            image: image_ref.whole(),
+            not_before: None,
+            not_after: None,
+            image_pull_secrets: Some(secrets.collect::<Vec<_>>()).filter(|s| !s.is_empty()),
+            platform_profiles: stream.spec.platform_profiles.clone(),
+            kernel_command_lines: stream.spec.kernel_command_lines.clone(),
+        },
+        status: None,
+    })
+}
+
+async fn resolve_digest(
+    oci_client: &oci_client::Client,
+    image_ref: &Reference,
+    auth: &RegistryAuth,
+) -> Result<String> {
+    Ok(oci_client.fetch_manifest_digest(image_ref, auth).await?)
+}
+
+/// Resolve the tag of a stream and create the ApprovedImage for its digest unless it exists.
+/// Returns the reason of the TagResolved condition and, if an ApprovedImage exists for the digest,
+/// the digest and the name of the image.
+async fn generate_latest_image(
+    client: Client,
+    stream: &ApprovedImageStream,
+    cluster_spec: Option<&TrustedExecutionClusterSpec>,
+) -> Result<(&'static str, Option<(String, String)>)> {
+    let tag_ref: Reference = stream.spec.image.parse()?;
+    // Only carries the pull secrets to resolve the tag with
+    let tag_image = generated_image(stream, &tag_ref)?;
+    let config = cluster_spec.and_then(|s| s.registry.as_ref());
+    let read = registry::read_image(client.clone(), &tag_image, &tag_ref, config, resolve_digest);
+    let digest = read.await?;
+    let registry = tag_ref.registry().to_string();
+    let repository = tag_ref.repository().to_string();
+    let digest_ref = Reference::with_digest(registry, repository, digest.clone());
+    let image = generated_image(stream, &digest_ref)?;
+    let image_name = image.metadata.name.clone().context("Image had no digest")?;
+    let latest = Some((digest, image_name.clone()));
+
+    let images: Api<ApprovedImage> = Api::default_namespaced(client.clone());
+    if images.get_opt(&image_name).await?.is_some() {
+        return Ok((TAG_RESOLVED_REASON, latest));
+    }
+    if stream.spec.require_signature == Some(true) {
+        let spec = cluster_spec.filter(|s| s.signature_policy.is_some());
+        let err = "requireSignature is set, but the TrustedExecutionCluster has no signaturePolicy";
+        let spec = spec.context(err)?;
+        if !signature::verify_image(client.clone(), &image, &digest_ref, spec).await? {
+            return Ok((TAG_NOT_RESOLVED_REASON_SIGNATURE_MISSING, None));
+        }
+    }
+    create_or_info_if_exists!(client, ApprovedImage, image);
+    Ok((TAG_RESOLVED_REASON, latest))
+}
+
+fn creation_time(image: &ApprovedImage) -> Option<Timestamp> {
+    image.metadata.creation_timestamp.as_ref().map(|t| t.0)
+}
+
+/// Names of generated images to retire, and when the next image is due by grace period. Images
+/// are superseded by the next newer image, and the latest image is never retired.
+fn images_to_retire(
+    images: &[ApprovedImage],
+    latest_image: &str,
+    retain_count: usize,
+    grace_period: Option<SignedDuration>,
+    now: Timestamp,
+) -> (Vec<String>, Option<Timestamp>) {
+    let mut images: Vec<_> = images.iter().collect();
+    images.sort_by_key(|image| std::cmp::Reverse(creation_time(image)));
+    let mut retire = Vec::new();
+    let mut next_due: Option<Timestamp> = None;
+    for (index, image) in images.iter().enumerate() {
+        let Some(name) = image.metadata.name.as_ref().filter(|n| *n != latest_image) else {
+            continue;
+        };
+        if index >= retain_count {
+            retire.push(name.clone());
+            continue;
+        }
+        let newer = index.checked_sub(1).and_then(|i| creation_time(images[i]));
+        let Some((superseded, grace_period)) = newer.zip(grace_period) else {
+            continue;
+        };
+        let due = superseded + grace_period;
+        if due <= now {
+            retire.push(name.clone());
+        } else {
+            next_due = Some(next_due.map_or(due, |next| next.min(due)));
+        }
+    }
+    (retire, next_due)
+}
+
+/// Delete superseded images of a stream. Returns when the next image is due by grace period.
+async fn retire_images(
+    client: Client,
+    stream: &ApprovedImageStream,
+    latest_image: &str,
+) -> Result<Option<Timestamp>> {
+    let stream_name = stream.metadata.name.as_deref().unwrap_or_default();
+    let images: Api<ApprovedImage> = Api::default_namespaced(client);
+    let params = ListParams::default().labels(&format!("{IMAGE_STREAM_LABEL}={stream_name}"));
+    let generated = images.list(&params).await?;
+    let retain_count = stream.spec.retain_count.map(|c| c.max(1) as usize);
+    let retain_count = retain_count.unwrap_or(DEFAULT_RETAIN_COUNT);
+    let grace_period = stream
+        .spec
+        .grace_period_seconds
+        .map(SignedDuration::from_secs);
+    let now = Timestamp::now();
+    let (retire, next_due) = images_to_retire(
+        &generated.items,
+        latest_image,
+        retain_count,
+        grace_period,
+        now,
+    );
+    for name in retire {
+        info!("Retiring ApprovedImage {name} of ApprovedImageStream {stream_name}");
+        match images.delete(&name, &DeleteParams::default()).await {
+            Ok(_) => {}
+            Err(kube::Error::Api(ae)) if ae.code == 404 => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(next_due)
+}
+
+async fn reconcile_stream(client: Client, stream: &ApprovedImageStream) -> Result<Action> {
+    let name = stream
+        .metadata
+        .name
+        .as_ref()
+        .context("ApprovedImageStream had no name")?;
+    let cluster = get_opt_trusted_execution_cluster(client.clone()).await?;
+    let cluster_spec = cluster.as_ref().map(|c| &c.spec);
+    let image = &stream.spec.image;
+    let (reason, message, latest) =
+        match generate_latest_image(client.clone(), stream, cluster_spec).await {
+            Ok((reason, latest)) => (reason, String::new(), latest),
+            Err(e) => {
+                warn!("Resolving {image} of ApprovedImageStream {name} failed: {e}");
+                (TAG_NOT_RESOLVED_REASON_FAILED, e.to_string(), None)
+            }
+        };
+    let mut next_due = None;
+    if let Some((_, latest_image)) = &latest {
+        next_due = retire_images(client.clone(), stream, latest_image).await?;
+    }
+
+    let status = &stream.status;
+    let generation = stream.metadata.generation;
+    let condition = tag_resolved_condition(reason, message, generation, status);
+    let mut conditions = status.as_ref().and_then(|s| s.conditions.clone());
+    let condition_changed = upsert_condition(&mut conditions, condition);
+    // The latest image remains as it was if the tag could not be resolved
+    let previous = status
+        .as_ref()
+        .map(|s| (s.latest_digest.clone(), s.latest_image.clone()));
+    let previous = previous.unwrap_or_default();
+    let (latest_digest, latest_image) = match latest {
+        Some((digest, image)) => (Some(digest), Some(image)),
+        None => previous.clone(),
+    };
+    if condition_changed || (latest_digest.clone(), latest_image.clone()) != previous {
+        let streams: Api<ApprovedImageStream> = Api::default_namespaced(client);
+        let status = ApprovedImageStreamStatus {
+            conditions,
+            latest_digest,
+            latest_image,
+        };
+        update_status!(streams, name, status)?;
+    }
+
+    let poll_interval = stream.spec.poll_interval_seconds.map(|s| s.max(0) as u64);
+    let poll_interval = poll_interval.map_or(DEFAULT_POLL_INTERVAL, Duration::from_secs);
+    let now = Timestamp::now().as_second();
+    let until_due = next_due.map(|due| (due.as_second() - now).unsigned_abs() + 1);
+    let until_due = until_due.map(Duration::from_secs);
+    Ok(Action::requeue(
+        until_due.map_or(poll_interval, |d| d.min(poll_interval)),
+    ))
+}
+
+async fn stream_reconcile(
+    stream: Arc<ApprovedImageStream>,
+    client: Arc<Client>,
+) -> Result<Action, ControllerError> {
+    let kube_client = Arc::unwrap_or_clone(client);
+    Ok(reconcile_stream(kube_client, &stream).await?)
+}
+
+pub async fn launch_image_stream_controller(client: Client) {
+    let streams: Api<ApprovedImageStream> = Api::default_namespaced(client.clone());
+    tokio::spawn(
+        Controller::new(streams, Default::default())
+            .run(stream_reconcile, controller_error_policy, Arc::new(client))
+            .for_each(controller_info),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{Method, Request};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use kube::client::Body;
+    use trusted_cluster_operator_test_utils::mock_client::*;
+
+    const DIGEST: &str = "sha256:e71dad00aa0e3d70540e726a0c66407e3004d96e045ab6c253186e327a2419e5";
+
+    fn dummy_stream() -> ApprovedImageStream {
+        ApprovedImageStream {
+            metadata: ObjectMeta {
+                name: Some("coreos".to_string()),
+                ..Default::default()
+            },
+            spec: ApprovedImageStreamSpec {
+                image: "quay.io/org/image:stable".to_string(),
+                poll_interval_seconds: None,
+                retain_count: None,
+                grace_period_seconds: None,
+                require_signature: None,
+                image_pull_secrets: Some(vec![ApprovedImageStreamImagePullSecrets {
+                    name: Some("pull-secret".to_string()),
+                }]),
+                platform_profiles: None,
+                kernel_command_lines: None,
+            },
+            status: None,
+        }
+    }
+
+    /// Generated images named image-0, image-1 and so on, created an hour apart, newest first
+    fn generated_images(count: i64, now: Timestamp) -> Vec<ApprovedImage> {
+        let image_ref = format!("quay.io/org/image@{DIGEST}").parse().unwrap();
+        let image = generated_image(&dummy_stream(), &image_ref).unwrap();
+        let images = (0..count).map(|i| {
+            let mut image = image.clone();
+            image.metadata.name = Some(format!("image-{i}"));
+            let created = now - SignedDuration::from_hours(i);
+            image.metadata.creation_timestamp = Some(Time(created));
+            image
+        });
+        images.collect()
+    }
+
+    #[test]
+    fn test_generated_image() {
+        let image_ref = format!("quay.io/org/image@{DIGEST}").parse().unwrap();
+        let image = generated_image(&dummy_stream(), &image_ref).unwrap();
+        assert_eq!(image.metadata.name.unwrap(), "coreos-e71dad00aa0e");
+        assert_eq!(image.spec.image, format!("quay.io/org/image@{DIGEST}"));
+        let labels = image.metadata.labels.unwrap();
+        assert_eq!(labels[IMAGE_STREAM_LABEL], "coreos");
+        let secrets = image.spec.image_pull_secrets.unwrap();
+        assert_eq!(secrets[0].name.as_deref(), Some("pull-secret"));
+    }
+
+    #[test]
+    fn test_generated_image_name_length() {
+        let name = generated_image_name(&"a".repeat(253), DIGEST);
+        assert_eq!(name.len(), 253);
+        assert!(name.ends_with("-e71dad00aa0e"));
+    }
+
+    #[test]
+    fn test_images_to_retire_count() {
+        let now = Timestamp::now();
+        let images = generated_images(5, now);
+        let (retire, next_due) = images_to_retire(&images, "image-0", 3, None, now);
+        assert_eq!(retire, vec!["image-3", "image-4"]);
+        assert_eq!(next_due, None);
+    }
+
+    #[test]
+    fn test_images_to_retire_latest_kept() {
+        let now = Timestamp::now();
+        let images = generated_images(3, now);
+        // The tag moved back to an older digest
+        let (retire, _) = images_to_retire(&images, "image-2", 1, None, now);
+        assert_eq!(retire, vec!["image-1"]);
+    }
+
+    #[test]
+    fn test_images_to_retire_grace_period() {
+        let now = Timestamp::now();
+        let images = generated_images(3, now);
+        let grace_period = Some(SignedDuration::from_mins(30));
+        let (retire, next_due) = images_to_retire(&images, "image-0", 3, grace_period, now);
+        // image-2 was superseded by image-1 an hour ago, image-1 by image-0 just now
+        assert_eq!(retire, vec!["image-2"]);
+        assert_eq!(next_due, Some(now + SignedDuration::from_mins(30)));
+    }
+
+    #[tokio::test]
+    async fn test_retire_images() {
+        let now = Timestamp::now();
+        let clos = move |req: Request<Body>, ctr| async move {
+            match (ctr, req.method()) {
+                (0, &Method::GET) => {
+                    let query = req.uri().query().unwrap_or_default();
+                    assert!(query.contains("coreos"));
+                    let list = kube::api::ObjectList {
+                        items: generated_images(2, now),
+                        types: Default::default(),
+                        metadata: Default::default(),
+                    };
+                    Ok(serde_json::to_string(&list).unwrap())
+                }
+                (1, &Method::DELETE) => {
+                    assert!(req.uri().path().ends_with("/image-1"));
+                    let image = generated_images(2, now).remove(1);
+                    Ok(serde_json::to_string(&image).unwrap())
+                }
+                _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+            }
+        };
+        count_check!(2, clos, |client| {
+            let mut stream = dummy_stream();
+            stream.spec.retain_count = Some(1);
+            let next_due = retire_images(client, &stream, "image-0").await.unwrap();
+            assert_eq!(next_due, None);
+        });
+    }
+}
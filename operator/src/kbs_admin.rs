@@ -0,0 +1,227 @@
+// SPDX-FileCopyrightText: Jakob Naucke <jnaucke@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+// Client for the Trustee admin API. Requests are authorized with a JWT
+// signed by the Ed25519 key stored in the admin Secret, matching the
+// "Simple" admin backend of KBS.
+
+use anyhow::{Context, Result, anyhow};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client};
+use openssl::pkey::{PKey, Private};
+use openssl::sign::Signer;
+use reqwest::{StatusCode, header::CONTENT_TYPE};
+use serde_json::json;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use trusted_cluster_operator_lib::endpoints::*;
+use trusted_cluster_operator_lib::{TrustedExecutionCluster, TrustedExecutionClusterSpec};
+
+pub const TRUSTEE_ADMIN_SECRET: &str = "trustee-admin-keys";
+pub const ADMIN_PRIVATE_KEY: &str = "private.key";
+pub const ADMIN_PUBLIC_KEY: &str = "public.pub";
+pub const ADMIN_PERSONA: &str = "trusted-cluster-operator";
+const ADMIN_TOKEN_LIFETIME_SECS: u64 = 300;
+
+/// Generate an Ed25519 keypair as (PKCS#8 private key, public key) PEM
+pub fn generate_admin_keypair() -> Result<(Vec<u8>, Vec<u8>)> {
+    let key = PKey::generate_ed25519()?;
+    Ok((key.private_key_to_pem_pkcs8()?, key.public_key_to_pem()?))
+}
+
+/// Whether Trustee is managed through its admin API, which is always the case for an external
+/// Trustee
+pub fn uses_admin_api(spec: &TrustedExecutionClusterSpec) -> bool {
+    spec.trustee_admin_api == Some(true) || spec.external_trustee.is_some()
+}
+
+async fn get_secret_key(secrets: &Api<Secret>, name: &str, key: &str) -> Result<Vec<u8>> {
+    let secret = secrets.get(name).await?;
+    let err = format!("Secret {name} does not contain {key}");
+    let data = secret.data.as_ref().and_then(|d| d.get(key)).context(err)?;
+    Ok(data.0.clone())
+}
+
+pub struct KbsAdminClient {
+    http: reqwest::Client,
+    base_url: String,
+    key: PKey<Private>,
+}
+
+impl KbsAdminClient {
+    /// Build a client for the Trustee of this cluster. For a Trustee deployed by the operator,
+    /// the admin key from the admin Secret and, if set, the CA of the Trustee Secret are used.
+    /// For an external Trustee, both come from the Secrets of its configuration.
+    pub async fn new(client: Client, cluster: &TrustedExecutionCluster) -> Result<Self> {
+        let namespace = client.default_namespace().to_string();
+        let secrets: Api<Secret> = Api::default_namespaced(client);
+        let (key_secret, ca_secret, address) = match &cluster.spec.external_trustee {
+            Some(external) => (
+                external.admin_key_secret.as_str(),
+                &external.ca_secret,
+                external.address.clone(),
+            ),
+            None => {
+                let port = cluster.spec.trustee_kbs_port.unwrap_or(TRUSTEE_PORT);
+                let address = format!("{TRUSTEE_SERVICE}.{namespace}.svc:{port}");
+                (TRUSTEE_ADMIN_SECRET, &cluster.spec.trustee_secret, address)
+            }
+        };
+        let pem = get_secret_key(&secrets, key_secret, ADMIN_PRIVATE_KEY).await?;
+        let key = PKey::private_key_from_pem(&pem)?;
+
+        let mut builder = reqwest::Client::builder();
+        let scheme = match ca_secret {
+            Some(name) => {
+                let ca = get_secret_key(&secrets, name, "ca.crt").await?;
+                builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&ca)?);
+                "https"
+            }
+            None => "http",
+        };
+        Ok(Self {
+            http: builder.build()?,
+            base_url: format!("{scheme}://{address}/kbs/v0"),
+            key,
+        })
+    }
+
+    fn token(&self) -> Result<String> {
+        let iat = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let header = json!({"alg": "EdDSA", "typ": "JWT"});
+        let claims = json!({"iat": iat, "exp": iat + ADMIN_TOKEN_LIFETIME_SECS});
+        let header = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?);
+        let claims = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?);
+        let signing_input = format!("{header}.{claims}");
+        // Ed25519 signs the message as a whole, without a separate digest
+        let mut signer = Signer::new_without_digest(&self.key)?;
+        let signature = signer.sign_oneshot_to_vec(signing_input.as_bytes())?;
+        Ok(format!(
+            "{signing_input}.{}",
+            URL_SAFE_NO_PAD.encode(signature)
+        ))
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let response = request.bearer_auth(self.token()?).send().await?;
+        let status = response.status();
+        if !status.is_success() && status != StatusCode::NOT_FOUND {
+            let url = response.url().clone();
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow!(
+                "Trustee admin request to {url} failed ({status}): {text}"
+            ));
+        }
+        Ok(response)
+    }
+
+    async fn post(&self, path: &str, content_type: &str, body: Vec<u8>) -> Result<()> {
+        let url = format!("{}/{path}", self.base_url);
+        let request = self.http.post(&url).header(CONTENT_TYPE, content_type);
+        let response = self.send(request.body(body)).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(anyhow!("Trustee admin endpoint {url} not found"));
+        }
+        Ok(())
+    }
+
+    pub async fn set_attestation_policy(&self, policy_id: &str, policy: &str) -> Result<()> {
+        let body = json!({
+            "type": "rego",
+            "policy_id": policy_id,
+            "policy": URL_SAFE_NO_PAD.encode(policy),
+        });
+        let body = serde_json::to_vec(&body)?;
+        self.post("attestation-policy", "application/json", body)
+            .await
+    }
+
+    pub async fn set_resource_policy(&self, policy: &str) -> Result<()> {
+        let body = json!({"policy": URL_SAFE_NO_PAD.encode(policy)});
+        let body = serde_json::to_vec(&body)?;
+        self.post("resource-policy", "application/json", body).await
+    }
+
+    /// Set a resource at `<repository>/<type>/<tag>`
+    pub async fn set_resource(&self, path: &str, data: &[u8]) -> Result<()> {
+        let path = format!("resource/{path}");
+        self.post(&path, "application/octet-stream", data.to_vec())
+            .await
+    }
+
+    /// Delete a resource at `<repository>/<type>/<tag>`. A resource that
+    /// does not exist (anymore) is not an error.
+    pub async fn delete_resource(&self, path: &str) -> Result<()> {
+        let url = format!("{}/resource/{path}", self.base_url);
+        self.send(self.http.delete(&url)).await.map(|_| ())
+    }
+
+    /// Register a reference value provider message, e.g. of type "sample"
+    pub async fn set_reference_values(&self, message: &serde_json::Value) -> Result<()> {
+        let body = serde_json::to_vec(message)?;
+        self.post("reference-value", "application/json", body).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{Method, Request};
+    use k8s_openapi::ByteString;
+    use openssl::sign::Verifier;
+    use std::collections::BTreeMap;
+    use trusted_cluster_operator_lib::TrustedExecutionClusterExternalTrustee;
+    use trusted_cluster_operator_test_utils::mock_client::*;
+
+    #[test]
+    fn test_admin_token_verifies() {
+        let (private_pem, public_pem) = generate_admin_keypair().unwrap();
+        let admin_client = KbsAdminClient {
+            http: reqwest::Client::new(),
+            base_url: String::new(),
+            key: PKey::private_key_from_pem(&private_pem).unwrap(),
+        };
+        let token = admin_client.token().unwrap();
+        let (signing_input, signature) = token.rsplit_once('.').unwrap();
+        let (header, _) = signing_input.split_once('.').unwrap();
+        let header: serde_json::Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header).unwrap()).unwrap();
+        assert_eq!(header["alg"], "EdDSA");
+
+        let public_key = PKey::public_key_from_pem(&public_pem).unwrap();
+        let mut verifier = Verifier::new_without_digest(&public_key).unwrap();
+        let signature = URL_SAFE_NO_PAD.decode(signature).unwrap();
+        let verified = verifier.verify_oneshot(&signature, signing_input.as_bytes());
+        assert!(verified.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_admin_client_external_trustee() {
+        let clos = async |req: Request<_>, ctr| match (ctr, req.method()) {
+            (0, &Method::GET) => {
+                assert!(req.uri().path().ends_with("/secrets/shared-kbs-admin"));
+                let (private_pem, _) = generate_admin_keypair().unwrap();
+                let data =
+                    BTreeMap::from([(ADMIN_PRIVATE_KEY.to_string(), ByteString(private_pem))]);
+                let secret = Secret {
+                    data: Some(data),
+                    ..Default::default()
+                };
+                Ok(serde_json::to_string(&secret).unwrap())
+            }
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(1, clos, |client| {
+            let mut cluster = dummy_cluster();
+            cluster.spec.external_trustee = Some(TrustedExecutionClusterExternalTrustee {
+                address: "kbs.example.com:8080".to_string(),
+                ca_secret: None,
+                admin_key_secret: "shared-kbs-admin".to_string(),
+            });
+            let admin_client = KbsAdminClient::new(client, &cluster).await.unwrap();
+            assert_eq!(admin_client.base_url, "http://kbs.example.com:8080/kbs/v0");
+        });
+    }
+}
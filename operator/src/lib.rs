@@ -23,7 +23,9 @@ use std::{sync::Arc, time::Duration};
 use tokio::time::timeout;
 
 // Re-export common functions from the lib
-pub use trusted_cluster_operator_lib::generate_owner_reference;
+pub use trusted_cluster_operator_lib::{generate_owner_reference, retry_on_conflict};
+
+pub mod kbs_admin;
 
 #[derive(Debug, thiserror::Error)]
 pub enum ControllerError {
@@ -10,7 +10,7 @@ use std::time::Duration;
 use anyhow::{Context, Result};
 use env_logger::Env;
 use futures_util::StreamExt;
-use k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, OwnerReference};
 use kube::runtime::controller::{Action, Controller};
 use kube::runtime::reflector::{self, Store};
 use kube::runtime::watcher;
@@ -18,15 +18,23 @@ use kube::{Api, Client};
 use log::{info, warn};
 
 use operator::{generate_owner_reference, upsert_condition};
-use trusted_cluster_operator_lib::{TrustedExecutionCluster, TrustedExecutionClusterStatus};
+use trusted_cluster_operator_lib::{
+    TrustedExecutionCluster, TrustedExecutionClusterSpec, TrustedExecutionClusterStatus,
+};
 use trusted_cluster_operator_lib::{conditions::*, images::*, update_status};
 
 mod attestation_key_register;
 mod conditions;
+mod image_stream;
 mod reference_values;
 mod register_server;
+mod registry;
+mod rv_history;
+mod rvps;
+mod signature;
 #[cfg(test)]
 mod test_utils;
+mod token_key;
 mod trustee;
 
 use crate::conditions::*;
@@ -44,6 +52,10 @@ const COMPONENT_VERSION: &str = match option_env!("COMPONENT_VERSION") {
 /// Default registry
 const TEC_REGISTRY: &str = "quay.io/trusted-execution-clusters";
 
+/// `operator diff-reference-values <from> <to>` prints the changes between two versions of the
+/// reference value history
+const DIFF_REFERENCE_VALUES_COMMAND: &str = "diff-reference-values";
+
 struct ClusterContext {
     client: Client,
     tec_store: Store<TrustedExecutionCluster>,
@@ -67,6 +79,13 @@ impl ClusterContext {
     }
 }
 
+/// Generation that the installation was last completed for
+fn installed_generation(status: &Option<TrustedExecutionClusterStatus>) -> Option<i64> {
+    let conditions = status.as_ref().and_then(|s| s.conditions.as_ref())?;
+    let chk = |c: &&Condition| c.type_ == INSTALLED_CONDITION && c.status == "True";
+    conditions.iter().find(chk)?.observed_generation
+}
+
 fn is_installed(status: Option<TrustedExecutionClusterStatus>) -> bool {
     let chk = |c: &Condition| c.type_ == INSTALLED_CONDITION && c.status == "True";
     status
@@ -80,7 +99,9 @@ async fn reconcile(
     ctx: Arc<ClusterContext>,
 ) -> Result<Action, ControllerError> {
     let generation = cluster.metadata.generation;
-    let known_address = cluster.spec.public_trustee_addr.is_some();
+    // Machines are pointed to an external Trustee at its configured address
+    let spec = &cluster.spec;
+    let known_address = spec.public_trustee_addr.is_some() || spec.external_trustee.is_some();
     let existing_status = &cluster.status;
     let address_condition =
         known_trustee_address_condition(known_address, generation, existing_status);
@@ -102,12 +123,62 @@ async fn reconcile(
             installed_condition(uninstalling_reason, generation, existing_status);
         let changed = upsert_condition(&mut conditions, uninstall_condition);
         if changed {
-            update_status!(clusters, name, TrustedExecutionClusterStatus { conditions })?;
+            update_status!(
+                clusters,
+                name,
+                TrustedExecutionClusterStatus {
+                    conditions,
+                    pcr_combination: None
+                }
+            )?;
         }
         return Ok(LONG_REQUEUE);
     }
 
+    if let Err(e) = trustee::validate_trustee_config(&cluster.spec) {
+        warn!("Invalid Trustee configuration: {e}");
+        let invalid_reason = NOT_INSTALLED_REASON_INVALID;
+        let mut invalid_condition =
+            installed_condition(invalid_reason, generation, existing_status);
+        invalid_condition.message = format!("Invalid Trustee configuration: {e}");
+        let changed = upsert_condition(&mut conditions, invalid_condition);
+        if changed {
+            update_status!(
+                clusters,
+                name,
+                TrustedExecutionClusterStatus {
+                    conditions,
+                    pcr_combination: None
+                }
+            )?;
+        }
+        // A fix changes the spec, which triggers reconciliation
+        return Ok(LONG_REQUEUE);
+    }
+
     if is_installed(cluster.status.clone()) {
+        if installed_generation(existing_status) == generation {
+            return Ok(LONG_REQUEUE);
+        }
+        // The spec changed after installation. An external Trustee has nothing to update, as its
+        // settings are immutable and it can be neither added nor removed after creation.
+        if cluster.spec.external_trustee.is_none() {
+            let owner_reference = generate_owner_reference(&cluster)?;
+            trustee::update_trustee_config(kube_client, owner_reference, &cluster.spec).await?;
+        }
+        let installed_condition =
+            installed_condition(INSTALLED_REASON, generation, existing_status);
+        let changed = upsert_condition(&mut conditions, installed_condition);
+        if changed {
+            update_status!(
+                clusters,
+                name,
+                TrustedExecutionClusterStatus {
+                    conditions,
+                    pcr_combination: None
+                }
+            )?;
+        }
         return Ok(LONG_REQUEUE);
     }
 
@@ -121,7 +192,14 @@ async fn reconcile(
             installed_condition(NOT_INSTALLED_REASON_NON_UNIQUE, generation, existing_status);
         let changed = upsert_condition(&mut conditions, non_unique_condition);
         if changed {
-            update_status!(clusters, name, TrustedExecutionClusterStatus { conditions })?;
+            update_status!(
+                clusters,
+                name,
+                TrustedExecutionClusterStatus {
+                    conditions,
+                    pcr_combination: None
+                }
+            )?;
         }
         return Ok(Action::requeue(Duration::from_secs(60)));
     }
@@ -133,6 +211,7 @@ async fn reconcile(
     if changed {
         let status = TrustedExecutionClusterStatus {
             conditions: conditions.clone(),
+            pcr_combination: None,
         };
         update_status!(clusters, name, status)?;
     }
@@ -147,7 +226,10 @@ async fn reconcile(
     let installed_condition = installed_condition(INSTALLED_REASON, generation, existing_status);
     let changed = upsert_condition(&mut conditions, installed_condition);
     if changed {
-        let status = TrustedExecutionClusterStatus { conditions };
+        let status = TrustedExecutionClusterStatus {
+            conditions,
+            pcr_combination: None,
+        };
         update_status!(clusters, name, status)?;
     }
     Ok(LONG_REQUEUE)
@@ -164,10 +246,36 @@ async fn install_trustee_configuration(
     client: Client,
     cluster: &TrustedExecutionCluster,
 ) -> Result<()> {
+    if let Some(external) = &cluster.spec.external_trustee {
+        trustee::configure_external_trustee(client, cluster)
+            .await
+            .context("Failed to configure the external Trustee")?;
+        info!("Configured the external Trustee at {}", external.address);
+        return Ok(());
+    }
+
     let owner_reference = generate_owner_reference(cluster)?;
 
-    let trustee_secret = &cluster.spec.trustee_secret;
-    trustee::generate_trustee_data(client.clone(), owner_reference.clone(), trustee_secret)
+    let spec = &cluster.spec;
+    if spec.trustee_admin_api == Some(true) {
+        trustee::generate_admin_secret(client.clone(), owner_reference.clone())
+            .await
+            .context("Failed to create the Trustee admin keys secret")?;
+        info!("Generated secret for the Trustee admin keys");
+    }
+
+    let rotation_days = spec.attestation_token_key_rotation_days;
+    token_key::generate_token_key_secret(client.clone(), owner_reference.clone(), rotation_days)
+        .await
+        .context("Failed to create the attestation token key secret")?;
+    info!("Generated secret for the attestation token key");
+
+    token_key::publish_token_key_jwks(client.clone(), owner_reference.clone())
+        .await
+        .context("Failed to publish the attestation token JWKS")?;
+    info!("Published the JWKS of the attestation token key");
+
+    trustee::generate_trustee_data(client.clone(), owner_reference.clone(), spec)
         .await
         .context("Failed to create the KBS configuration configmap")?;
     info!("Generated configmap for the KBS configuration");
@@ -183,13 +291,47 @@ async fn install_trustee_configuration(
         .context("Failed to create the KBS service")?;
     info!("Generated the KBS service");
 
+    if rvps::is_external_rvps(spec) {
+        install_rvps(client.clone(), owner_reference.clone(), spec).await?;
+    }
+
     let default = format!("{TEC_REGISTRY}/key-broker-service:{TRUSTEE_VERSION}");
     let trustee_image = env::var(RELATED_IMAGE_TRUSTEE).ok().unwrap_or(default);
-    trustee::generate_kbs_deployment(client, owner_reference, &trustee_image, trustee_secret)
+    let owner = owner_reference.clone();
+    trustee::generate_kbs_deployment(client.clone(), owner, &trustee_image, spec)
         .await
         .context("Failed to create the KBS deployment")?;
     info!("Generated the KBS deployment");
 
+    // Trustee may already exist from an earlier installation with different settings
+    trustee::update_trustee_config(client, owner_reference, spec)
+        .await
+        .context("Failed to update the Trustee configuration")?;
+
+    Ok(())
+}
+
+async fn install_rvps(
+    client: Client,
+    owner_reference: OwnerReference,
+    spec: &TrustedExecutionClusterSpec,
+) -> Result<()> {
+    rvps::generate_rvps_config(client.clone(), owner_reference.clone(), spec)
+        .await
+        .context("Failed to create the RVPS configuration configmap")?;
+    info!("Generated configmap for the RVPS configuration");
+
+    rvps::generate_rvps_service(client.clone(), owner_reference.clone())
+        .await
+        .context("Failed to create the RVPS service")?;
+    info!("Generated the RVPS service");
+
+    let default = format!("{TEC_REGISTRY}/reference-value-provider-service:{TRUSTEE_VERSION}");
+    let rvps_image = env::var(RELATED_IMAGE_RVPS).ok().unwrap_or(default);
+    rvps::generate_rvps_deployment(client, owner_reference, &rvps_image, spec)
+        .await
+        .context("Failed to create the RVPS deployment")?;
+    info!("Generated the RVPS deployment");
     Ok(())
 }
 
@@ -254,6 +396,17 @@ async fn main() -> Result<()> {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
     let kube_client = Client::try_default().await?;
+
+    // Print the changes between two versions of the reference value history instead of running
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let [command, from, to] = args.as_slice()
+        && command == DIFF_REFERENCE_VALUES_COMMAND
+    {
+        let diff = rv_history::diff_versions(kube_client, from.parse()?, to.parse()?).await?;
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+        return Ok(());
+    }
+
     info!("trusted execution clusters operator",);
 
     const CACHE_SYNC_TIMEOUT: Duration = Duration::from_secs(60);
@@ -283,9 +436,13 @@ async fn main() -> Result<()> {
     attestation_key_register::launch_ak_controller(ak_ctx.clone()).await;
     attestation_key_register::launch_machine_ak_controller(ak_ctx.clone()).await;
     attestation_key_register::launch_secret_ak_controller(ak_ctx).await;
-    reference_values::create_pcrs_config_map(kube_client.clone()).await?;
+    reference_values::migrate_image_pcrs(kube_client.clone()).await?;
     reference_values::launch_rv_image_controller(kube_client.clone()).await;
     reference_values::launch_rv_job_controller(kube_client.clone()).await;
+    reference_values::launch_rv_update_controller(kube_client.clone()).await;
+    image_stream::launch_image_stream_controller(kube_client.clone()).await;
+    token_key::launch_token_key_rotation(kube_client.clone()).await;
+    rvps::launch_rvps_controller(kube_client.clone()).await;
 
     Controller::new(cl, watcher::Config::default())
         .run(reconcile, controller_error_policy, ctx)
@@ -299,13 +456,14 @@ async fn main() -> Result<()> {
 mod tests {
     use http::{Method, Request, StatusCode};
     use k8s_openapi::api::apps::v1::Deployment;
-    use k8s_openapi::api::core::v1::{ConfigMap, Service};
+    use k8s_openapi::api::core::v1::{ConfigMap, Secret, Service};
     use k8s_openapi::{apimachinery::pkg::apis::meta::v1::Time, jiff::Timestamp};
     use kube::api::ObjectList;
     use kube::client::Body;
     use trusted_cluster_operator_lib::ApprovedImage;
 
     use super::*;
+    use crate::test_utils::dummy_token_key_secret;
     use trusted_cluster_operator_test_utils::mock_client::*;
 
     fn dummy_cluster_ctx(client: Client) -> ClusterContext {
@@ -419,6 +577,7 @@ mod tests {
             cluster.metadata.deletion_timestamp = Some(Time(Timestamp::now()));
             cluster.status = Some(TrustedExecutionClusterStatus {
                 conditions: Some(vec![foreign_condition]),
+                pcr_combination: None,
             });
             let result = reconcile(Arc::new(cluster), Arc::new(dummy_cluster_ctx(client))).await;
             assert_eq!(result.unwrap(), LONG_REQUEUE);
@@ -441,31 +600,37 @@ mod tests {
         };
 
         let clos = async |req: Request<Body>, ctr| {
-            if ctr < 8 && req.method() == Method::POST {
+            if ctr < 15 {
                 use serde_json::to_string;
-                let resp = match ctr {
+                let resp = match (ctr, req.method()) {
                     // Trustee
-                    0 => to_string(&ConfigMap::default()),
-                    1 => to_string(&ConfigMap::default()),
-                    2 => to_string(&Service::default()),
-                    3 => to_string(&Deployment::default()),
+                    (0, &Method::POST) => to_string(&Secret::default()),
+                    // JWKS of the token key
+                    (1, &Method::GET) => to_string(&dummy_token_key_secret(None)),
+                    (2, &Method::PATCH) => to_string(&ConfigMap::default()),
+                    (3 | 4, &Method::POST) => to_string(&ConfigMap::default()),
+                    (5, &Method::POST) => to_string(&Service::default()),
+                    (6, &Method::POST) => to_string(&Deployment::default()),
+                    // Trustee configuration update
+                    (7, &Method::GET) | (8, &Method::PUT) => to_string(&ConfigMap::default()),
+                    (9, &Method::GET) | (10, &Method::PATCH) => to_string(&Deployment::default()),
                     // Registration server
-                    4 => to_string(&Deployment::default()),
-                    5 => to_string(&Service::default()),
+                    (11, &Method::POST) => to_string(&Deployment::default()),
+                    (12, &Method::POST) => to_string(&Service::default()),
                     // Attestation key register server
-                    6 => to_string(&Deployment::default()),
-                    7 => to_string(&Service::default()),
-                    _ => unreachable!("unexpected counter {ctr}"),
+                    (13, &Method::POST) => to_string(&Deployment::default()),
+                    (14, &Method::POST) => to_string(&Service::default()),
+                    _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
                 };
                 Ok(resp.unwrap())
-            } else if ctr == 8 && req.method() == Method::GET {
+            } else if ctr == 15 && req.method() == Method::GET {
                 let object_list = ObjectList::<ApprovedImage> {
                     items: Vec::new(),
                     types: Default::default(),
                     metadata: Default::default(),
                 };
                 Ok(serde_json::to_string(&object_list).unwrap())
-            } else if ctr == 9 && req.method() == Method::PATCH {
+            } else if ctr == 16 && req.method() == Method::PATCH {
                 let body = req.into_body().collect_bytes().await.unwrap().to_vec();
                 let body = String::from_utf8_lossy(&body);
                 assert!(body.contains("ForeignCondition"),);
@@ -495,8 +660,9 @@ mod tests {
         let mut cluster = dummy_cluster();
         cluster.status = Some(TrustedExecutionClusterStatus {
             conditions: Some(vec![pre_existing_installed, foreign_condition]),
+            pcr_combination: None,
         });
-        count_check!(10, clos, |client| {
+        count_check!(17, clos, |client| {
             let result = reconcile(Arc::new(cluster), Arc::new(dummy_cluster_ctx(client))).await;
             assert_eq!(result.unwrap(), LONG_REQUEUE);
         });
@@ -547,7 +713,10 @@ mod tests {
         count_check!(0, clos2, |client| {
             let mut cluster = dummy_cluster();
             cluster.metadata.deletion_timestamp = Some(Time(Timestamp::now()));
-            cluster.status = Some(TrustedExecutionClusterStatus { conditions });
+            cluster.status = Some(TrustedExecutionClusterStatus {
+                conditions,
+                pcr_combination: None,
+            });
             reconcile(Arc::new(cluster), Arc::new(dummy_cluster_ctx(client)))
                 .await
                 .unwrap();
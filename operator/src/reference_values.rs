@@ -3,91 +3,187 @@
 //
 // SPDX-License-Identifier: MIT
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail};
 use compute_pcrs_lib::Pcr;
 use futures_util::StreamExt;
 use k8s_openapi::{
     api::{
-        batch::v1::{Job, JobSpec},
-        core::v1::{ConfigMap, Container, ImageVolumeSource, Volume, VolumeMount},
-        core::v1::{Pod, PodSpec, PodTemplateSpec},
+        batch::v1::{Job, JobCondition, JobSpec},
+        core::v1::{ConfigMap, ConfigMapVolumeSource, Container, EmptyDirVolumeSource},
+        core::v1::{ImageVolumeSource, ResourceRequirements},
+        core::v1::{Node, Pod, PodSpec, PodTemplateSpec},
+        core::v1::{Volume, VolumeMount},
     },
-    jiff::Timestamp,
+    apimachinery::pkg::apis::meta::v1::Condition,
+    jiff::{SignedDuration, Timestamp},
 };
-use kube::api::{DeleteParams, ListParams, ObjectMeta, Patch};
+use kube::api::{DeleteParams, ListParams, LogParams, ObjectMeta, Patch};
 use kube::runtime::{
-    controller::{Action, Controller},
+    controller::{self, Action, Controller},
+    events::{self, EventType, Recorder},
     finalizer,
     finalizer::Event,
+    reflector::{self, ObjectRef},
     watcher,
 };
 use kube::{Api, Client, Resource};
 use log::{info, warn};
-use oci_client::secrets::RegistryAuth;
+use oci_client::{manifest::OciManifest, secrets::RegistryAuth};
 use oci_spec::image::ImageConfiguration;
 use openssl::hash::{MessageDigest, hash};
-use serde::Deserialize;
 use serde_json::json;
-use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, PoisonError, atomic::AtomicBool, atomic::Ordering};
+use std::time::Duration;
 
 use crate::COMPONENT_VERSION;
-use crate::trustee::{self, get_image_pcrs};
+use crate::{registry, signature, trustee};
 use operator::{ControllerError, LONG_REQUEUE, upsert_condition};
-use operator::{controller_error_policy, controller_info, create_or_info_if_exists};
+use operator::{controller_error_policy, controller_info, spawn_reflector};
 use trusted_cluster_operator_lib::{conditions::*, reference_values::*, *};
 
 const JOB_LABEL_KEY: &str = "kind";
 const APPROVED_IMAGE_ANNOTATION: &str = "approved-image";
 const PCR_COMMAND_NAME: &str = "compute-pcrs";
 const PCR_LABEL: &str = "org.coreos.pcrs";
+const NODE_ARCH_LABEL: &str = "kubernetes.io/arch";
 /// Finalizer name to discard reference values when an image is no longer approved
 const APPROVED_IMAGE_FINALIZER: &str = "finalizer.approved-image.trusted-execution-clusters.io";
+/// Time ahead of notAfter from which an image is reported as expiring
+const EXPIRY_WARNING_PERIOD: SignedDuration = SignedDuration::from_hours(7 * 24);
+/// Quiet period after an image PCR change before reference values are recomputed
+const RV_UPDATE_DEBOUNCE: Duration = Duration::from_secs(5);
+/// Label that Kubernetes sets on the pods of a Job
+const JOB_NAME_LABEL: &str = "batch.kubernetes.io/job-name";
+/// Lines from the end of the log of a failed compute-pcrs Job to report in the image status
+const JOB_LOG_TAIL_LINES: i64 = 20;
+/// Characters from the end of the log of a failed compute-pcrs Job to report in the image status
+const JOB_LOG_MAX_CHARS: usize = 2048;
+/// Number of failed compute-pcrs Jobs of an image since its PCRs were last committed
+const COMPUTE_FAILURES_ANNOTATION: &str = "trusted-execution-clusters.io/compute-pcrs-failures";
+/// Time of the last failed compute-pcrs Job of an image
+const COMPUTE_FAILED_AT_ANNOTATION: &str = "trusted-execution-clusters.io/compute-pcrs-failed-at";
+/// Delay before a failed computation is retried, doubling with every further failure
+const COMPUTE_RETRY_BACKOFF: SignedDuration = SignedDuration::from_secs(60);
+const COMPUTE_RETRY_MAX_BACKOFF: SignedDuration = SignedDuration::from_hours(1);
+/// Defaults of the pcrComputation settings of the TrustedExecutionCluster
+const DEFAULT_MAX_CONCURRENT_JOBS: i32 = 3;
+const DEFAULT_JOB_DEADLINE_SECONDS: i64 = 1800;
+const DEFAULT_JOB_TTL_SECONDS: i32 = 3600;
+/// Interval at which queued images check for a free compute-pcrs Job slot
+const JOB_QUEUE_REQUEUE: Duration = Duration::from_secs(30);
 
-/// Synchronize with compute_pcrs_cli::Output
-#[derive(Deserialize)]
-struct ComputePcrsOutput {
-    pcrs: Vec<Pcr>,
-}
+/// Whether the API server accepts image volumes. Cleared when it rejects one, after which
+/// compute-pcrs pulls images itself.
+static IMAGE_VOLUMES_SUPPORTED: AtomicBool = AtomicBool::new(true);
 
-pub async fn create_pcrs_config_map(client: Client) -> Result<()> {
-    let empty_data = BTreeMap::from([(
-        PCR_CONFIG_FILE.to_string(),
-        serde_json::to_string(&ImagePcrs::default())?,
-    )]);
-    let config_map = ConfigMap {
-        metadata: ObjectMeta {
-            name: Some(PCR_CONFIG_MAP.to_string()),
-            ..Default::default()
-        },
-        data: Some(empty_data),
-        ..Default::default()
+/// Move image PCRs from the single ConfigMap of earlier versions to per-image ConfigMaps
+pub async fn migrate_image_pcrs(client: Client) -> Result<()> {
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client.clone());
+    let Some(image_pcrs_map) = config_maps.get_opt(PCR_CONFIG_MAP).await? else {
+        return Ok(());
     };
-    create_or_info_if_exists!(client, ConfigMap, config_map);
+    let images: Api<ApprovedImage> = Api::default_namespaced(client.clone());
+    for (name, image_pcr) in get_image_pcrs(image_pcrs_map)?.0 {
+        let Some(image) = images.get_opt(&name).await? else {
+            info!("ApprovedImage {name} no longer exists, not migrating its PCRs");
+            continue;
+        };
+        let owner_reference = generate_owner_reference(&image)?;
+        store_image_pcr(client.clone(), &name, &image_pcr, owner_reference).await?;
+    }
+    let delete = config_maps.delete(PCR_CONFIG_MAP, &DeleteParams::default());
+    delete.await?;
+    info!("Migrated image PCRs from ConfigMap {PCR_CONFIG_MAP} to per-image ConfigMaps");
     Ok(())
 }
 
-async fn fetch_pcr_label(image_ref: &oci_client::Reference) -> Result<Option<Vec<Pcr>>> {
-    let client = oci_client::Client::new(Default::default());
-    let (_, _, raw_config) = client
-        .pull_manifest_and_config(image_ref, &RegistryAuth::Anonymous)
-        .await?;
+/// PCR labels by OCI architecture, None for architectures without a label
+type PcrLabels = BTreeMap<String, Option<Vec<Pcr>>>;
+
+/// OCI architecture and PCR label of a single-platform image
+async fn read_pcr_label(
+    oci_client: &oci_client::Client,
+    image_ref: &oci_client::Reference,
+    auth: &RegistryAuth,
+) -> Result<(String, Option<Vec<Pcr>>)> {
+    let (_, _, raw_config) = oci_client.pull_manifest_and_config(image_ref, auth).await?;
     let config: ImageConfiguration = serde_json::from_str(&raw_config)?;
-    config
+    let label = config
         .labels_of_config()
         .and_then(|m| m.get(PCR_LABEL))
         .map(|l| serde_json::from_str::<ComputePcrsOutput>(l).map(|o| o.pcrs))
-        .transpose()
-        .map_err(Into::into)
+        .transpose()?;
+    Ok((config.architecture().to_string(), label))
+}
+
+/// PCR labels of an image, one per Linux platform of a multi-architecture image
+async fn read_pcr_labels(
+    oci_client: &oci_client::Client,
+    image_ref: &oci_client::Reference,
+    auth: &RegistryAuth,
+) -> Result<PcrLabels> {
+    let (manifest, _) = oci_client.pull_manifest(image_ref, auth).await?;
+    let OciManifest::ImageIndex(index) = manifest else {
+        let (architecture, label) = read_pcr_label(oci_client, image_ref, auth).await?;
+        return Ok(BTreeMap::from([(architecture, label)]));
+    };
+    let mut labels = BTreeMap::new();
+    for entry in &index.manifests {
+        // Skips e.g. attestation manifests, whose platform is unknown
+        let platform = entry.platform.as_ref().filter(|p| p.os == "linux");
+        let Some(platform) = platform else {
+            continue;
+        };
+        let platform_ref = oci_client::Reference::with_digest(
+            image_ref.registry().to_string(),
+            image_ref.repository().to_string(),
+            entry.digest.clone(),
+        );
+        let (_, label) = read_pcr_label(oci_client, &platform_ref, auth).await?;
+        labels.insert(platform.architecture.to_string(), label);
+    }
+    if labels.is_empty() {
+        bail!("Image index {image_ref} had no Linux platforms");
+    }
+    Ok(labels)
+}
+
+/// Read the PCR labels of an image with the registry configuration of the TrustedExecutionCluster
+async fn fetch_pcr_labels(
+    client: Client,
+    image: &ApprovedImage,
+    image_ref: &oci_client::Reference,
+    spec: &TrustedExecutionClusterSpec,
+) -> Result<PcrLabels> {
+    let config = spec.registry.as_ref();
+    registry::read_image(client, image, image_ref, config, read_pcr_labels).await
 }
 
+/// Pod spec of a compute-pcrs Job. Without image volume support, the image is pulled by
+/// compute-pcrs into an emptyDir instead.
 fn build_compute_pcrs_pod_spec(
     resource_name: &str,
     boot_image: &str,
     pcrs_compute_image: &str,
+    image_volume: bool,
 ) -> PodSpec {
     let image_volume_name = "image";
     let mut cmd = vec![PCR_COMMAND_NAME, "--image", boot_image];
     cmd.extend(&["--resource-name", resource_name]);
+    let mut volume = Volume {
+        name: image_volume_name.to_string(),
+        ..Default::default()
+    };
+    if image_volume {
+        volume.image = Some(ImageVolumeSource {
+            reference: Some(boot_image.to_string()),
+            ..Default::default()
+        });
+    } else {
+        cmd.push("--pull");
+        volume.empty_dir = Some(EmptyDirVolumeSource::default());
+    }
 
     PodSpec {
         service_account_name: Some("trusted-cluster-operator".to_string()),
@@ -102,26 +198,107 @@ fn build_compute_pcrs_pod_spec(
             }]),
             ..Default::default()
         }],
-        volumes: Some(vec![Volume {
-            name: image_volume_name.to_string(),
-            image: Some(ImageVolumeSource {
-                reference: Some(boot_image.to_string()),
-                ..Default::default()
-            }),
-            ..Default::default()
-        }]),
+        volumes: Some(vec![volume]),
         restart_policy: Some("Never".to_string()),
         ..Default::default()
     }
 }
 
+/// Whether a Job failed, including by exhausting its backoffLimit
+fn job_failed(job: &Job) -> bool {
+    let status = job.status.as_ref();
+    let conditions = status.and_then(|s| s.conditions.as_ref());
+    let failed = |c: &JobCondition| c.type_ == "Failed" && c.status == "True";
+    let backoff_limit = job.spec.as_ref().and_then(|s| s.backoff_limit);
+    // Kubernetes default
+    let backoff_limit = backoff_limit.unwrap_or(6);
+    let failed_pods = status.and_then(|s| s.failed).unwrap_or(0);
+    conditions.is_some_and(|cs| cs.iter().any(failed)) || failed_pods > backoff_limit
+}
+
+/// Tail of the log of the latest pod of a Job
+async fn job_log_tail(client: Client, job_name: &str) -> Result<String> {
+    let pods: Api<Pod> = Api::default_namespaced(client);
+    let lp = ListParams::default().labels(&format!("{JOB_NAME_LABEL}={job_name}"));
+    let pod_list = pods.list(&lp).await?;
+    let latest = pod_list
+        .iter()
+        .max_by_key(|pod| pod.metadata.creation_timestamp.as_ref().map(|t| t.0));
+    let name = latest.and_then(|pod| pod.metadata.name.as_ref());
+    let name = name.context(format!("Job {job_name} had no pods"))?;
+    let params = LogParams {
+        container: Some(PCR_COMMAND_NAME.to_string()),
+        tail_lines: Some(JOB_LOG_TAIL_LINES),
+        ..Default::default()
+    };
+    let log = pods.logs(name, &params).await?;
+    let skip = log.chars().count().saturating_sub(JOB_LOG_MAX_CHARS);
+    let tail: String = log.chars().skip(skip).collect();
+    Ok(tail.trim_end().to_string())
+}
+
+/// Record the failure of a compute-pcrs Job on its image, with the log tail in the Committed
+/// condition, so that the computation is retried with backoff
+async fn record_job_failure(client: Client, job: &Job, job_name: &str) -> Result<()> {
+    let owners = job.metadata.owner_references.iter().flatten();
+    let owner = owners.find(|o| o.kind == ApprovedImage::kind(&()));
+    let Some(owner) = owner else {
+        warn!("Job {job_name} failed, but had no ApprovedImage owner");
+        return Ok(());
+    };
+    let image_name = &owner.name;
+    let images: Api<ApprovedImage> = Api::default_namespaced(client.clone());
+    let Some(image) = images.get_opt(image_name).await? else {
+        info!("ApprovedImage {image_name} of failed Job {job_name} no longer exists");
+        return Ok(());
+    };
+    let log = match job_log_tail(client, job_name).await {
+        Ok(log) => log,
+        Err(e) => {
+            warn!("Failed to read log of Job {job_name}: {e}");
+            "<no log available>".to_string()
+        }
+    };
+    let previous = compute_failures(&image).map(|(failures, _)| failures);
+    let failures = previous.unwrap_or(0) + 1;
+    warn!("Job {job_name} computing PCRs of {image_name} failed {failures} time(s)");
+
+    // Before the status, so that the image does not relaunch the Job without backoff
+    let json = json!({
+        "metadata": {
+            "annotations": {
+                COMPUTE_FAILURES_ANNOTATION: failures.to_string(),
+                COMPUTE_FAILED_AT_ANNOTATION: Timestamp::now().to_string(),
+            },
+        }
+    });
+    let patch = Patch::Merge(&json);
+    images
+        .patch(image_name, &Default::default(), &patch)
+        .await?;
+
+    let generation = image.metadata.generation;
+    let mut committed = committed_condition(NOT_COMMITTED_REASON_FAILED, generation, &image.status);
+    committed.message = format!("Job {job_name} failed, retrying with backoff. Log tail:\n{log}");
+    let mut conditions = image.status.as_ref().and_then(|s| s.conditions.clone());
+    upsert_condition(&mut conditions, committed);
+    let status = ApprovedImageStatus {
+        conditions,
+        pcrs: None,
+    };
+    update_status!(images, image_name, status)?;
+    Ok(())
+}
+
 async fn job_reconcile(job: Arc<Job>, client: Arc<Client>) -> Result<Action, ControllerError> {
     let err = "Job changed, but had no name";
     let name = &job.metadata.name.clone().context(err)?;
     let err = format!("Job {name} changed, but had no status");
     let status = &job.status.clone().context(err)?;
     let kube_client = Arc::unwrap_or_clone(client);
-    if status.completion_time.is_none() {
+    if job_failed(&job) {
+        record_job_failure(kube_client.clone(), &job, name).await?;
+    } else if status.completion_time.is_none() {
         info!("Job {name} changed, but had not completed");
         return Ok(Action::requeue(Duration::from_secs(300)));
     }
@@ -129,10 +306,49 @@ async fn job_reconcile(job: Arc<Job>, client: Arc<Client>) -> Result<Action, Con
     // Foreground deletion: Delete the pod too
     let delete = jobs.delete(name, &DeleteParams::foreground()).await;
     delete.map_err(Into::<anyhow::Error>::into)?;
-    trustee::update_reference_values(kube_client).await?;
     Ok(Action::await_change())
 }
 
+/// Recompute reference values when image PCRs or the TrustedExecutionCluster change. Changes in
+/// quick succession, e.g. when several images are approved at once, all map to the cluster and
+/// are coalesced into one update by debouncing.
+async fn rv_update_reconcile(
+    _cluster: Arc<TrustedExecutionCluster>,
+    client: Arc<Client>,
+) -> Result<Action, ControllerError> {
+    let kube_client = Arc::unwrap_or_clone(client);
+    trustee::update_reference_values(kube_client, false).await?;
+    Ok(LONG_REQUEUE)
+}
+
+pub async fn launch_rv_update_controller(client: Client) {
+    let clusters: Api<TrustedExecutionCluster> = Api::default_namespaced(client.clone());
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client.clone());
+    let controller = Controller::new(clusters, Default::default());
+    let cluster_store = controller.store();
+    // Deletions of image PCRs trigger an update too, which they would not as primary resource
+    let to_clusters = move |_: ConfigMap| {
+        let clusters = cluster_store.state();
+        let refs = clusters
+            .iter()
+            .map(|cluster| ObjectRef::from_obj(&**cluster));
+        refs.collect::<Vec<_>>()
+    };
+    let image_pcrs_watcher = watcher::Config::default().labels(IMAGE_PCR_LABEL);
+    let controller_config = controller::Config::default().debounce(RV_UPDATE_DEBOUNCE);
+    tokio::spawn(
+        controller
+            .watches(config_maps, image_pcrs_watcher, to_clusters)
+            .with_config(controller_config)
+            .run(
+                rv_update_reconcile,
+                controller_error_policy,
+                Arc::new(client),
+            )
+            .for_each(controller_info),
+    );
+}
+
 pub async fn launch_rv_job_controller(client: Client) {
     let jobs: Api<Job> = Api::default_namespaced(client.clone());
     let watcher = watcher::Config {
@@ -146,28 +362,105 @@ pub async fn launch_rv_job_controller(client: Client) {
     );
 }
 
-// Name job by sanitized image name, plus a hash to disambiguate
-// tags that differed only beyond the truncation limit
-fn get_job_name(boot_image: &str) -> Result<String> {
+// Name job by architecture, if any, and sanitized image name, plus a
+// hash to disambiguate tags that differed only beyond the truncation limit.
+// The hash covers the ApprovedImage name too, as images of the same digest
+// may differ in platform profiles and kernel command lines.
+fn get_job_name(image: &ApprovedImage, architecture: Option<&str>) -> Result<String> {
+    let name = image.metadata.name.as_ref();
+    let name = name.context("ApprovedImage had no name")?;
+    let boot_image = &image.spec.image;
     let rfc1035_boot_image = boot_image.replace(['.', ':', '/', '@', '_'], "-");
-    let boot_image_hash = hash(MessageDigest::sha1(), boot_image.as_bytes())?;
+    let hashed = format!("{name}/{boot_image}{}", architecture.unwrap_or_default());
+    let boot_image_hash = hash(MessageDigest::sha1(), hashed.as_bytes())?;
     let mut boot_image_hash_str = hex::encode(boot_image_hash);
     boot_image_hash_str.truncate(10);
-    let job_name = format!("{PCR_COMMAND_NAME}-{boot_image_hash_str}-{rfc1035_boot_image}");
+    let prefix = match architecture {
+        Some(architecture) => format!("{PCR_COMMAND_NAME}-{architecture}"),
+        None => PCR_COMMAND_NAME.to_string(),
+    };
+    let job_name = format!("{prefix}-{boot_image_hash_str}-{rfc1035_boot_image}");
     let trimmed: String = job_name.chars().take(63).collect();
     let trimmed = trimmed.trim_end_matches('-').to_string();
     Ok(trimmed)
 }
 
-async fn compute_fresh_pcrs(client: Client, image: &ApprovedImage) -> anyhow::Result<()> {
-    let job_name = get_job_name(&image.spec.image)?;
+/// Resources of the compute-pcrs container. The CRD type is converted through its JSON form.
+fn job_resources(
+    config: Option<&TrustedExecutionClusterPcrComputation>,
+) -> Result<Option<ResourceRequirements>> {
+    let Some(resources) = config.and_then(|c| c.resources.as_ref()) else {
+        return Ok(None);
+    };
+    let resources = serde_json::to_value(resources)?;
+    Ok(Some(serde_json::from_value(resources)?))
+}
+
+/// Mount the EFI and MOK variable ConfigMaps of each platform profile at the profile's name below
+/// EFI_VARIABLES_MOUNTPOINT and MOK_VARIABLES_MOUNTPOINT, where compute-pcrs reads them
+fn add_profile_volumes(pod_spec: &mut PodSpec, profiles: &[PlatformProfile]) -> Result<()> {
+    for (i, profile) in profiles.iter().enumerate() {
+        let name = profile.metadata.name.as_ref();
+        let profile_name = name.context("PlatformProfile had no name")?;
+        let spec = &profile.spec;
+        let efivars = (
+            EFI_VARIABLES_MOUNTPOINT,
+            &spec.efi_variables_config_map_name,
+        );
+        let mokvars = (
+            MOK_VARIABLES_MOUNTPOINT,
+            &spec.mok_variables_config_map_name,
+        );
+        for (mountpoint, config_map) in [efivars, mokvars] {
+            let Some(config_map) = config_map else {
+                continue;
+            };
+            let name = format!("{}-{i}", mountpoint.trim_start_matches('/'));
+            pod_spec.volumes.get_or_insert_default().push(Volume {
+                name: name.clone(),
+                config_map: Some(ConfigMapVolumeSource {
+                    name: config_map.clone(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+            let mounts = pod_spec.containers[0].volume_mounts.get_or_insert_default();
+            mounts.push(VolumeMount {
+                name,
+                mount_path: format!("{mountpoint}/{profile_name}"),
+                read_only: Some(true),
+                ..Default::default()
+            });
+        }
+    }
+    Ok(())
+}
+
+fn build_compute_pcrs_job(
+    image: &ApprovedImage,
+    profiles: &[PlatformProfile],
+    architecture: Option<&str>,
+    config: Option<&TrustedExecutionClusterPcrComputation>,
+    image_volume: bool,
+) -> Result<Job> {
+    let job_name = get_job_name(image, architecture)?;
     let env = "RELATED_IMAGE_COMPUTE_PCRS";
     let default_image =
         format!("quay.io/trusted-execution-clusters/compute-pcrs:{COMPONENT_VERSION}");
     let pcrs_compute_image = std::env::var(env).ok().unwrap_or(default_image);
     let resource_name = image.metadata.name.as_ref().unwrap();
-    let pod_spec =
-        build_compute_pcrs_pod_spec(resource_name, &image.spec.image, &pcrs_compute_image);
+    let boot_image = &image.spec.image;
+    let mut pod_spec =
+        build_compute_pcrs_pod_spec(resource_name, boot_image, &pcrs_compute_image, image_volume);
+    // Used to pull the image volume, compute-pcrs reads them itself when it pulls the image
+    pod_spec.image_pull_secrets = registry::image_pull_secrets(image);
+    pod_spec.node_selector = architecture.map(|architecture| {
+        BTreeMap::from([(NODE_ARCH_LABEL.to_string(), architecture.to_string())])
+    });
+    pod_spec.containers[0].resources = job_resources(config)?;
+    add_profile_volumes(&mut pod_spec, profiles)?;
+    let deadline = config.and_then(|c| c.active_deadline_seconds);
+    let ttl = config.and_then(|c| c.ttl_seconds_after_finished);
     let job = Job {
         metadata: ObjectMeta {
             name: Some(job_name.clone()),
@@ -189,14 +482,139 @@ async fn compute_fresh_pcrs(client: Client, image: &ApprovedImage) -> anyhow::Re
                 }),
                 spec: Some(pod_spec),
             },
+            active_deadline_seconds: Some(deadline.unwrap_or(DEFAULT_JOB_DEADLINE_SECONDS)),
+            // In case the job controller does not delete the job
+            ttl_seconds_after_finished: Some(ttl.unwrap_or(DEFAULT_JOB_TTL_SECONDS)),
             ..Default::default()
         }),
         ..Default::default()
     };
-    create_or_info_if_exists!(client, Job, job);
+    Ok(job)
+}
+
+/// Whether the API server rejected the volumes of a Job as invalid, as one without image volume
+/// support does for an image volume, which it reads as a volume without a type
+fn image_volume_rejected(error: &kube::Error) -> bool {
+    let kube::Error::Api(status) = error else {
+        return false;
+    };
+    let mut causes = status.details.iter().flat_map(|details| &details.causes);
+    status.code == 422
+        && status.reason == "Invalid"
+        && causes.any(|cause| cause.field.starts_with("spec.template.spec.volumes"))
+}
+
+/// Launch a job that computes the PCRs of an image. With an architecture, the job runs on a node
+/// of that architecture, which pulls the image for it. When the API server rejects the image
+/// volume, e.g. on clusters without the ImageVolume feature, the job is created again to pull
+/// the image in compute-pcrs, and so are all later jobs.
+async fn compute_fresh_pcrs(
+    client: Client,
+    image: &ApprovedImage,
+    architecture: Option<&str>,
+    config: Option<&TrustedExecutionClusterPcrComputation>,
+) -> anyhow::Result<()> {
+    let job_name = get_job_name(image, architecture)?;
+    let profiles = get_platform_profiles(client.clone(), image).await?;
+    let jobs: Api<Job> = Api::default_namespaced(client);
+    let image_volume = IMAGE_VOLUMES_SUPPORTED.load(Ordering::Relaxed);
+    let job = build_compute_pcrs_job(image, &profiles, architecture, config, image_volume)?;
+    let mut created = jobs.create(&Default::default(), &job).await;
+    if image_volume && matches!(&created, Err(e) if image_volume_rejected(e)) {
+        warn!("Image volume of Job {job_name} was rejected, pulling images in the Job instead");
+        IMAGE_VOLUMES_SUPPORTED.store(false, Ordering::Relaxed);
+        let job = build_compute_pcrs_job(image, &profiles, architecture, config, false)?;
+        created = jobs.create(&Default::default(), &job).await;
+    }
+    match created {
+        Ok(_) => info!("Create Job {job_name}"),
+        Err(kube::Error::Api(ae)) if ae.code == 409 => info!("Job {job_name} already exists"),
+        Err(e) => return Err(e.into()),
+    }
     Ok(())
 }
 
+/// Whether a Job neither completed nor failed
+fn job_active(job: &Job) -> bool {
+    let status = job.status.as_ref();
+    let completed = status.and_then(|s| s.completion_time.as_ref()).is_some();
+    !completed && !job_failed(job)
+}
+
+/// Whether an image waits for a free compute-pcrs Job slot
+fn is_queued(image: &ApprovedImage) -> bool {
+    let conditions = image.status.as_ref().and_then(|s| s.conditions.as_ref());
+    let queued =
+        |c: &Condition| c.type_ == COMMITTED_CONDITION && c.reason == NOT_COMMITTED_REASON_QUEUED;
+    conditions.is_some_and(|cs| cs.iter().any(queued))
+}
+
+/// Position of an image in the queue for compute-pcrs Job slots, earlier created images first
+fn queue_key(image: &ApprovedImage) -> (Option<Timestamp>, Option<&String>) {
+    let created = image.metadata.creation_timestamp.as_ref().map(|t| t.0);
+    (created, image.metadata.name.as_ref())
+}
+
+/// Held while counting free compute-pcrs Job slots and launching Jobs into them
+static JOB_LAUNCH: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Number of compute-pcrs Jobs that an image may launch now. Active Jobs take up slots, and
+/// queued images that were created earlier go first.
+fn free_job_slots(
+    jobs: &[Job],
+    images: &[ApprovedImage],
+    image: &ApprovedImage,
+    max_jobs: usize,
+) -> usize {
+    let active = jobs.iter().filter(|job| job_active(job)).count();
+    let key = queue_key(image);
+    let ahead = images.iter().filter(|i| is_queued(i) && queue_key(i) < key);
+    max_jobs.saturating_sub(active + ahead.count())
+}
+
+/// Launch compute-pcrs Jobs for architectures of an image, None for that of whichever node a Job
+/// runs on, as far as the maximum of concurrent Jobs allows. Returns the commit reason.
+async fn launch_jobs(
+    client: Client,
+    image: &ApprovedImage,
+    architectures: &[Option<&str>],
+    config: Option<&TrustedExecutionClusterPcrComputation>,
+) -> Result<&'static str> {
+    // Images are reconciled concurrently, and free slots must be counted after the Jobs of
+    // another image were created
+    let _launching = JOB_LAUNCH.lock().await;
+    let jobs: Api<Job> = Api::default_namespaced(client.clone());
+    let params = ListParams::default().labels(&format!("{JOB_LABEL_KEY}={PCR_COMMAND_NAME}"));
+    let jobs = jobs.list(&params).await?.items;
+    let job_names = jobs.iter().filter_map(|j| j.metadata.name.clone());
+    let job_names: BTreeSet<_> = job_names.collect();
+    let mut unlaunched = Vec::new();
+    for architecture in architectures {
+        let name = get_job_name(image, *architecture)?;
+        if !job_names.contains(&name) {
+            unlaunched.push(*architecture);
+        }
+    }
+    if unlaunched.is_empty() {
+        return Ok(NOT_COMMITTED_REASON_COMPUTING);
+    }
+
+    let images: Api<ApprovedImage> = Api::default_namespaced(client.clone());
+    let images = images.list(&Default::default()).await?.items;
+    let max_jobs = config.and_then(|c| c.max_concurrent_jobs);
+    let max_jobs = usize::try_from(max_jobs.unwrap_or(DEFAULT_MAX_CONCURRENT_JOBS))?;
+    let free = free_job_slots(&jobs, &images, image, max_jobs);
+    for architecture in unlaunched.iter().take(free) {
+        compute_fresh_pcrs(client.clone(), image, *architecture, config).await?;
+    }
+    if unlaunched.len() > free {
+        let name = image.metadata.name.as_deref().unwrap_or("<no name>");
+        info!("Queued PCR computation for {name}, {max_jobs} compute-pcrs jobs are active");
+        return Ok(NOT_COMMITTED_REASON_QUEUED);
+    }
+    Ok(NOT_COMMITTED_REASON_COMPUTING)
+}
+
 async fn adopt_approved_image(
     client: Client,
     image_name: &str,
@@ -294,25 +712,150 @@ async fn image_add_reconcile(
         info!("TrustedExecutionCluster is being deleted, deferring image processing for {name}");
         return Ok(Action::requeue(Duration::from_secs(5)));
     }
-    let (action, reason) = match handle_new_image(client.clone(), image).await {
-        Ok(reason) => (LONG_REQUEUE, reason),
-        Err(e) => {
-            warn!("PCR computation for {name} failed: {e}");
-            let action = Action::requeue(Duration::from_secs(60));
-            (action, NOT_COMMITTED_REASON_FAILED)
+    let now = Timestamp::now();
+    let (not_before, not_after) = image_validity(&image.spec)?;
+    let mut image_pcr = None;
+    let (action, reason) = if not_after.is_some_and(|t| t <= now) {
+        (LONG_REQUEUE, NOT_COMMITTED_REASON_EXPIRED)
+    } else if not_before.is_some_and(|t| t > now) {
+        (LONG_REQUEUE, NOT_COMMITTED_REASON_NOT_YET)
+    } else if let Some(delay) = compute_retry_delay(image, now) {
+        // Keeps the Committed condition with the log of the failed Job until the retry
+        info!("Retrying the computation of PCRs for {name} in {delay:?}");
+        return Ok(Action::requeue(delay));
+    } else {
+        match handle_new_image(client.clone(), image, &cluster.spec).await {
+            // Signatures may be published after the image was approved
+            Ok((NOT_COMMITTED_REASON_SIGNATURE_INVALID, _)) => (
+                Action::requeue(Duration::from_secs(300)),
+                NOT_COMMITTED_REASON_SIGNATURE_INVALID,
+            ),
+            Ok((reason, stored)) => {
+                if reason == COMMITTED_REASON {
+                    reset_compute_failures(client.clone(), image).await?;
+                }
+                image_pcr = stored;
+                let action = match reason {
+                    NOT_COMMITTED_REASON_QUEUED => Action::requeue(JOB_QUEUE_REQUEUE),
+                    _ => LONG_REQUEUE,
+                };
+                (action, reason)
+            }
+            Err(e) => {
+                warn!("PCR computation for {name} failed: {e}");
+                let action = Action::requeue(Duration::from_secs(60));
+                (action, NOT_COMMITTED_REASON_FAILED)
+            }
         }
     };
-    let committed = committed_condition(reason, image.metadata.generation, &image.status);
+    let generation = image.metadata.generation;
+    let committed = committed_condition(reason, generation, &image.status);
+    let expiring = not_after.is_some_and(|t| t - EXPIRY_WARNING_PERIOD <= now);
+    let expiring = expiring_condition(not_after, expiring, generation, &image.status);
 
     // Upserting the committed condition and keeping the existing conditions intact.
     let mut conditions = image.status.as_ref().and_then(|s| s.conditions.clone());
-    let changed = upsert_condition(&mut conditions, committed);
-    if changed {
+    let committed_changed = upsert_condition(&mut conditions, committed);
+    let expiring_changed = upsert_condition(&mut conditions, expiring);
+    if committed_changed
+        && [NOT_COMMITTED_REASON_EXPIRED, NOT_COMMITTED_REASON_NOT_YET].contains(&reason)
+    {
+        // Only on transition, so that reference values are not recomputed on every requeue
+        disallow_image(client.clone(), name).await?;
+        if reason == NOT_COMMITTED_REASON_EXPIRED {
+            info!("ApprovedImage {name} expired and was disallowed");
+            publish_expired_event(client.clone(), image).await;
+        }
+    }
+    let pcrs = image_pcr.as_ref().map(pcrs_status).transpose()?;
+    let existing_pcrs = image.status.as_ref().and_then(|s| s.pcrs.as_ref());
+    // Status types do not implement PartialEq
+    let pcrs_changed = pcrs.is_some() && serde_json::to_value(&pcrs)? != json!(existing_pcrs);
+    if committed_changed || expiring_changed || pcrs_changed {
         let images: Api<ApprovedImage> = Api::default_namespaced(client);
-        update_status!(images, &name, ApprovedImageStatus { conditions })
+        update_status!(images, &name, ApprovedImageStatus { conditions, pcrs })
             .map_err(|e| finalizer::Error::<ControllerError>::ApplyFailed(e.into()))?;
     }
-    Ok(action)
+    Ok(validity_action(action, now, not_before, not_after))
+}
+
+/// Number of failed compute-pcrs Jobs of an image and the time of the last failure
+fn compute_failures(image: &ApprovedImage) -> Option<(u32, Timestamp)> {
+    let annotations = image.metadata.annotations.as_ref()?;
+    let failures = annotations.get(COMPUTE_FAILURES_ANNOTATION)?;
+    let failed_at = annotations.get(COMPUTE_FAILED_AT_ANNOTATION)?;
+    Some((failures.parse().ok()?, failed_at.parse().ok()?))
+}
+
+/// Time until the computation of an image is retried after failed compute-pcrs Jobs, if it is
+/// not due yet
+fn compute_retry_delay(image: &ApprovedImage, now: Timestamp) -> Option<Duration> {
+    let (failures, failed_at) = compute_failures(image)?;
+    let doublings = failures.saturating_sub(1).min(10);
+    let backoff = COMPUTE_RETRY_BACKOFF * 2_i32.pow(doublings);
+    let backoff = backoff.min(COMPUTE_RETRY_MAX_BACKOFF);
+    let remaining = now.duration_until(failed_at + backoff);
+    // Negative when due
+    Duration::try_from(remaining).ok().filter(|d| !d.is_zero())
+}
+
+/// Forget failed compute-pcrs Jobs of an image once its PCRs are committed
+async fn reset_compute_failures(client: Client, image: &ApprovedImage) -> Result<()> {
+    if compute_failures(image).is_none() {
+        return Ok(());
+    }
+    let name = image.metadata.name.as_deref();
+    let name = name.context("ApprovedImage had no name")?;
+    let json = json!({
+        "metadata": {
+            "annotations": {
+                COMPUTE_FAILURES_ANNOTATION: null,
+                COMPUTE_FAILED_AT_ANNOTATION: null,
+            },
+        }
+    });
+    let images: Api<ApprovedImage> = Api::default_namespaced(client);
+    let patch = Patch::Merge(&json);
+    images.patch(name, &Default::default(), &patch).await?;
+    Ok(())
+}
+
+/// Requeue earlier than the given action if the validity of an image changes before
+fn validity_action(
+    action: Action,
+    now: Timestamp,
+    not_before: Option<Timestamp>,
+    not_after: Option<Timestamp>,
+) -> Action {
+    if action != LONG_REQUEUE {
+        return action;
+    }
+    let warning = not_after.map(|t| t - EXPIRY_WARNING_PERIOD);
+    let boundaries = [not_before, warning, not_after].into_iter().flatten();
+    let next = boundaries.filter(|t| *t > now).min();
+    let until_next = next.map(|t| (t.as_second() - now.as_second()).unsigned_abs() + 1);
+    match until_next.map(Duration::from_secs) {
+        Some(duration) if duration < Duration::from_hours(1) => Action::requeue(duration),
+        _ => action,
+    }
+}
+
+/// Events are informational, failing to publish one is not an error
+async fn publish_expired_event(client: Client, image: &ApprovedImage) {
+    let recorder = Recorder::new(client, "trusted-cluster-operator".into());
+    let not_after = image.spec.not_after.as_deref().unwrap_or("<unknown>");
+    let event = events::Event {
+        type_: EventType::Warning,
+        reason: NOT_COMMITTED_REASON_EXPIRED.to_string(),
+        note: Some(format!(
+            "Image approval expired at {not_after}, its reference values were withdrawn"
+        )),
+        action: "Disallow".to_string(),
+        secondary: None,
+    };
+    if let Err(e) = recorder.publish(&event, &image.object_ref(&())).await {
+        warn!("Failed to publish expiry event: {e}");
+    }
 }
 
 async fn image_remove_reconcile(
@@ -339,10 +882,101 @@ async fn image_remove_reconcile(
     Ok(LONG_REQUEUE)
 }
 
+/// ApprovedImages of `images` that reference a PlatformProfile of `profiles`
+fn images_referencing(
+    images: Vec<Arc<ApprovedImage>>,
+    profiles: &BTreeSet<&str>,
+) -> Vec<ObjectRef<ApprovedImage>> {
+    let referencing = images.into_iter().filter(|image| {
+        let mut names = image.spec.platform_profiles.iter().flatten();
+        names.any(|name| profiles.contains(name.as_str()))
+    });
+    referencing
+        .map(|image| ObjectRef::from_obj(&*image))
+        .collect()
+}
+
+/// Whether `node` joined or changed its architecture since it was recorded in `architectures` by
+/// UID, recording it
+fn node_architecture_changed(node: &Node, architectures: &mut BTreeMap<String, String>) -> bool {
+    let uid = node.metadata.uid.clone();
+    let labels = node.metadata.labels.as_ref();
+    let architecture = labels.and_then(|l| l.get(NODE_ARCH_LABEL)).cloned();
+    let (Some(uid), Some(architecture)) = (uid, architecture) else {
+        return false;
+    };
+    architectures.insert(uid, architecture.clone()) != Some(architecture)
+}
+
+/// Names of the PlatformProfiles of `profiles` that take variables from the ConfigMap `name`
+fn profiles_with_variables<'a>(
+    profiles: &'a [Arc<PlatformProfile>],
+    name: &str,
+) -> BTreeSet<&'a str> {
+    let with_variables = profiles.iter().filter(|profile| {
+        let spec = &profile.spec;
+        let names = [
+            &spec.efi_variables_config_map_name,
+            &spec.mok_variables_config_map_name,
+        ];
+        names.into_iter().any(|n| n.as_deref() == Some(name))
+    });
+    let names = with_variables.filter_map(|profile| profile.metadata.name.as_deref());
+    names.collect()
+}
+
 pub async fn launch_rv_image_controller(client: Client) {
     let images: Api<ApprovedImage> = Api::default_namespaced(client.clone());
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client.clone());
+    // compute-pcrs Jobs store PCRs in ConfigMaps owned by the image, which are reported in its
+    // status
+    let image_pcrs_watcher = watcher::Config::default().labels(IMAGE_PCR_LABEL);
+    let profiles: Api<PlatformProfile> = Api::default_namespaced(client.clone());
+    let (profile_store, profile_writer) = reflector::store::<PlatformProfile>();
+    spawn_reflector(profile_writer, client.clone(), "PlatformProfile");
+    let controller = Controller::new(images, Default::default());
+    let image_store = controller.store();
+    // Changes of a platform profile or its variables require the PCRs of the images that
+    // reference it again
+    let profile_images = image_store.clone();
+    let node_images = image_store.clone();
+    let to_images = move |profile: PlatformProfile| {
+        let names = profile.metadata.name.iter().map(String::as_str).collect();
+        images_referencing(profile_images.state(), &names)
+    };
+    let variables_to_images = move |config_map: ConfigMap| {
+        let Some(name) = config_map.metadata.name.as_deref() else {
+            return Vec::new();
+        };
+        let profiles = profile_store.state();
+        let names = profiles_with_variables(&profiles, name);
+        match names.is_empty() {
+            true => Vec::new(),
+            false => images_referencing(image_store.state(), &names),
+        }
+    };
+    // PCRs of architectures without nodes are pending until a node of theirs joins
+    let nodes: Api<Node> = Api::all(client.clone());
+    let nodes_watcher = watcher::Config::default().labels(NODE_ARCH_LABEL);
+    let node_architectures = Mutex::new(BTreeMap::new());
+    let nodes_to_images = move |node: Node| {
+        let architectures = node_architectures.lock();
+        let mut architectures = architectures.unwrap_or_else(PoisonError::into_inner);
+        if !node_architecture_changed(&node, &mut architectures) {
+            return Vec::new();
+        }
+        let images = node_images.state();
+        images
+            .iter()
+            .map(|image| ObjectRef::from_obj(&**image))
+            .collect()
+    };
     tokio::spawn(
-        Controller::new(images, Default::default())
+        controller
+            .owns(config_maps.clone(), image_pcrs_watcher)
+            .watches(profiles, Default::default(), to_images)
+            .watches(config_maps, Default::default(), variables_to_images)
+            .watches(nodes, nodes_watcher, nodes_to_images)
             .run(image_reconcile, controller_error_policy, Arc::new(client))
             .for_each(controller_info),
     );
@@ -359,18 +993,125 @@ async fn is_pending(client: &Client, resource_name: &str) -> Result<bool> {
         .is_some_and(|phase| phase == "Pending"))
 }
 
-pub async fn handle_new_image(client: Client, image: &ApprovedImage) -> Result<&'static str> {
+/// Architectures of the nodes of the cluster. compute-pcrs Jobs for other architectures could
+/// not be scheduled.
+async fn node_architectures(client: Client) -> Result<BTreeSet<String>> {
+    let nodes: Api<Node> = Api::all(client);
+    let nodes = nodes.list(&Default::default()).await?;
+    let labels = nodes
+        .items
+        .into_iter()
+        .filter_map(|node| node.metadata.labels);
+    let architectures = labels.filter_map(|mut labels| labels.remove(NODE_ARCH_LABEL));
+    Ok(architectures.collect())
+}
+
+/// Add the PCR labels of every architecture of an image to `image_pcr`, so that nodes of any
+/// architecture can attest. Architectures without a label, or all if the image is `unlabeled`,
+/// are pending. Returns those of them that have nodes to compute them on now, the others are
+/// computed once a node of theirs joins.
+fn add_labeled_pcrs(
+    image_pcr: &mut ImagePcr,
+    labels: PcrLabels,
+    node_architectures: &BTreeSet<String>,
+    unlabeled: bool,
+) -> Vec<String> {
+    let image_ref = &image_pcr.reference;
+    let mut pending = Vec::new();
+    for (architecture, label) in labels {
+        let label = label.filter(|_| !unlabeled);
+        if label.is_some() {
+            let origin = PcrOrigin::label();
+            image_pcr.origins.insert(architecture.clone(), origin);
+        } else if !node_architectures.contains(&architecture) {
+            info!("No node of architecture {architecture} for {image_ref}. Pending its PCRs.");
+        } else {
+            if !unlabeled {
+                info!("No {PCR_LABEL} label present for {image_ref} on {architecture}. Computing.");
+            }
+            pending.push(architecture.clone());
+        }
+        let pcrs = label.unwrap_or_default();
+        image_pcr.architectures.insert(architecture, pcrs);
+    }
+    pending
+}
+
+/// Commit reason of an image and its PCRs as stored, if any were
+pub async fn handle_new_image(
+    client: Client,
+    image: &ApprovedImage,
+    spec: &TrustedExecutionClusterSpec,
+) -> Result<(&'static str, Option<ImagePcr>)> {
     let resource_name = image.metadata.name.as_ref().unwrap();
     let boot_image = image.spec.image.as_ref();
-    let config_maps: Api<ConfigMap> = Api::default_namespaced(client.clone());
-    let mut image_pcrs_map = config_maps.get(PCR_CONFIG_MAP).await?;
-    let mut image_pcrs = get_image_pcrs(image_pcrs_map.clone())?;
-    if let Some(pcr) = image_pcrs.0.get(resource_name)
+    let (_, not_after) = image_validity(&image.spec)?;
+    let owner_reference = generate_owner_reference(image)?;
+    let profiles = get_opt_platform_profiles(client.clone(), image).await?;
+    let missing = profiles.iter().filter(|(_, profile)| profile.is_none());
+    let missing: Vec<_> = missing.map(|(name, _)| name.as_str()).collect();
+    let stored = get_image_pcr(client.clone(), resource_name).await?;
+    if !missing.is_empty() {
+        // Creating the profiles reconciles the image again
+        let missing = missing.join(", ");
+        warn!("PlatformProfiles {missing} of image {boot_image} did not exist");
+        let stored = stored.filter(|pcr| pcr.reference == boot_image);
+        return Ok((NOT_COMMITTED_REASON_PROFILE_MISSING, stored));
+    }
+    let profiles: Vec<_> = profiles.into_iter().filter_map(|(_, p)| p).collect();
+    if let Some(mut pcr) = stored
         && pcr.reference == boot_image
     {
+        // Architectures without nodes cannot be computed and do not hold back the commit
+        let node_architectures = node_architectures(client.clone()).await?;
+        let pending = pcr.pending_architectures().into_iter();
+        let pending: Vec<_> = pending
+            .filter(|a| node_architectures.contains(*a))
+            .collect();
+        if !pending.is_empty() {
+            if is_pending(&client, resource_name).await? {
+                return Ok((NOT_COMMITTED_REASON_PENDING, Some(pcr)));
+            }
+            // Jobs of architectures that exist already are not launched again
+            let architectures: Vec<_> = pending.into_iter().map(Some).collect();
+            let config = spec.pcr_computation.as_ref();
+            let reason = launch_jobs(client, image, &architectures, config).await?;
+            return Ok((reason, Some(pcr)));
+        }
+        // Changed platform profiles and kernel command lines invalidate the PCRs computed for them
+        let records = profile_records(client.clone(), &profiles).await?;
+        let cmdlines = image.spec.kernel_command_lines.clone().unwrap_or_default();
+        if records != pcr.profiles || cmdlines != pcr.kernel_command_lines {
+            if is_pending(&client, resource_name).await? {
+                return Ok((NOT_COMMITTED_REASON_PENDING, Some(pcr)));
+            }
+            info!(
+                "Platform profiles or kernel command lines of image {boot_image} changed. \
+                 Computing its PCRs again."
+            );
+            let architectures = pcr.architectures.keys();
+            let architectures = architectures.filter(|a| node_architectures.contains(*a));
+            let mut architectures: Vec<_> = architectures.map(|a| Some(a.as_str())).collect();
+            if architectures.is_empty() {
+                architectures.push(None);
+            }
+            let config = spec.pcr_computation.as_ref();
+            let reason = launch_jobs(client, image, &architectures, config).await?;
+            return Ok((reason, Some(pcr)));
+        }
+        if pcr.unmeasured_command_lines() {
+            warn!(
+                "Image {boot_image} did not boot as a UKI. \
+                 Its kernel command lines cannot be verified and it is not allowed."
+            );
+            return Ok((NOT_COMMITTED_REASON_CMDLINE_UNMEASURED, Some(pcr)));
+        }
         info!("Image {boot_image} was to be allowed, but already was allowed");
-        let res = trustee::update_reference_values(client).await;
-        return res.map(|_| COMMITTED_REASON);
+        if pcr.not_after != not_after {
+            pcr.not_after = not_after;
+            store_image_pcr(client, resource_name, &pcr, owner_reference).await?;
+        }
+        return Ok((COMMITTED_REASON, Some(pcr)));
     }
     let image_ref: oci_client::Reference = boot_image.parse()?;
     if image_ref.digest().is_none() {
@@ -378,51 +1119,98 @@ pub async fn handle_new_image(client: Client, image: &ApprovedImage) -> Result<&
             "Image {boot_image} did not specify a digest. \
              Only images with a digest are supported to avoid ambiguity."
         );
-        return Ok(NOT_COMMITTED_REASON_NO_DIGEST);
+        return Ok((NOT_COMMITTED_REASON_NO_DIGEST, None));
     }
-    let label = fetch_pcr_label(&image_ref).await;
-
-    // Whether to compute pcrs or not.
-    let should_compute_pcrs = match label {
-        Err(ref e) => {
+    if !signature::verify_image(client.clone(), image, &image_ref, spec).await? {
+        return Ok((NOT_COMMITTED_REASON_SIGNATURE_INVALID, None));
+    }
+    let labels = match fetch_pcr_labels(client.clone(), image, &image_ref, spec).await {
+        Ok(labels) => labels,
+        Err(e) => {
             warn!("Fetching PCR label for {image_ref} failed: {e}. Falling back to computation.");
             if is_pending(&client, resource_name).await? {
-                return Ok(NOT_COMMITTED_REASON_PENDING);
+                return Ok((NOT_COMMITTED_REASON_PENDING, None));
             }
-            true
-        }
-        Ok(None) => {
-            info!("No {PCR_LABEL} label present for {image_ref}. Computing.");
-            true
+            // The job stores the PCRs for the architecture of the node it runs on
+            let config = spec.pcr_computation.as_ref();
+            let reason = launch_jobs(client, image, &[None], config).await?;
+            return Ok((reason, None));
         }
-        _ => false,
     };
-    if should_compute_pcrs {
-        let err = NOT_COMMITTED_REASON_COMPUTING;
-        return compute_fresh_pcrs(client, image).await.map(|_| err);
-    }
 
-    let image_pcr = ImagePcr {
+    let mut image_pcr = ImagePcr {
         first_seen: Timestamp::now(),
-        pcrs: label.unwrap().unwrap(),
+        pcrs: Vec::new(),
         reference: boot_image.to_string(),
+        not_after,
+        architectures: BTreeMap::new(),
+        origins: BTreeMap::new(),
+        profile_pcrs: BTreeMap::new(),
+        profiles: BTreeMap::new(),
+        kernel_cmdline_pcrs: Vec::new(),
+        kernel_command_lines: Vec::new(),
     };
-    image_pcrs.0.insert(resource_name.to_string(), image_pcr);
-    update_image_pcrs!(config_maps, image_pcrs_map, image_pcrs);
-    trustee::update_reference_values(client)
-        .await
-        .map(|_| COMMITTED_REASON)
+    // Labels cannot know the variables of platform profiles, nor whether the image boots as a UKI
+    // that measures its kernel command lines
+    let profiles = image.spec.platform_profiles.as_ref();
+    let cmdlines = image.spec.kernel_command_lines.as_ref();
+    let unlabeled =
+        profiles.is_some_and(|p| !p.is_empty()) || cmdlines.is_some_and(|c| !c.is_empty());
+    if unlabeled {
+        info!(
+            "Image {image_ref} has platform profiles or kernel command lines. \
+             Computing rather than using {PCR_LABEL}."
+        );
+    }
+    let node_architectures = node_architectures(client.clone()).await?;
+    let pending = add_labeled_pcrs(&mut image_pcr, labels, &node_architectures, unlabeled);
+    // Labeled architectures are trusted while the others are computed
+    store_image_pcr(client.clone(), resource_name, &image_pcr, owner_reference).await?;
+    if pending.is_empty() {
+        return Ok((COMMITTED_REASON, Some(image_pcr)));
+    }
+    let architectures: Vec<_> = pending.iter().map(|a| Some(a.as_str())).collect();
+    let config = spec.pcr_computation.as_ref();
+    let reason = launch_jobs(client, image, &architectures, config).await?;
+    Ok((reason, Some(image_pcr)))
+}
+
+/// PCRs of an image by architecture and platform as reported in the ApprovedImage status,
+/// without those of architectures that are still being computed
+fn pcrs_status(image_pcr: &ImagePcr) -> Result<Vec<ApprovedImageStatusPcrs>> {
+    let platforms = image_pcr.platform_pcrs().into_iter();
+    let known = platforms.filter(|(_, _, pcrs)| !pcrs.is_empty());
+    let to_status = |entry: (Option<&str>, Option<&str>, Vec<Pcr>)| -> Result<_> {
+        let (architecture, platform, pcrs) = entry;
+        let origin = architecture.and_then(|a| image_pcr.origins.get(a));
+        let source = origin.map(|o| match o.computed {
+            true => ApprovedImageStatusPcrsSource::Computed,
+            false => ApprovedImageStatusPcrsSource::Label,
+        });
+        let values = pcrs.iter().map(|pcr| -> Result<_> {
+            Ok(ApprovedImageStatusPcrsValues {
+                id: i32::try_from(pcr.id)?,
+                value: hex::encode(&pcr.value),
+            })
+        });
+        Ok(ApprovedImageStatusPcrs {
+            architecture: architecture.map(str::to_string),
+            platform: platform.map(str::to_string),
+            source,
+            os_id: origin.and_then(|o| o.os_id.clone()),
+            os_version_id: origin.and_then(|o| o.os_version_id.clone()),
+            computation_time: origin.map(|o| o.time.to_string()),
+            values: values.collect::<Result<_>>()?,
+        })
+    };
+    known.map(to_status).collect()
 }
 
 pub async fn disallow_image(client: Client, resource_name: &str) -> Result<()> {
-    let config_maps: Api<ConfigMap> = Api::default_namespaced(client.clone());
-    let mut image_pcrs_map = config_maps.get(PCR_CONFIG_MAP).await?;
-    let mut image_pcrs = get_image_pcrs(image_pcrs_map.clone())?;
-    if image_pcrs.0.remove(resource_name).is_none() {
+    if !remove_image_pcr(client, resource_name).await? {
         info!("Image {resource_name} was to be disallowed, but already was not allowed");
     }
-    update_image_pcrs!(config_maps, image_pcrs_map, image_pcrs);
-    trustee::update_reference_values(client).await
+    Ok(())
 }
 
 #[cfg(test)]
@@ -440,24 +1228,6 @@ mod tests {
     const DUMMY_IMAGE_REF: &str =
         "quay.io/some-ref@sha256:e71dad00aa0e3d70540e726a0c66407e3004d96e045ab6c253186e327a2419e5";
 
-    #[tokio::test]
-    async fn test_create_pcrs_cm_success() {
-        let clos = |client| create_pcrs_config_map(client);
-        test_create_success::<_, _, ConfigMap>(clos).await;
-    }
-
-    #[tokio::test]
-    async fn test_create_pcrs_cm_exists() {
-        let clos = |client| create_pcrs_config_map(client);
-        test_create_already_exists(clos).await;
-    }
-
-    #[tokio::test]
-    async fn test_create_pcrs_cm_error() {
-        let clos = |client| create_pcrs_config_map(client);
-        test_error_method!(clos, Method::POST);
-    }
-
     fn dummy_image() -> ApprovedImage {
         ApprovedImage {
             metadata: ObjectMeta {
@@ -467,6 +1237,11 @@ mod tests {
             },
             spec: ApprovedImageSpec {
                 image: DUMMY_IMAGE_REF.to_string(),
+                not_before: None,
+                not_after: None,
+                image_pull_secrets: None,
+                platform_profiles: None,
+                kernel_command_lines: None,
             },
             status: None,
         }
@@ -487,20 +1262,46 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_job_reconcile_success() {
-        let clos = async |req: Request<_>, ctr| match (ctr, req.method()) {
-            (0, &Method::DELETE) => Ok(serde_json::to_string(&Job::default()).unwrap()),
+    async fn test_migrate_image_pcrs() {
+        let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
+            (0, &Method::GET) => Ok(serde_json::to_string(&dummy_pcrs_map()).unwrap()),
             (1, &Method::GET) => {
-                assert!(req.uri().path().contains(PCR_CONFIG_MAP));
-                Ok(serde_json::to_string(&dummy_pcrs_map()).unwrap())
+                assert!(req.uri().path().ends_with("/cos"));
+                Ok(serde_json::to_string(&dummy_image()).unwrap())
             }
-            (2, &Method::GET) | (3, &Method::PUT) => {
-                assert!(req.uri().path().contains(trustee::TRUSTEE_DATA_MAP));
-                Ok(serde_json::to_string(&dummy_trustee_map()).unwrap())
+            (2, &Method::POST) => {
+                assert_body_contains(req, &image_pcr_map_name("cos")).await;
+                Ok(serde_json::to_string(&ConfigMap::default()).unwrap())
+            }
+            (3, &Method::DELETE) => {
+                assert!(req.uri().path().ends_with(PCR_CONFIG_MAP));
+                Ok(serde_json::to_string(&ConfigMap::default()).unwrap())
             }
             _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
         };
         count_check!(4, clos, |client| {
+            assert!(migrate_image_pcrs(client).await.is_ok());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_migrate_image_pcrs_done() {
+        let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
+            (0, &Method::GET) => Err(StatusCode::NOT_FOUND),
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(1, clos, |client| {
+            assert!(migrate_image_pcrs(client).await.is_ok());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_job_reconcile_success() {
+        let clos = async |req: Request<_>, ctr| match (ctr, req.method()) {
+            (0, &Method::DELETE) => Ok(serde_json::to_string(&Job::default()).unwrap()),
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(1, clos, |client| {
             let job = Arc::new(dummy_job());
             let result = job_reconcile(job, Arc::new(client)).await.unwrap();
             assert_eq!(result, Action::await_change());
@@ -519,35 +1320,391 @@ mod tests {
         });
     }
 
+    fn failed_job() -> Job {
+        let mut job = dummy_job();
+        job.metadata.owner_references =
+            Some(vec![generate_owner_reference(&dummy_image()).unwrap()]);
+        let status = job.status.as_mut().unwrap();
+        status.completion_time = None;
+        status.failed = Some(7);
+        job
+    }
+
+    #[test]
+    fn test_job_failed() {
+        assert!(!job_failed(&dummy_job()));
+        assert!(job_failed(&failed_job()));
+
+        let mut job = dummy_job();
+        let status = job.status.as_mut().unwrap();
+        status.conditions = Some(vec![JobCondition {
+            type_: "Failed".to_string(),
+            status: "True".to_string(),
+            reason: Some("DeadlineExceeded".to_string()),
+            ..Default::default()
+        }]);
+        assert!(job_failed(&job));
+    }
+
+    #[tokio::test]
+    async fn test_job_reconcile_failed() {
+        let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
+            (0, &Method::GET) => Ok(serde_json::to_string(&dummy_image()).unwrap()),
+            (1, &Method::GET) => {
+                assert!(req.uri().query().unwrap().contains("job-name"));
+                let pod = Pod {
+                    metadata: ObjectMeta {
+                        name: Some("test-pod".to_string()),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                let list = ObjectList {
+                    items: vec![pod],
+                    types: Default::default(),
+                    metadata: Default::default(),
+                };
+                Ok(serde_json::to_string(&list).unwrap())
+            }
+            (2, &Method::GET) => {
+                assert!(req.uri().path().ends_with("/test-pod/log"));
+                Ok("Error: /etc/os-release missed key: VERSION_ID\n".to_string())
+            }
+            (3, &Method::PATCH) => {
+                assert_body_contains(req, COMPUTE_FAILURES_ANNOTATION).await;
+                Ok(serde_json::to_string(&dummy_image()).unwrap())
+            }
+            (4, &Method::PATCH) => {
+                let body = get_body_string(req).await;
+                assert!(body.contains(NOT_COMMITTED_REASON_FAILED));
+                assert!(body.contains("missed key: VERSION_ID"));
+                Ok(serde_json::to_string(&dummy_image()).unwrap())
+            }
+            (5, &Method::DELETE) => Ok(serde_json::to_string(&Job::default()).unwrap()),
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(6, clos, |client| {
+            let job = Arc::new(failed_job());
+            let result = job_reconcile(job, Arc::new(client)).await.unwrap();
+            assert_eq!(result, Action::await_change());
+        });
+    }
+
+    #[test]
+    fn test_compute_retry_delay() {
+        let now = Timestamp::now();
+        let mut image = dummy_image();
+        assert!(compute_retry_delay(&image, now).is_none());
+
+        let failed = |failures: u32, failed_at: Timestamp| {
+            BTreeMap::from([
+                (
+                    COMPUTE_FAILURES_ANNOTATION.to_string(),
+                    failures.to_string(),
+                ),
+                (
+                    COMPUTE_FAILED_AT_ANNOTATION.to_string(),
+                    failed_at.to_string(),
+                ),
+            ])
+        };
+        image.metadata.annotations = Some(failed(1, now));
+        let delay = compute_retry_delay(&image, now);
+        assert_eq!(delay, Some(Duration::from_secs(60)));
+
+        // Doubles with every failure
+        image.metadata.annotations = Some(failed(3, now));
+        let delay = compute_retry_delay(&image, now);
+        assert_eq!(delay, Some(Duration::from_secs(240)));
+
+        image.metadata.annotations = Some(failed(20, now));
+        let delay = compute_retry_delay(&image, now);
+        assert_eq!(delay, Some(Duration::from_hours(1)));
+
+        let earlier = now - SignedDuration::from_mins(2);
+        image.metadata.annotations = Some(failed(1, earlier));
+        assert!(compute_retry_delay(&image, now).is_none());
+    }
+
     #[test]
     fn test_get_job_name_trailing_dash() {
-        let name = get_job_name("quay.io/some_ref:some-tag-").unwrap();
-        assert_eq!(name, "compute-pcrs-105a7802d8-quay-io-some-ref-some-tag");
+        let mut image = dummy_image();
+        image.spec.image = "quay.io/some_ref:some-tag-".to_string();
+        let name = get_job_name(&image, None).unwrap();
+        assert_eq!(name, "compute-pcrs-ec9133044c-quay-io-some-ref-some-tag");
     }
 
     #[test]
     fn test_get_job_name_sha() {
-        let name = get_job_name(DUMMY_IMAGE_REF).unwrap();
+        let name = get_job_name(&dummy_image(), None).unwrap();
         assert_eq!(
             name,
-            "compute-pcrs-6c57e93939-quay-io-some-ref-sha256-e71dad00aa0e3d7"
+            "compute-pcrs-999059b93f-quay-io-some-ref-sha256-e71dad00aa0e3d7"
         );
     }
 
+    #[test]
+    fn test_get_job_name_architecture() {
+        let name = get_job_name(&dummy_image(), Some("arm64")).unwrap();
+        assert!(name.starts_with("compute-pcrs-arm64-"));
+        assert!(name.len() <= 63);
+        let other = get_job_name(&dummy_image(), Some("s390x")).unwrap();
+        assert!(other.starts_with("compute-pcrs-s390x-"));
+        // Hashes differ as well
+        assert_ne!(name[19..29], other[19..29]);
+    }
+
+    #[test]
+    fn test_get_job_name_image_name() {
+        // Images of the same digest may differ in platform profiles and kernel command lines
+        let mut image = dummy_image();
+        image.metadata.name = Some("other".to_string());
+        let name = get_job_name(&dummy_image(), None).unwrap();
+        assert_ne!(name, get_job_name(&image, None).unwrap());
+        image.metadata.name = None;
+        assert!(get_job_name(&image, None).is_err());
+    }
+
     #[tokio::test]
     async fn test_compute_fresh_pcrs_success() {
         let image = dummy_image();
-        let clos = |client| compute_fresh_pcrs(client, &image);
+        let clos = |client| compute_fresh_pcrs(client, &image, None, None);
         test_create_success::<_, _, Job>(clos).await;
     }
 
+    #[tokio::test]
+    async fn test_compute_fresh_pcrs_architecture() {
+        let image = dummy_image();
+        let clos = async |req: Request<Body>, _| {
+            assert_body_contains(req, r#""nodeSelector":{"kubernetes.io/arch":"arm64"}"#).await;
+            Ok(serde_json::to_string(&Job::default()).unwrap())
+        };
+        count_check!(1, clos, |client| {
+            let result = compute_fresh_pcrs(client, &image, Some("arm64"), None).await;
+            assert!(result.is_ok());
+        });
+    }
+
     #[tokio::test]
     async fn test_compute_fresh_pcrs_error() {
         let image = dummy_image();
-        let clos = |client| compute_fresh_pcrs(client, &image);
+        let clos = |client| compute_fresh_pcrs(client, &image, None, None);
         test_error_method!(clos, Method::POST);
     }
 
+    #[tokio::test]
+    async fn test_compute_fresh_pcrs_image_volume_rejected() {
+        let image = dummy_image();
+        let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
+            (0, &Method::POST) => Err(StatusCode::UNPROCESSABLE_ENTITY),
+            (1, &Method::POST) => {
+                let body = get_body_string(req).await;
+                let job: Job = serde_json::from_str(&body).unwrap();
+                let pod_spec = job.spec.unwrap().template.spec.unwrap();
+                let volume = &pod_spec.volumes.unwrap()[0];
+                assert!(volume.image.is_none() && volume.empty_dir.is_some());
+                let command = pod_spec.containers[0].command.clone().unwrap();
+                assert!(command.contains(&"--pull".to_string()));
+                Ok(body)
+            }
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        // Reset in case another test cleared it
+        IMAGE_VOLUMES_SUPPORTED.store(true, Ordering::Relaxed);
+        count_check!(2, clos, |client| {
+            let result = compute_fresh_pcrs(client, &image, None, None).await;
+            assert!(result.is_ok());
+            assert!(!IMAGE_VOLUMES_SUPPORTED.load(Ordering::Relaxed));
+        });
+    }
+
+    #[test]
+    fn test_build_compute_pcrs_pod_spec_image_volume() {
+        let pod_spec = build_compute_pcrs_pod_spec("image", DUMMY_IMAGE_REF, "compute", true);
+        let volume = &pod_spec.volumes.unwrap()[0];
+        let reference = volume.image.as_ref().and_then(|i| i.reference.as_deref());
+        assert_eq!(reference, Some(DUMMY_IMAGE_REF));
+        let command = pod_spec.containers[0].command.clone().unwrap();
+        assert!(!command.contains(&"--pull".to_string()));
+    }
+
+    #[test]
+    fn test_build_compute_pcrs_job_profiles() {
+        let image = dummy_image();
+        let profiles = [dummy_profile("azure"), dummy_profile("qemu")];
+        let job = build_compute_pcrs_job(&image, &profiles, None, None, true).unwrap();
+        let pod_spec = job.spec.unwrap().template.spec.unwrap();
+        let volumes = pod_spec.volumes.unwrap();
+        let mounts = pod_spec.containers[0].volume_mounts.clone().unwrap();
+        // The image and the EFI variables of both profiles
+        assert_eq!(volumes.len(), 3);
+        let config_map = volumes[1].config_map.as_ref().map(|c| c.name.as_str());
+        assert_eq!(config_map, Some("azure-efivars"));
+        assert_eq!(mounts[1].name, volumes[1].name);
+        assert_eq!(
+            mounts[1].mount_path,
+            format!("{EFI_VARIABLES_MOUNTPOINT}/azure")
+        );
+        assert_eq!(
+            mounts[2].mount_path,
+            format!("{EFI_VARIABLES_MOUNTPOINT}/qemu")
+        );
+    }
+
+    fn dummy_pcr_computation() -> TrustedExecutionClusterPcrComputation {
+        TrustedExecutionClusterPcrComputation {
+            max_concurrent_jobs: Some(1),
+            resources: serde_json::from_value(json!({"limits": {"memory": "1Gi"}})).unwrap(),
+            active_deadline_seconds: Some(600),
+            ttl_seconds_after_finished: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compute_fresh_pcrs_config() {
+        let image = dummy_image();
+        let clos = async |req: Request<Body>, _| {
+            let body = get_body_string(req).await;
+            let job: Job = serde_json::from_str(&body).unwrap();
+            let spec = job.spec.unwrap();
+            assert_eq!(spec.active_deadline_seconds, Some(600));
+            assert_eq!(
+                spec.ttl_seconds_after_finished,
+                Some(DEFAULT_JOB_TTL_SECONDS)
+            );
+            let pod_spec = spec.template.spec.unwrap();
+            let resources = pod_spec.containers[0].resources.clone().unwrap();
+            assert!(resources.limits.unwrap().contains_key("memory"));
+            Ok(body)
+        };
+        count_check!(1, clos, |client| {
+            let config = dummy_pcr_computation();
+            let result = compute_fresh_pcrs(client, &image, None, Some(&config)).await;
+            assert!(result.is_ok());
+        });
+    }
+
+    fn queued_image(name: &str, created: Timestamp) -> ApprovedImage {
+        let mut image = dummy_image();
+        image.metadata.name = Some(name.to_string());
+        image.metadata.creation_timestamp = Some(Time(created));
+        let queued = committed_condition(NOT_COMMITTED_REASON_QUEUED, None, &None);
+        image.status = Some(ApprovedImageStatus {
+            conditions: Some(vec![queued]),
+            pcrs: None,
+        });
+        image
+    }
+
+    #[test]
+    fn test_free_job_slots() {
+        let now = Timestamp::now();
+        let mut active = dummy_job();
+        active.status.as_mut().unwrap().completion_time = None;
+        let jobs = vec![dummy_job(), active.clone(), failed_job()];
+        let image = queued_image("test", now);
+        // Only the active job takes a slot
+        assert_eq!(free_job_slots(&jobs, &[], &image, 3), 2);
+        assert_eq!(free_job_slots(&[active.clone(), active], &[], &image, 1), 0);
+
+        let earlier = queued_image("earlier", now - SignedDuration::from_mins(1));
+        let later = queued_image("later", now + SignedDuration::from_mins(1));
+        let images = [earlier, image.clone(), later];
+        assert_eq!(free_job_slots(&jobs, &images, &image, 3), 1);
+    }
+
+    #[tokio::test]
+    async fn test_launch_jobs_queued() {
+        let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
+            (0, &Method::GET) => {
+                let mut job = dummy_job();
+                job.metadata.name = Some("other".to_string());
+                job.status.as_mut().unwrap().completion_time = None;
+                let list = ObjectList {
+                    items: vec![job],
+                    types: Default::default(),
+                    metadata: Default::default(),
+                };
+                Ok(serde_json::to_string(&list).unwrap())
+            }
+            (1, &Method::GET) => {
+                let list = ObjectList {
+                    items: vec![dummy_image()],
+                    types: Default::default(),
+                    metadata: Default::default(),
+                };
+                Ok(serde_json::to_string(&list).unwrap())
+            }
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(2, clos, |client| {
+            let config = dummy_pcr_computation();
+            let architectures = [Some("amd64")];
+            let reason = launch_jobs(client, &dummy_image(), &architectures, Some(&config)).await;
+            assert_eq!(reason.unwrap(), NOT_COMMITTED_REASON_QUEUED);
+        });
+    }
+
+    #[tokio::test]
+    async fn test_launch_jobs_concurrent_images() {
+        let images: Vec<_> = ["first", "second"]
+            .map(|name| {
+                let mut image = dummy_image();
+                image.metadata.name = Some(name.to_string());
+                image.spec.image = format!("quay.io/{name}@sha256:{}", "0".repeat(64));
+                image
+            })
+            .to_vec();
+        let jobs = Arc::new(std::sync::Mutex::new(Vec::<Job>::new()));
+        let (server_jobs, server_images) = (jobs.clone(), images.clone());
+        let clos = move |req: Request<Body>, ctr| {
+            let (jobs, images) = (server_jobs.clone(), server_images.clone());
+            async move {
+                let path = req.uri().path().to_string();
+                match req.method() {
+                    &Method::GET if path.ends_with("/jobs") => {
+                        let list = ObjectList {
+                            items: jobs.lock().unwrap().clone(),
+                            types: Default::default(),
+                            metadata: Default::default(),
+                        };
+                        Ok(serde_json::to_string(&list).unwrap())
+                    }
+                    &Method::GET if path.ends_with("/approvedimages") => {
+                        let list = ObjectList {
+                            items: images,
+                            types: Default::default(),
+                            metadata: Default::default(),
+                        };
+                        Ok(serde_json::to_string(&list).unwrap())
+                    }
+                    &Method::POST if path.ends_with("/jobs") => {
+                        let body = get_body_string(req).await;
+                        jobs.lock()
+                            .unwrap()
+                            .push(serde_json::from_str(&body).unwrap());
+                        Ok(body)
+                    }
+                    _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+                }
+            }
+        };
+        // Both list Jobs and images, but only the first creates a Job
+        count_check!(5, clos, |client| {
+            let config = dummy_pcr_computation();
+            let (first, second) = tokio::join!(
+                launch_jobs(client.clone(), &images[0], &[None], Some(&config)),
+                launch_jobs(client, &images[1], &[None], Some(&config)),
+            );
+            let mut reasons = [first.unwrap(), second.unwrap()];
+            reasons.sort();
+            let mut expected = [NOT_COMMITTED_REASON_COMPUTING, NOT_COMMITTED_REASON_QUEUED];
+            expected.sort();
+            assert_eq!(reasons, expected);
+        });
+        assert_eq!(jobs.lock().unwrap().len(), 1);
+    }
+
     #[tokio::test]
     async fn test_adopt_approved_image() {
         let cluster = dummy_cluster();
@@ -603,22 +1760,394 @@ mod tests {
 
     #[tokio::test]
     async fn test_image_remove_reconcile() {
-        let image = Arc::new(dummy_image());
+        let mut image = dummy_image();
+        // allowed in the dummy image PCRs
+        image.metadata.name = Some("cos".to_string());
+        let image = Arc::new(image);
         let cluster = Some(dummy_cluster());
         let clos = async |req: Request<_>, ctr| match (ctr, req.method()) {
-            // fetched & updated for removal, then fetched for recomputation
-            (0, &Method::GET) | (1, &Method::PUT) | (2, &Method::GET) => {
-                assert!(req.uri().path().contains(PCR_CONFIG_MAP));
-                Ok(serde_json::to_string(&dummy_pcrs_map()).unwrap())
-            }
-            (3, &Method::GET) | (4, &Method::PUT) => {
-                assert!(req.uri().path().contains(trustee::TRUSTEE_DATA_MAP));
-                Ok(serde_json::to_string(&dummy_trustee_map()).unwrap())
+            // recomputation follows from the deletion
+            (0, &Method::DELETE) => {
+                assert!(req.uri().path().ends_with(&image_pcr_map_name("cos")));
+                Ok(serde_json::to_string(&ConfigMap::default()).unwrap())
             }
             _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
         };
-        count_check!(5, clos, |client| {
+        count_check!(1, clos, |client| {
             assert!(image_remove_reconcile(client, image, cluster).await.is_ok());
         });
     }
+
+    fn dummy_nodes(architectures: &[&str]) -> ObjectList<Node> {
+        let node = |architecture: &&str| Node {
+            metadata: ObjectMeta {
+                labels: Some(BTreeMap::from([(
+                    NODE_ARCH_LABEL.to_string(),
+                    architecture.to_string(),
+                )])),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        ObjectList {
+            items: architectures.iter().map(node).collect(),
+            types: Default::default(),
+            metadata: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_new_image_architecture_without_nodes() {
+        let pcr = Pcr {
+            id: 4,
+            value: vec![4],
+            events: Vec::new(),
+        };
+        let image_pcr = ImagePcr {
+            first_seen: Timestamp::now(),
+            pcrs: Vec::new(),
+            reference: DUMMY_IMAGE_REF.to_string(),
+            not_after: None,
+            architectures: BTreeMap::from([
+                ("amd64".to_string(), vec![pcr]),
+                ("s390x".to_string(), Vec::new()),
+            ]),
+            origins: BTreeMap::new(),
+            profile_pcrs: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            kernel_cmdline_pcrs: Vec::new(),
+            kernel_command_lines: Vec::new(),
+        };
+        let image_pcr_map = image_pcr_map("test", &image_pcr, Default::default()).unwrap();
+        let image_pcr_map = serde_json::to_string(&image_pcr_map).unwrap();
+        let clos = move |req: Request<Body>, ctr| {
+            let image_pcr_map = image_pcr_map.clone();
+            async move {
+                match (ctr, req.method()) {
+                    (0, &Method::GET) => Ok(image_pcr_map),
+                    (1, &Method::GET) => {
+                        assert!(req.uri().path().ends_with("/nodes"));
+                        Ok(serde_json::to_string(&dummy_nodes(&["amd64"])).unwrap())
+                    }
+                    // No job for s390x, which no node could run
+                    _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+                }
+            }
+        };
+        count_check!(2, clos, |client| {
+            let spec = dummy_cluster().spec;
+            let result = handle_new_image(client, &dummy_image(), &spec).await;
+            let (reason, _) = result.unwrap();
+            assert_eq!(reason, COMMITTED_REASON);
+        });
+    }
+
+    #[test]
+    fn test_add_labeled_pcrs() {
+        let pcr = Pcr {
+            id: 4,
+            value: vec![4],
+            events: Vec::new(),
+        };
+        let labels = BTreeMap::from([
+            ("amd64".to_string(), None),
+            ("arm64".to_string(), Some(vec![pcr])),
+            ("s390x".to_string(), None),
+        ]);
+        let nodes = BTreeSet::from(["amd64".to_string()]);
+        let mut image_pcr = ImagePcr {
+            first_seen: Timestamp::now(),
+            pcrs: Vec::new(),
+            reference: DUMMY_IMAGE_REF.to_string(),
+            not_after: None,
+            architectures: BTreeMap::new(),
+            origins: BTreeMap::new(),
+            profile_pcrs: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            kernel_cmdline_pcrs: Vec::new(),
+            kernel_command_lines: Vec::new(),
+        };
+        let empty = image_pcr.clone();
+        let pending = add_labeled_pcrs(&mut image_pcr, labels.clone(), &nodes, false);
+        assert_eq!(pending, vec!["amd64"]);
+        // Labels of architectures without nodes are stored, too
+        assert_eq!(image_pcr.architectures["arm64"].len(), 1);
+        assert_eq!(image_pcr.pending_architectures(), vec!["amd64", "s390x"]);
+
+        let mut image_pcr = empty;
+        let pending = add_labeled_pcrs(&mut image_pcr, labels, &nodes, true);
+        assert_eq!(pending, vec!["amd64"]);
+        assert_eq!(image_pcr.pending_architectures().len(), 3);
+    }
+
+    #[test]
+    fn test_node_architecture_changed() {
+        let mut node = dummy_nodes(&["arm64"]).items.remove(0);
+        node.metadata.uid = Some("uid".to_string());
+        let mut architectures = BTreeMap::new();
+        assert!(node_architecture_changed(&node, &mut architectures));
+        assert!(!node_architecture_changed(&node, &mut architectures));
+        let labels = node.metadata.labels.as_mut().unwrap();
+        labels.insert(NODE_ARCH_LABEL.to_string(), "amd64".to_string());
+        assert!(node_architecture_changed(&node, &mut architectures));
+        node.metadata.uid = None;
+        assert!(!node_architecture_changed(&node, &mut architectures));
+    }
+
+    #[tokio::test]
+    async fn test_handle_new_image_cmdline_unmeasured() {
+        let pcr = Pcr {
+            id: 4,
+            value: vec![4],
+            events: Vec::new(),
+        };
+        let cmdlines = vec!["rw".to_string()];
+        let image_pcr = ImagePcr {
+            first_seen: Timestamp::now(),
+            pcrs: Vec::new(),
+            reference: DUMMY_IMAGE_REF.to_string(),
+            not_after: None,
+            architectures: BTreeMap::from([("amd64".to_string(), vec![pcr])]),
+            origins: BTreeMap::new(),
+            profile_pcrs: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            // Not a UKI
+            kernel_cmdline_pcrs: Vec::new(),
+            kernel_command_lines: cmdlines.clone(),
+        };
+        let image_pcr_map = image_pcr_map("test", &image_pcr, Default::default()).unwrap();
+        let image_pcr_map = serde_json::to_string(&image_pcr_map).unwrap();
+        let clos = move |req: Request<Body>, ctr| {
+            let image_pcr_map = image_pcr_map.clone();
+            async move {
+                match (ctr, req.method()) {
+                    (0, &Method::GET) => Ok(image_pcr_map),
+                    (1, &Method::GET) => {
+                        Ok(serde_json::to_string(&dummy_nodes(&["amd64"])).unwrap())
+                    }
+                    // Not computed again while the command lines are unchanged
+                    _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+                }
+            }
+        };
+        let mut image = dummy_image();
+        image.spec.kernel_command_lines = Some(cmdlines);
+        count_check!(2, clos, |client| {
+            let spec = dummy_cluster().spec;
+            let result = handle_new_image(client, &image, &spec).await;
+            let (reason, _) = result.unwrap();
+            assert_eq!(reason, NOT_COMMITTED_REASON_CMDLINE_UNMEASURED);
+        });
+    }
+
+    fn profiled_image() -> ApprovedImage {
+        let mut image = dummy_image();
+        image.spec.platform_profiles = Some(vec!["azure".to_string()]);
+        image
+    }
+
+    #[tokio::test]
+    async fn test_handle_new_image_profile_missing() {
+        let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
+            (0, &Method::GET) => {
+                assert!(req.uri().path().ends_with("/platformprofiles/azure"));
+                Err(StatusCode::NOT_FOUND)
+            }
+            (1, &Method::GET) => Err(StatusCode::NOT_FOUND),
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(2, clos, |client| {
+            let spec = dummy_cluster().spec;
+            let result = handle_new_image(client, &profiled_image(), &spec).await;
+            let (reason, stored) = result.unwrap();
+            assert_eq!(reason, NOT_COMMITTED_REASON_PROFILE_MISSING);
+            assert!(stored.is_none());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_handle_new_image_profile_variables_changed() {
+        let pcr = Pcr {
+            id: 4,
+            value: vec![4],
+            events: Vec::new(),
+        };
+        let profile = dummy_profile("azure");
+        let record = ProfileRecord::new(&profile, "stale".to_string());
+        let image_pcr = ImagePcr {
+            first_seen: Timestamp::now(),
+            pcrs: Vec::new(),
+            reference: DUMMY_IMAGE_REF.to_string(),
+            not_after: None,
+            architectures: BTreeMap::from([("amd64".to_string(), vec![pcr])]),
+            origins: BTreeMap::new(),
+            profile_pcrs: BTreeMap::new(),
+            profiles: BTreeMap::from([("azure".to_string(), record)]),
+            kernel_cmdline_pcrs: Vec::new(),
+            kernel_command_lines: Vec::new(),
+        };
+        let image_pcr_map = image_pcr_map("test", &image_pcr, Default::default()).unwrap();
+        let image_pcr_map = serde_json::to_string(&image_pcr_map).unwrap();
+        let job = Job {
+            metadata: ObjectMeta {
+                name: Some(get_job_name(&dummy_image(), Some("amd64")).unwrap()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let clos = move |req: Request<Body>, ctr| {
+            let (image_pcr_map, profile, job) =
+                (image_pcr_map.clone(), profile.clone(), job.clone());
+            async move {
+                let path = req.uri().path();
+                match (ctr, req.method()) {
+                    (0, &Method::GET) => Ok(serde_json::to_string(&profile).unwrap()),
+                    (1, &Method::GET) => Ok(image_pcr_map),
+                    (2, &Method::GET) => {
+                        Ok(serde_json::to_string(&dummy_nodes(&["amd64"])).unwrap())
+                    }
+                    (3, &Method::GET) => {
+                        assert!(path.ends_with("/configmaps/azure-efivars"));
+                        Ok(serde_json::to_string(&ConfigMap::default()).unwrap())
+                    }
+                    (4, &Method::GET) => {
+                        assert!(path.ends_with("/pods"));
+                        let pods = ObjectList::<Pod> {
+                            items: Vec::new(),
+                            types: Default::default(),
+                            metadata: Default::default(),
+                        };
+                        Ok(serde_json::to_string(&pods).unwrap())
+                    }
+                    // The Job of the new computation exists already
+                    (5, &Method::GET) => {
+                        assert!(path.ends_with("/jobs"));
+                        let jobs = ObjectList {
+                            items: vec![job],
+                            types: Default::default(),
+                            metadata: Default::default(),
+                        };
+                        Ok(serde_json::to_string(&jobs).unwrap())
+                    }
+                    _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+                }
+            }
+        };
+        count_check!(6, clos, |client| {
+            let spec = dummy_cluster().spec;
+            let result = handle_new_image(client, &profiled_image(), &spec).await;
+            let (reason, _) = result.unwrap();
+            assert_eq!(reason, NOT_COMMITTED_REASON_COMPUTING);
+        });
+    }
+
+    #[test]
+    fn test_images_referencing() {
+        let other = dummy_image();
+        let mut image = profiled_image();
+        image.metadata.name = Some("profiled".to_string());
+        let images = vec![Arc::new(other), Arc::new(image)];
+        let refs = images_referencing(images.clone(), &BTreeSet::from(["azure"]));
+        let names: Vec<_> = refs.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["profiled"]);
+        assert!(images_referencing(images, &BTreeSet::from(["qemu"])).is_empty());
+    }
+
+    #[test]
+    fn test_profiles_with_variables() {
+        let mut qemu = dummy_profile("qemu");
+        qemu.spec.efi_variables_config_map_name = None;
+        qemu.spec.mok_variables_config_map_name = Some("azure-efivars".to_string());
+        let profiles = [dummy_profile("azure"), qemu, dummy_profile("gcp")].map(Arc::new);
+        let names = profiles_with_variables(&profiles, "azure-efivars");
+        assert_eq!(names, BTreeSet::from(["azure", "qemu"]));
+        assert!(profiles_with_variables(&profiles, "image-pcrs-test").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_image_add_reconcile_expired() {
+        let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
+            // disallowed like on removal
+            (0, &Method::DELETE) => Ok(serde_json::to_string(&ConfigMap::default()).unwrap()),
+            (1, &Method::POST) => {
+                assert!(req.uri().path().contains("events"));
+                Ok(serde_json::to_string(&k8s_openapi::api::events::v1::Event::default()).unwrap())
+            }
+            (2, &Method::PATCH) => {
+                let body = get_body_string(req).await;
+                assert!(body.contains(NOT_COMMITTED_REASON_EXPIRED));
+                assert!(body.contains(EXPIRING_REASON));
+                Ok(serde_json::to_string(&dummy_image()).unwrap())
+            }
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(3, clos, |client| {
+            let mut image = dummy_image();
+            image.metadata.name = Some("cos".to_string());
+            image.spec.not_after = Some("2020-01-01T00:00:00Z".to_string());
+            let result = image_add_reconcile(client, &image, Some(dummy_cluster())).await;
+            assert_eq!(result.unwrap(), LONG_REQUEUE);
+        });
+    }
+
+    #[test]
+    fn test_validity_action() {
+        let now = Timestamp::now();
+        let soon = Some(now + SignedDuration::from_mins(10));
+        let action = validity_action(LONG_REQUEUE, now, soon, None);
+        assert_eq!(action, Action::requeue(Duration::from_secs(601)));
+
+        let later = Some(now + SignedDuration::from_hours(24 * 30));
+        assert_eq!(
+            validity_action(LONG_REQUEUE, now, None, later),
+            LONG_REQUEUE
+        );
+
+        let short = Action::requeue(Duration::from_secs(5));
+        assert_eq!(validity_action(short.clone(), now, soon, None), short);
+    }
+
+    #[test]
+    fn test_pcrs_status() {
+        let mut image_pcr = dummy_pcrs().0.remove("cos").unwrap();
+        let pcrs = image_pcr.pcrs.clone();
+        image_pcr.architectures = BTreeMap::from([
+            ("amd64".to_string(), pcrs.clone()),
+            ("arm64".to_string(), pcrs),
+            ("s390x".to_string(), Vec::new()),
+        ]);
+        let computed = PcrOrigin {
+            computed: true,
+            os_id: Some("fedora".to_string()),
+            os_version_id: Some("42".to_string()),
+            time: Timestamp::now(),
+        };
+        image_pcr.origins = BTreeMap::from([
+            ("amd64".to_string(), PcrOrigin::label()),
+            ("arm64".to_string(), computed),
+        ]);
+        let status = pcrs_status(&image_pcr).unwrap();
+        // s390x is still being computed
+        assert_eq!(status.len(), 3);
+
+        // Unknown architecture of earlier versions
+        assert!(status[0].architecture.is_none());
+        assert!(status[0].source.is_none());
+        assert_eq!(status[0].values[0].id, 4);
+        assert_eq!(status[0].values[0].value, DUMMY_PCR_4_VALUE);
+
+        assert_eq!(status[1].architecture.as_deref(), Some("amd64"));
+        let label = &status[1].source;
+        assert!(matches!(label, Some(ApprovedImageStatusPcrsSource::Label)));
+        assert!(status[1].os_id.is_none());
+
+        let computed = &status[2].source;
+        assert!(matches!(
+            computed,
+            Some(ApprovedImageStatusPcrsSource::Computed)
+        ));
+        assert_eq!(status[2].os_id.as_deref(), Some("fedora"));
+        assert_eq!(status[2].os_version_id.as_deref(), Some("42"));
+        assert_eq!(status[2].values[1].value, DUMMY_PCR_7_VALUE);
+    }
 }
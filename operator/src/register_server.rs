@@ -17,6 +17,8 @@ use kube::runtime::{
     controller::{Action, Controller},
     finalizer,
     finalizer::Event,
+    reflector::ObjectRef,
+    watcher,
 };
 use kube::{Api, Client, Resource};
 use log::info;
@@ -24,7 +26,9 @@ use std::{collections::BTreeMap, sync::Arc};
 
 use crate::trustee;
 use operator::*;
-use trusted_cluster_operator_lib::{Machine, TrustedExecutionCluster, endpoints::*};
+use trusted_cluster_operator_lib::{
+    Machine, TrustedExecutionCluster, endpoints::*, get_trusted_execution_cluster,
+};
 
 /// Finalizer name to discard decryption keys when a machine is deleted
 const MACHINE_FINALIZER: &str = "finalizer.machine.trusted-execution-clusters.io";
@@ -139,7 +143,8 @@ async fn keygen_reconcile(
                 async {
                     let owner_reference = generate_owner_reference(&Arc::unwrap_or_clone(machine))?;
                     trustee::generate_secret(kube_client.clone(), id, owner_reference).await?;
-                    trustee::mount_secret(kube_client, id).await
+                    let cluster = get_trusted_execution_cluster(kube_client.clone()).await?;
+                    trustee::deliver_secret(kube_client, &cluster, id).await
                 }
                 .await
                 .map(|_| LONG_REQUEUE)
@@ -150,7 +155,8 @@ async fn keygen_reconcile(
                 let id = &machine.spec.id;
 
                 // Check if the TrustedExecutionCluster is being deleted
-                // If so, skip unmounting the secret as everything will be cleaned up
+                // If so, skip withdrawing the secret as everything will be cleaned up
+                let mut owner_cluster = None;
                 if let Some(owner_refs) = &machine.metadata.owner_references
                     && let Some(tec_owner) = owner_refs
                         .iter()
@@ -162,33 +168,40 @@ async fn keygen_reconcile(
 
                     match tecs.get(tec_name).await {
                         Ok(tec) if tec.metadata.deletion_timestamp.is_some() => {
-                            // TEC is being deleted, skip unmount_secret
+                            // TEC is being deleted, skip withdraw_secret
                             info!(
                                 "TrustedExecutionCluster {tec_name} is being deleted, \
-                                     skipping unmount_secret for Machine {}",
+                                     skipping withdraw_secret for Machine {}",
                                 machine.metadata.name.as_deref().unwrap_or("unknown")
                             );
                             return Ok(LONG_REQUEUE);
                         }
                         Err(kube::Error::Api(ae)) if ae.code == 404 => {
-                            // TEC already deleted, skip unmount_secret
+                            // TEC already deleted, skip withdraw_secret
                             info!(
                                 "TrustedExecutionCluster {tec_name} not found, \
-                                     skipping unmount_secret for Machine {}",
+                                     skipping withdraw_secret for Machine {}",
                                 machine.metadata.name.as_deref().unwrap_or("unknown")
                             );
                             return Ok(LONG_REQUEUE);
                         }
+                        Ok(tec) => owner_cluster = Some(tec),
                         _ => {
-                            // TEC exists and is not being deleted, proceed with unmount_secret
+                            // Proceed with withdraw_secret, looking up the TEC again below
                         }
                     }
                 }
 
-                trustee::unmount_secret(kube_client, id)
-                    .await
-                    .map(|_| LONG_REQUEUE)
-                    .map_err(|e| finalizer::Error::<ControllerError>::CleanupFailed(e.into()))
+                async {
+                    let cluster = match owner_cluster {
+                        Some(cluster) => cluster,
+                        None => get_trusted_execution_cluster(kube_client.clone()).await?,
+                    };
+                    trustee::withdraw_secret(kube_client, &cluster, id).await
+                }
+                .await
+                .map(|_| LONG_REQUEUE)
+                .map_err(|e| finalizer::Error::<ControllerError>::CleanupFailed(e.into()))
             }
         }
     })
@@ -198,8 +211,23 @@ async fn keygen_reconcile(
 
 pub async fn launch_keygen_controller(client: Client) {
     let machines: Api<Machine> = Api::default_namespaced(client.clone());
+    let deployments: Api<Deployment> = Api::default_namespaced(client.clone());
+    let controller = Controller::new(machines, Default::default());
+    let store = controller.store();
+    // Secrets pushed through the KBS resource API do not survive a restart of Trustee. Redeliver
+    // all machines' secrets when the Trustee deployment changes.
+    let trustee_filter = format!("metadata.name={TRUSTEE_DEPLOYMENT}");
+    let trustee_config = watcher::Config::default().fields(&trustee_filter);
+    let all_machines = move |_: Deployment| {
+        let machines = store.state();
+        machines
+            .iter()
+            .map(|m| ObjectRef::from_obj(&**m))
+            .collect::<Vec<_>>()
+    };
     tokio::spawn(
-        Controller::new(machines, Default::default())
+        controller
+            .watches(deployments, trustee_config, all_machines)
             .run(keygen_reconcile, controller_error_policy, Arc::new(client))
             .for_each(controller_info),
     );
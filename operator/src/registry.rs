@@ -0,0 +1,297 @@
+// SPDX-FileCopyrightText: Jakob Naucke <jnaucke@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+// Access to the registries of ApprovedImages. Credentials are taken from the image pull secrets
+// that an ApprovedImage references, in the kubernetes.io/dockerconfigjson format. CAs, mirrors
+// and proxies are configured for all images on the TrustedExecutionCluster.
+
+use anyhow::{Context, Result};
+use k8s_openapi::api::core::v1::{ConfigMap, LocalObjectReference};
+use kube::{Api, Client};
+use log::warn;
+use oci_client::client::{Certificate, CertificateEncoding, ClientConfig};
+use oci_client::{Reference, secrets::RegistryAuth};
+use openssl::x509::X509;
+
+use trusted_cluster_operator_lib::registry_auth::{
+    image_pull_credentials, image_pull_secret_names,
+};
+use trusted_cluster_operator_lib::{ApprovedImage, TrustedExecutionClusterRegistry};
+
+const CA_BUNDLE_KEY: &str = "ca-bundle.crt";
+
+/// Registry credentials for an ApprovedImage from its image pull secrets, anonymous if none
+/// apply
+pub async fn registry_auth(
+    client: Client,
+    image: &ApprovedImage,
+    image_ref: &Reference,
+) -> Result<RegistryAuth> {
+    let repository = format!("{}/{}", image_ref.registry(), image_ref.repository());
+    let credentials = image_pull_credentials(client, image, &repository).await?;
+    let auth = credentials.map(|(username, password)| RegistryAuth::Basic(username, password));
+    Ok(auth.unwrap_or(RegistryAuth::Anonymous))
+}
+
+/// Registry client with the CAs and proxies of the registry configuration
+pub async fn oci_client(
+    client: Client,
+    config: Option<&TrustedExecutionClusterRegistry>,
+) -> Result<oci_client::Client> {
+    let Some(config) = config else {
+        return Ok(oci_client::Client::new(Default::default()));
+    };
+    let mut extra_root_certificates = Vec::new();
+    if let Some(name) = &config.ca_bundle_config_map {
+        let config_maps: Api<ConfigMap> = Api::default_namespaced(client);
+        let config_map = config_maps.get(name).await?;
+        let err = format!("ConfigMap {name} does not contain {CA_BUNDLE_KEY}");
+        let bundle = config_map.data.as_ref().and_then(|d| d.get(CA_BUNDLE_KEY));
+        let bundle = bundle.context(err)?;
+        // A certificate of the client config is a single certificate, not a bundle
+        for cert in X509::stack_from_pem(bundle.as_bytes())? {
+            extra_root_certificates.push(Certificate {
+                encoding: CertificateEncoding::Pem,
+                data: cert.to_pem()?,
+            });
+        }
+    }
+    Ok(oci_client::Client::new(ClientConfig {
+        extra_root_certificates,
+        https_proxy: config.https_proxy.clone(),
+        http_proxy: config.http_proxy.clone(),
+        no_proxy: config.no_proxy.clone(),
+        ..Default::default()
+    }))
+}
+
+/// References to read an image from: its mirrors in order of preference, then the image itself.
+/// Mirrors are only used for references by digest, which guarantees the same content.
+pub fn mirrored_references(
+    image_ref: &Reference,
+    config: Option<&TrustedExecutionClusterRegistry>,
+) -> Vec<Reference> {
+    let mut references = Vec::new();
+    let mirrors = config
+        .and_then(|c| c.mirrors.as_ref())
+        .into_iter()
+        .flatten();
+    if let Some(digest) = image_ref.digest() {
+        let image = format!("{}/{}", image_ref.registry(), image_ref.repository());
+        for mirror in mirrors {
+            let source = mirror.source.trim_end_matches('/');
+            let Some(path) = image.strip_prefix(source) else {
+                continue;
+            };
+            if !path.is_empty() && !path.starts_with('/') {
+                continue;
+            }
+            for target in &mirror.mirrors {
+                let mirrored = format!("{}{path}", target.trim_end_matches('/'));
+                let Some((registry, repository)) = mirrored.split_once('/') else {
+                    continue;
+                };
+                let reference = Reference::with_digest(
+                    registry.to_string(),
+                    repository.to_string(),
+                    digest.to_string(),
+                );
+                references.push(reference);
+            }
+        }
+    }
+    references.push(image_ref.clone());
+    references
+}
+
+/// Read from an image with the registry configuration, from the first of its mirrors that serves
+/// it or the image itself
+pub async fn read_image<T>(
+    client: Client,
+    image: &ApprovedImage,
+    image_ref: &Reference,
+    config: Option<&TrustedExecutionClusterRegistry>,
+    read: impl AsyncFn(&oci_client::Client, &Reference, &RegistryAuth) -> Result<T>,
+) -> Result<T> {
+    let oci_client = oci_client(client.clone(), config).await?;
+    let mut references = mirrored_references(image_ref, config)
+        .into_iter()
+        .peekable();
+    loop {
+        let reference = references.next().context("Image had no references")?;
+        let auth = registry_auth(client.clone(), image, &reference).await?;
+        match read(&oci_client, &reference, &auth).await {
+            Err(e) if references.peek().is_some() => warn!("Reading {reference} failed: {e}"),
+            result => return result,
+        }
+    }
+}
+
+/// Image pull secrets of an ApprovedImage for pods that use the image
+pub fn image_pull_secrets(image: &ApprovedImage) -> Option<Vec<LocalObjectReference>> {
+    let secrets = image_pull_secret_names(image);
+    let secrets = secrets
+        .into_iter()
+        .map(|name| LocalObjectReference { name });
+    Some(secrets.collect::<Vec<_>>()).filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{Engine as _, engine::general_purpose};
+    use http::{Method, Request, StatusCode};
+    use k8s_openapi::ByteString;
+    use k8s_openapi::api::core::v1::Secret;
+    use kube::api::ObjectMeta;
+    use kube::client::Body;
+    use std::collections::BTreeMap;
+    use trusted_cluster_operator_lib::TrustedExecutionClusterRegistryMirrors;
+    use trusted_cluster_operator_lib::registry_auth::DOCKER_CONFIG_KEY;
+    use trusted_cluster_operator_lib::{ApprovedImageImagePullSecrets, ApprovedImageSpec};
+    use trusted_cluster_operator_test_utils::mock_client::*;
+
+    const IMAGE_REF: &str =
+        "quay.io/org/image@sha256:e71dad00aa0e3d70540e726a0c66407e3004d96e045ab6c253186e327a2419e5";
+
+    fn docker_config(auths: &[(&str, &str)]) -> Vec<u8> {
+        let auths: BTreeMap<_, _> = auths
+            .iter()
+            .map(|(key, creds)| {
+                let auth = general_purpose::STANDARD.encode(creds);
+                (*key, serde_json::json!({"auth": auth}))
+            })
+            .collect();
+        serde_json::to_vec(&serde_json::json!({"auths": auths})).unwrap()
+    }
+
+    fn dummy_image(secrets: &[&str]) -> ApprovedImage {
+        let secrets = secrets.iter().map(|name| ApprovedImageImagePullSecrets {
+            name: Some(name.to_string()),
+        });
+        ApprovedImage {
+            metadata: ObjectMeta::default(),
+            spec: ApprovedImageSpec {
+                image: IMAGE_REF.to_string(),
+                not_before: None,
+                not_after: None,
+                image_pull_secrets: Some(secrets.collect()),
+                platform_profiles: None,
+                kernel_command_lines: None,
+            },
+            status: None,
+        }
+    }
+
+    fn pull_secret(config: Vec<u8>) -> Secret {
+        Secret {
+            data: Some(BTreeMap::from([(
+                DOCKER_CONFIG_KEY.to_string(),
+                ByteString(config),
+            )])),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_auth() {
+        let config = docker_config(&[("quay.io", "user:pass")]);
+        let secret = serde_json::to_string(&pull_secret(config)).unwrap();
+        let clos = move |req: Request<Body>, ctr| {
+            let secret = secret.clone();
+            async move {
+                match (ctr, req.method()) {
+                    // Missing secrets are skipped
+                    (0, &Method::GET) => Err(StatusCode::NOT_FOUND),
+                    (1, &Method::GET) => {
+                        assert!(req.uri().path().ends_with("/pull-secret"));
+                        Ok(secret)
+                    }
+                    _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+                }
+            }
+        };
+        count_check!(2, clos, |client| {
+            let image = dummy_image(&["missing", "pull-secret"]);
+            let image_ref = IMAGE_REF.parse().unwrap();
+            let auth = registry_auth(client, &image, &image_ref).await.unwrap();
+            let expected = RegistryAuth::Basic("user".to_string(), "pass".to_string());
+            assert_eq!(auth, expected);
+        });
+    }
+
+    fn dummy_registry_config(mirrors: &[(&str, &[&str])]) -> TrustedExecutionClusterRegistry {
+        let mirrors = mirrors.iter().map(|(source, targets)| {
+            let mirrors = targets.iter().map(|t| t.to_string()).collect();
+            TrustedExecutionClusterRegistryMirrors {
+                source: source.to_string(),
+                mirrors,
+            }
+        });
+        TrustedExecutionClusterRegistry {
+            ca_bundle_config_map: Some("registry-ca".to_string()),
+            mirrors: Some(mirrors.collect()),
+            https_proxy: None,
+            http_proxy: None,
+            no_proxy: None,
+        }
+    }
+
+    #[test]
+    fn test_mirrored_references() {
+        let image_ref: Reference = IMAGE_REF.parse().unwrap();
+        let config = dummy_registry_config(&[
+            ("quay.io/org", &["mirror.example.com/org-mirror"]),
+            (
+                "quay.io",
+                &["mirror.example.com/quay.io", "backup.example.com"],
+            ),
+            ("quay.io/organization", &["mirror.example.com/wrong"]),
+        ]);
+        let references = mirrored_references(&image_ref, Some(&config));
+        let references: Vec<_> = references.iter().map(|r| r.whole()).collect();
+        let digest = image_ref.digest().unwrap();
+        assert_eq!(
+            references,
+            vec![
+                format!("mirror.example.com/org-mirror/image@{digest}"),
+                format!("mirror.example.com/quay.io/org/image@{digest}"),
+                format!("backup.example.com/org/image@{digest}"),
+                IMAGE_REF.to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mirrored_references_tag() {
+        let image_ref: Reference = "quay.io/org/image:latest".parse().unwrap();
+        let config = dummy_registry_config(&[("quay.io", &["mirror.example.com"])]);
+        let references = mirrored_references(&image_ref, Some(&config));
+        assert_eq!(references.len(), 1);
+        assert_eq!(references[0].whole(), image_ref.whole());
+    }
+
+    #[tokio::test]
+    async fn test_oci_client_no_ca_bundle() {
+        let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
+            (0, &Method::GET) => {
+                assert!(req.uri().path().ends_with("/registry-ca"));
+                Ok(serde_json::to_string(&ConfigMap::default()).unwrap())
+            }
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(1, clos, |client| {
+            let config = dummy_registry_config(&[]);
+            let err = oci_client(client, Some(&config)).await.err().unwrap();
+            assert!(err.to_string().contains(CA_BUNDLE_KEY));
+        });
+    }
+
+    #[test]
+    fn test_image_pull_secrets() {
+        assert!(image_pull_secrets(&dummy_image(&[])).is_none());
+        let secrets = image_pull_secrets(&dummy_image(&["pull-secret"])).unwrap();
+        assert_eq!(secrets[0].name, "pull-secret");
+    }
+}
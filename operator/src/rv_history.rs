@@ -0,0 +1,440 @@
+// SPDX-FileCopyrightText: Jakob Naucke <jnaucke@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+// Versioned history of reference value sets. Every set that differs from its predecessor is kept
+// in a ConfigMap together with the ApprovedImages that contributed to it and the changes to the
+// previous version. A TrustedExecutionCluster can pin a version to roll back to it.
+
+use anyhow::{Context, Result, anyhow};
+use k8s_openapi::api::core::v1::ConfigMap;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use k8s_openapi::jiff::Timestamp;
+use kube::api::{DeleteParams, ListParams, ObjectMeta};
+use kube::{Api, Client};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::trustee::{REFERENCE_VALUES_FILE, ReferenceValue};
+use trusted_cluster_operator_lib::reference_values::ImagePcrs;
+
+const RV_HISTORY_LABEL: &str = "trusted-execution-clusters.io/reference-values-history";
+const RV_VERSION_ANNOTATION: &str = "trusted-execution-clusters.io/reference-values-version";
+const RV_HISTORY_PREFIX: &str = "reference-values-v";
+const PROVENANCE_FILE: &str = "provenance.json";
+const CHANGES_FILE: &str = "changes.json";
+/// Number of versions to keep
+const RV_HISTORY_LIMIT: usize = 10;
+
+/// Reference value names mapped to their values
+type ValueSets = BTreeMap<String, BTreeSet<String>>;
+
+#[derive(Clone, Deserialize, PartialEq, Serialize)]
+struct ImageRecord {
+    reference: String,
+    first_seen: Timestamp,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Provenance {
+    /// ApprovedImages that contributed to this version
+    images: BTreeMap<String, ImageRecord>,
+    /// Reference value names mapped to their values and the ApprovedImages they stem from. Values
+    /// that only arise from combining events of several images list all images with that PCR.
+    values: BTreeMap<String, BTreeMap<String, BTreeSet<String>>>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct ReferenceValueDiff {
+    pub added: ValueSets,
+    pub removed: ValueSets,
+}
+
+#[derive(Serialize)]
+struct Changes {
+    previous_version: Option<u32>,
+    images_added: BTreeSet<String>,
+    images_removed: BTreeSet<String>,
+    #[serde(flatten)]
+    values: ReferenceValueDiff,
+}
+
+fn history_map_name(version: u32) -> String {
+    format!("{RV_HISTORY_PREFIX}{version}")
+}
+
+fn value_sets(reference_values: &[ReferenceValue]) -> ValueSets {
+    let values = |rv: &ReferenceValue| {
+        let values = rv.value.as_array().into_iter().flatten();
+        values
+            .filter_map(|v| v.as_str().map(String::from))
+            .collect()
+    };
+    let sets = reference_values
+        .iter()
+        .map(|rv| (rv.name.clone(), values(rv)));
+    sets.collect()
+}
+
+/// Values that were added to and removed from `old` to obtain `new`
+pub fn diff(old: &ValueSets, new: &ValueSets) -> ReferenceValueDiff {
+    let difference = |a: &ValueSets, b: &ValueSets| -> ValueSets {
+        let empty = BTreeSet::new();
+        a.iter()
+            .map(|(name, values)| {
+                let other = b.get(name).unwrap_or(&empty);
+                (name.clone(), values - other)
+            })
+            .filter(|(_, values)| !values.is_empty())
+            .collect()
+    };
+    ReferenceValueDiff {
+        added: difference(new, old),
+        removed: difference(old, new),
+    }
+}
+
+fn provenance(image_pcrs: &ImagePcrs, reference_values: &[ReferenceValue]) -> Provenance {
+    let images = image_pcrs.0.iter().map(|(name, image)| {
+        let record = ImageRecord {
+            reference: image.reference.clone(),
+            first_seen: image.first_seen,
+        };
+        (name.clone(), record)
+    });
+
+    let mut values = BTreeMap::new();
+    for (name, rv_values) in value_sets(reference_values) {
+        // Only PCR values stem from images, prefixed with the platform profile they were
+        // computed for, if any
+        let (platform, pcr_name) = match name.split_once('/') {
+            Some((platform, pcr_name)) => (Some(platform), pcr_name),
+            None => (None, name.as_str()),
+        };
+        let Some(pcr_id) = pcr_name.strip_prefix("tpm_pcr") else {
+            continue;
+        };
+        let images_with = |value: Option<&str>| -> BTreeSet<String> {
+            let has_pcr = |pcr: &compute_pcrs_lib::Pcr| {
+                pcr.id.to_string() == pcr_id && value.is_none_or(|v| hex::encode(&pcr.value) == v)
+            };
+            let images = image_pcrs.0.iter();
+            let images = images.filter(|(_, image)| {
+                let platform_pcrs = image.platform_pcrs();
+                let mut pcrs = platform_pcrs.iter().filter(|(_, p, _)| *p == platform);
+                let on_platform = pcrs.clone().next().is_some();
+                pcrs.any(|(_, _, pcrs)| pcrs.iter().any(&has_pcr))
+                    || on_platform && image.kernel_cmdline_pcrs.iter().any(&has_pcr)
+            });
+            images.map(|(name, _)| name.clone()).collect()
+        };
+        let sources = rv_values.into_iter().map(|value| {
+            let mut images = images_with(Some(value.as_str()));
+            if images.is_empty() {
+                images = images_with(None);
+            }
+            (value, images)
+        });
+        values.insert(name, sources.collect());
+    }
+    Provenance {
+        images: images.collect(),
+        values,
+    }
+}
+
+fn map_version(config_map: &ConfigMap) -> Option<u32> {
+    let annotations = config_map.metadata.annotations.as_ref();
+    let version = annotations.and_then(|a| a.get(RV_VERSION_ANNOTATION));
+    version.and_then(|v| v.parse().ok())
+}
+
+fn map_file<'a>(config_map: &'a ConfigMap, file: &str) -> Result<&'a str> {
+    let name = config_map.metadata.name.as_deref().unwrap_or("<no name>");
+    let data = config_map.data.as_ref().and_then(|d| d.get(file));
+    let err = format!("ConfigMap {name} had no {file}");
+    data.map(String::as_str).context(err)
+}
+
+/// History ConfigMaps sorted by version, oldest first
+async fn list_history(config_maps: &Api<ConfigMap>) -> Result<Vec<(u32, ConfigMap)>> {
+    let params = ListParams::default().labels(&format!("{RV_HISTORY_LABEL}=true"));
+    let list = config_maps.list(&params).await?;
+    let mut history: Vec<_> = list
+        .items
+        .into_iter()
+        .filter_map(|cm| map_version(&cm).map(|v| (v, cm)))
+        .collect();
+    history.sort_by_key(|(version, _)| *version);
+    Ok(history)
+}
+
+/// Reference values of a version from the history and the ApprovedImages that contributed to it
+pub async fn get_version(
+    client: Client,
+    version: u32,
+) -> Result<(Vec<ReferenceValue>, BTreeSet<String>)> {
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client);
+    let name = history_map_name(version);
+    let err = format!("Reference value version {version} is not in the history");
+    let config_map = config_maps.get_opt(&name).await?.context(err)?;
+    let reference_values = map_file(&config_map, REFERENCE_VALUES_FILE)?;
+    let provenance: Provenance = serde_json::from_str(map_file(&config_map, PROVENANCE_FILE)?)?;
+    let images = provenance.images.into_keys().collect();
+    Ok((serde_json::from_str(reference_values)?, images))
+}
+
+/// Values that were added to and removed from version `from` to obtain version `to`
+pub async fn diff_versions(client: Client, from: u32, to: u32) -> Result<ReferenceValueDiff> {
+    let (old, _) = get_version(client.clone(), from).await?;
+    let (new, _) = get_version(client, to).await?;
+    Ok(diff(&value_sets(&old), &value_sets(&new)))
+}
+
+/// Record a reference value set as a new version if its values or contributing images differ
+/// from the latest version, and drop versions beyond the history limit
+pub async fn record(
+    client: Client,
+    owner_reference: Option<OwnerReference>,
+    image_pcrs: &ImagePcrs,
+    reference_values: &[ReferenceValue],
+) -> Result<()> {
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client);
+    let history = list_history(&config_maps).await?;
+    let provenance = provenance(image_pcrs, reference_values);
+    let values = value_sets(reference_values);
+
+    let latest = match history.last() {
+        Some((version, config_map)) => {
+            let latest_values = map_file(config_map, REFERENCE_VALUES_FILE)?;
+            let latest_values: Vec<ReferenceValue> = serde_json::from_str(latest_values)?;
+            let latest_provenance = map_file(config_map, PROVENANCE_FILE)?;
+            let latest_provenance: Provenance = serde_json::from_str(latest_provenance)?;
+            Some((
+                *version,
+                value_sets(&latest_values),
+                latest_provenance.images,
+            ))
+        }
+        None => None,
+    };
+    if let Some((_, latest_values, latest_images)) = &latest
+        && *latest_values == values
+        && *latest_images == provenance.images
+    {
+        return Ok(());
+    }
+
+    let previous_version = latest.as_ref().map(|(version, _, _)| *version);
+    let (previous_values, previous_images) = latest
+        .map(|(_, values, images)| (values, images))
+        .unwrap_or_default();
+    let image_names = |images: &BTreeMap<String, ImageRecord>| -> BTreeSet<String> {
+        images.keys().cloned().collect()
+    };
+    let changes = Changes {
+        previous_version,
+        images_added: &image_names(&provenance.images) - &image_names(&previous_images),
+        images_removed: &image_names(&previous_images) - &image_names(&provenance.images),
+        values: diff(&previous_values, &values),
+    };
+
+    let version = previous_version.map_or(1, |v| v + 1);
+    let data = BTreeMap::from([
+        (
+            REFERENCE_VALUES_FILE.to_string(),
+            serde_json::to_string(reference_values)?,
+        ),
+        (
+            PROVENANCE_FILE.to_string(),
+            serde_json::to_string(&provenance)?,
+        ),
+        (CHANGES_FILE.to_string(), serde_json::to_string(&changes)?),
+    ]);
+    let config_map = ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(history_map_name(version)),
+            labels: Some(BTreeMap::from([(
+                RV_HISTORY_LABEL.to_string(),
+                "true".to_string(),
+            )])),
+            annotations: Some(BTreeMap::from([(
+                RV_VERSION_ANNOTATION.to_string(),
+                version.to_string(),
+            )])),
+            owner_references: owner_reference.map(|o| vec![o]),
+            ..Default::default()
+        },
+        data: Some(data),
+        ..Default::default()
+    };
+    config_maps.create(&Default::default(), &config_map).await?;
+    info!("Recorded reference values as version {version}");
+
+    // The new version is not part of the listed history
+    let excess = (history.len() + 1).saturating_sub(RV_HISTORY_LIMIT);
+    for (old_version, _) in history.iter().take(excess) {
+        let name = history_map_name(*old_version);
+        let delete = config_maps.delete(&name, &DeleteParams::default()).await;
+        delete.map_err(|e| anyhow!("Failed to drop reference value version {old_version}: {e}"))?;
+        info!("Dropped reference value version {old_version} from the history");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use http::{Method, Request};
+    use kube::api::ObjectList;
+    use kube::client::Body;
+    use trusted_cluster_operator_test_utils::mock_client::*;
+
+    fn dummy_history_map(version: u32, reference_values: &str, images: &str) -> ConfigMap {
+        let provenance = format!(r#"{{"images":{images},"values":{{}}}}"#);
+        ConfigMap {
+            metadata: ObjectMeta {
+                name: Some(history_map_name(version)),
+                annotations: Some(BTreeMap::from([(
+                    RV_VERSION_ANNOTATION.to_string(),
+                    version.to_string(),
+                )])),
+                ..Default::default()
+            },
+            data: Some(BTreeMap::from([
+                (
+                    REFERENCE_VALUES_FILE.to_string(),
+                    reference_values.to_string(),
+                ),
+                (PROVENANCE_FILE.to_string(), provenance),
+            ])),
+            ..Default::default()
+        }
+    }
+
+    fn dummy_history(maps: Vec<ConfigMap>) -> ObjectList<ConfigMap> {
+        ObjectList {
+            items: maps,
+            types: Default::default(),
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = ValueSets::from([
+            ("tpm_pcr4".to_string(), BTreeSet::from(["a".to_string()])),
+            ("tpm_pcr7".to_string(), BTreeSet::from(["c".to_string()])),
+        ]);
+        let new = ValueSets::from([(
+            "tpm_pcr4".to_string(),
+            BTreeSet::from(["a".to_string(), "b".to_string()]),
+        )]);
+        let result = diff(&old, &new);
+        let expected = ReferenceValueDiff {
+            added: ValueSets::from([("tpm_pcr4".to_string(), BTreeSet::from(["b".to_string()]))]),
+            removed: ValueSets::from([("tpm_pcr7".to_string(), BTreeSet::from(["c".to_string()]))]),
+        };
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_provenance() {
+        let image_pcrs = dummy_pcrs();
+        let reference_values = vec![ReferenceValue {
+            version: "0.1.0".to_string(),
+            name: "tpm_pcr4".to_string(),
+            expiration: Default::default(),
+            value: serde_json::json!([DUMMY_PCR_4_VALUE]),
+        }];
+        let provenance = provenance(&image_pcrs, &reference_values);
+        assert!(provenance.images.contains_key("cos"));
+        let sources = &provenance.values["tpm_pcr4"][DUMMY_PCR_4_VALUE];
+        assert_eq!(sources, &BTreeSet::from(["cos".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_diff_versions() {
+        let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
+            (0, &Method::GET) => {
+                assert!(req.uri().path().ends_with(&history_map_name(2)));
+                let values = r#"[{"version":"0.1.0","name":"tpm_pcr4","expiration":"2100-01-01T00:00:00Z","value":["a"]}]"#;
+                Ok(serde_json::to_string(&dummy_history_map(2, values, "{}")).unwrap())
+            }
+            (1, &Method::GET) => {
+                assert!(req.uri().path().ends_with(&history_map_name(5)));
+                let values = r#"[{"version":"0.1.0","name":"tpm_pcr4","expiration":"2100-01-01T00:00:00Z","value":["b"]}]"#;
+                Ok(serde_json::to_string(&dummy_history_map(5, values, "{}")).unwrap())
+            }
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(2, clos, |client| {
+            let result = diff_versions(client, 2, 5).await.unwrap();
+            let values = |v: &str| {
+                ValueSets::from([("tpm_pcr4".to_string(), BTreeSet::from([v.to_string()]))])
+            };
+            assert_eq!(result.added, values("b"));
+            assert_eq!(result.removed, values("a"));
+        });
+    }
+
+    #[tokio::test]
+    async fn test_record_first_version() {
+        let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
+            (0, &Method::GET) => Ok(serde_json::to_string(&dummy_history(vec![])).unwrap()),
+            (1, &Method::POST) => {
+                let body = get_body_string(req).await;
+                assert!(body.contains(&history_map_name(1)));
+                Ok(serde_json::to_string(&ConfigMap::default()).unwrap())
+            }
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(2, clos, |client| {
+            let result = record(client, None, &dummy_pcrs(), &[]).await;
+            assert!(result.is_ok());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_record_unchanged() {
+        let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
+            (0, &Method::GET) => {
+                let map = dummy_history_map(3, "[]", "{}");
+                Ok(serde_json::to_string(&dummy_history(vec![map])).unwrap())
+            }
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(1, clos, |client| {
+            let result = record(client, None, &ImagePcrs::default(), &[]).await;
+            assert!(result.is_ok());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_record_drops_oldest() {
+        let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
+            (0, &Method::GET) => {
+                let maps = (1..=RV_HISTORY_LIMIT as u32)
+                    .map(|v| dummy_history_map(v, "[]", "{}"))
+                    .collect();
+                Ok(serde_json::to_string(&dummy_history(maps)).unwrap())
+            }
+            (1, &Method::POST) => {
+                let name = history_map_name(RV_HISTORY_LIMIT as u32 + 1);
+                assert!(get_body_string(req).await.contains(&name));
+                Ok(serde_json::to_string(&ConfigMap::default()).unwrap())
+            }
+            (2, &Method::DELETE) => {
+                assert!(req.uri().path().ends_with(&history_map_name(1)));
+                Ok(serde_json::to_string(&ConfigMap::default()).unwrap())
+            }
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(3, clos, |client| {
+            let result = record(client, None, &dummy_pcrs(), &[]).await;
+            assert!(result.is_ok());
+        });
+    }
+}
@@ -0,0 +1,276 @@
+// SPDX-FileCopyrightText: Jakob Naucke <jnaucke@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+// Standalone reference value provider service (RVPS). When deployed, Trustee queries it over
+// gRPC instead of using its built-in RVPS, and the operator registers reference values through
+// the Trustee admin API, which forwards them to this service.
+
+use anyhow::Result;
+use futures_util::StreamExt;
+use k8s_openapi::api::apps::v1::{Deployment, DeploymentSpec};
+use k8s_openapi::api::core::v1::{
+    ConfigMap, ConfigMapVolumeSource, Container, ContainerPort, EmptyDirVolumeSource, EnvVar,
+    PodSpec, PodTemplateSpec, Service, ServicePort, ServiceSpec, Volume, VolumeMount,
+};
+use k8s_openapi::apimachinery::pkg::{
+    apis::meta::v1::{LabelSelector, OwnerReference},
+    util::intstr::IntOrString,
+};
+use kube::runtime::controller::{Action, Controller};
+use kube::runtime::watcher;
+use kube::{Api, Client, Resource, api::ObjectMeta};
+use log::info;
+use serde_json::json;
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+use crate::trustee;
+use operator::{
+    ControllerError, LONG_REQUEUE, controller_error_policy, controller_info,
+    create_or_info_if_exists,
+};
+use trusted_cluster_operator_lib::TrustedExecutionClusterSpec;
+use trusted_cluster_operator_lib::endpoints::*;
+
+const RVPS_CONFIG_MAP: &str = "rvps-config";
+const RVPS_CONFIG_FILE: &str = "rvps.json";
+const RVPS_CONFIG_DIR: &str = "/etc/rvps";
+const RVPS_DATA_VOLUME: &str = "rvps-data";
+const DEFAULT_RVPS_STORAGE_PATH: &str = "/opt/rvps/reference-values.json";
+
+pub fn is_external_rvps(spec: &TrustedExecutionClusterSpec) -> bool {
+    let external_rvps = spec.trustee.as_ref().and_then(|t| t.external_rvps);
+    external_rvps.unwrap_or(false)
+}
+
+pub fn rvps_address() -> String {
+    format!("http://{RVPS_SERVICE}:{RVPS_PORT}")
+}
+
+fn rvps_storage_path(spec: &TrustedExecutionClusterSpec) -> &str {
+    let path = spec
+        .trustee
+        .as_ref()
+        .and_then(|t| t.rvps_storage_path.as_deref());
+    path.unwrap_or(DEFAULT_RVPS_STORAGE_PATH)
+}
+
+pub async fn generate_rvps_config(
+    client: Client,
+    owner_reference: OwnerReference,
+    spec: &TrustedExecutionClusterSpec,
+) -> Result<()> {
+    let rvps_config = json!({
+        "storage": {
+            "type": "LocalJson",
+            "file_path": rvps_storage_path(spec),
+        }
+    });
+    let data = BTreeMap::from([(
+        RVPS_CONFIG_FILE.to_string(),
+        serde_json::to_string_pretty(&rvps_config)?,
+    )]);
+
+    let config_map = ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(RVPS_CONFIG_MAP.to_string()),
+            owner_references: Some(vec![owner_reference]),
+            ..Default::default()
+        },
+        data: Some(data),
+        ..Default::default()
+    };
+    create_or_info_if_exists!(client, ConfigMap, config_map);
+    Ok(())
+}
+
+pub async fn generate_rvps_service(client: Client, owner_reference: OwnerReference) -> Result<()> {
+    let selector = Some(BTreeMap::from([(
+        "app".to_string(),
+        RVPS_APP_LABEL.to_string(),
+    )]));
+
+    let service = Service {
+        metadata: ObjectMeta {
+            name: Some(RVPS_SERVICE.to_string()),
+            owner_references: Some(vec![owner_reference]),
+            ..Default::default()
+        },
+        spec: Some(ServiceSpec {
+            selector,
+            ports: Some(vec![ServicePort {
+                name: Some("rvps-port".to_string()),
+                port: RVPS_PORT,
+                target_port: Some(IntOrString::Int(RVPS_PORT)),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    create_or_info_if_exists!(client, Service, service);
+    Ok(())
+}
+
+fn generate_rvps_pod_spec(image: &str, spec: &TrustedExecutionClusterSpec) -> PodSpec {
+    let storage_dir = Path::new(rvps_storage_path(spec)).parent();
+    let storage_dir = storage_dir.map(|p| p.to_string_lossy().to_string());
+
+    PodSpec {
+        containers: vec![Container {
+            command: Some(vec![
+                "rvps".to_string(),
+                "--config".to_string(),
+                format!("{RVPS_CONFIG_DIR}/{RVPS_CONFIG_FILE}"),
+                "--address".to_string(),
+                format!("0.0.0.0:{RVPS_PORT}"),
+            ]),
+            env: Some(vec![EnvVar {
+                name: "RUST_LOG".to_string(),
+                value: Some(trustee::kbs_log_level(spec)),
+                ..Default::default()
+            }]),
+            image: Some(image.to_string()),
+            name: "rvps".to_string(),
+            ports: Some(vec![ContainerPort {
+                container_port: RVPS_PORT,
+                ..Default::default()
+            }]),
+            volume_mounts: Some(vec![
+                VolumeMount {
+                    name: RVPS_CONFIG_MAP.to_string(),
+                    mount_path: RVPS_CONFIG_DIR.to_string(),
+                    read_only: Some(true),
+                    ..Default::default()
+                },
+                VolumeMount {
+                    name: RVPS_DATA_VOLUME.to_string(),
+                    mount_path: storage_dir.unwrap_or_default(),
+                    ..Default::default()
+                },
+            ]),
+            ..Default::default()
+        }],
+        volumes: Some(vec![
+            Volume {
+                name: RVPS_CONFIG_MAP.to_string(),
+                config_map: Some(ConfigMapVolumeSource {
+                    name: RVPS_CONFIG_MAP.to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            // Reference values are re-registered when the RVPS restarts, see rvps_reconcile
+            Volume {
+                name: RVPS_DATA_VOLUME.to_string(),
+                empty_dir: Some(EmptyDirVolumeSource::default()),
+                ..Default::default()
+            },
+        ]),
+        ..Default::default()
+    }
+}
+
+pub async fn generate_rvps_deployment(
+    client: Client,
+    owner_reference: OwnerReference,
+    image: &str,
+    spec: &TrustedExecutionClusterSpec,
+) -> Result<()> {
+    let selector = Some(BTreeMap::from([(
+        "app".to_string(),
+        RVPS_APP_LABEL.to_string(),
+    )]));
+
+    let deployment = Deployment {
+        metadata: ObjectMeta {
+            name: Some(RVPS_DEPLOYMENT.to_string()),
+            owner_references: Some(vec![owner_reference]),
+            ..Default::default()
+        },
+        spec: Some(DeploymentSpec {
+            replicas: Some(1),
+            selector: LabelSelector {
+                match_labels: selector.clone(),
+                ..Default::default()
+            },
+            template: PodTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    labels: selector,
+                    ..Default::default()
+                }),
+                spec: Some(generate_rvps_pod_spec(image, spec)),
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    create_or_info_if_exists!(client, Deployment, deployment);
+    Ok(())
+}
+
+/// The RVPS keeps reference values in an emptyDir. Register them again whenever its deployment
+/// changes, e.g. because its pod was restarted, even though the recorded registration matches.
+async fn rvps_reconcile(
+    _deployment: Arc<Deployment>,
+    client: Arc<Client>,
+) -> Result<Action, ControllerError> {
+    let client = Arc::unwrap_or_clone(client);
+    trustee::update_reference_values(client, true).await?;
+    info!("Synchronized reference values with {RVPS_DEPLOYMENT}");
+    Ok(LONG_REQUEUE)
+}
+
+pub async fn launch_rvps_controller(client: Client) {
+    let deployments: Api<Deployment> = Api::default_namespaced(client.clone());
+    let rvps_filter = format!("metadata.name={RVPS_DEPLOYMENT}");
+    let watcher_config = watcher::Config::default().fields(&rvps_filter);
+    tokio::spawn(
+        Controller::new(deployments, watcher_config)
+            .run(rvps_reconcile, controller_error_policy, Arc::new(client))
+            .for_each(controller_info),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{Method, Request, StatusCode};
+    use trusted_cluster_operator_test_utils::mock_client::*;
+    use trusted_cluster_operator_test_utils::test_error_method;
+
+    #[test]
+    fn test_rvps_pod_spec_storage_dir() {
+        let pod_spec = generate_rvps_pod_spec("image", &dummy_cluster().spec);
+        let mounts = pod_spec.containers[0].volume_mounts.clone().unwrap();
+        let data_mount = mounts.iter().find(|m| m.name == RVPS_DATA_VOLUME).unwrap();
+        assert_eq!(data_mount.mount_path, "/opt/rvps");
+    }
+
+    #[tokio::test]
+    async fn test_generate_rvps_config_success() {
+        let spec = dummy_cluster().spec;
+        let clos = |client| generate_rvps_config(client, Default::default(), &spec);
+        test_create_success::<_, _, ConfigMap>(clos).await;
+    }
+
+    #[tokio::test]
+    async fn test_generate_rvps_service_success() {
+        let clos = |client| generate_rvps_service(client, Default::default());
+        test_create_success::<_, _, Service>(clos).await;
+    }
+
+    #[tokio::test]
+    async fn test_generate_rvps_depl_success() {
+        let spec = dummy_cluster().spec;
+        let clos = |client| generate_rvps_deployment(client, Default::default(), "image", &spec);
+        test_create_success::<_, _, Deployment>(clos).await;
+    }
+
+    #[tokio::test]
+    async fn test_generate_rvps_depl_error() {
+        let spec = dummy_cluster().spec;
+        let clos = |client| generate_rvps_deployment(client, Default::default(), "image", &spec);
+        test_error_method!(clos, Method::POST);
+    }
+}
@@ -0,0 +1,301 @@
+// SPDX-FileCopyrightText: Jakob Naucke <jnaucke@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+// Verification of ApprovedImage signatures against the signature policy of the
+// TrustedExecutionCluster. Signatures are cosign simple signing signatures, stored as layers of
+// the sha256-<digest>.sig tag in the repository of the image. A layer is a JSON payload that
+// names the manifest digest, with the signature of the payload as an annotation.
+
+use anyhow::{Context, Result, bail};
+use base64::{Engine as _, engine::general_purpose};
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{Api, Client};
+use log::{info, warn};
+use oci_client::{Reference, manifest::OciManifest, secrets::RegistryAuth};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::sign::Verifier;
+use serde::Deserialize;
+
+use crate::registry;
+use trusted_cluster_operator_lib::{
+    ApprovedImage, TrustedExecutionClusterSignaturePolicy, TrustedExecutionClusterSpec,
+};
+
+const SIGNATURE_ANNOTATION: &str = "dev.cosignproject.cosign/signature";
+const SIMPLE_SIGNING_MEDIA_TYPE: &str = "application/vnd.dev.cosign.simplesigning.v1+json";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct SignedImage {
+    docker_manifest_digest: String,
+}
+
+#[derive(Deserialize)]
+struct Critical {
+    image: SignedImage,
+}
+
+#[derive(Deserialize)]
+struct SimpleSigning {
+    critical: Critical,
+}
+
+struct Signature {
+    payload: Vec<u8>,
+    /// base64 of the signature of the payload
+    signature: String,
+}
+
+/// Tag under which cosign stores the signatures of an image digest
+fn signature_reference(image_ref: &Reference, digest: &str) -> Reference {
+    let tag = format!("{}.sig", digest.replace(':', "-"));
+    let registry = image_ref.registry().to_string();
+    Reference::with_tag(registry, image_ref.repository().to_string(), tag)
+}
+
+async fn read_signatures(
+    oci_client: &oci_client::Client,
+    image_ref: &Reference,
+    auth: &RegistryAuth,
+) -> Result<Vec<Signature>> {
+    let digest = image_ref.digest().context("Image had no digest")?;
+    let signature_ref = signature_reference(image_ref, digest);
+    let (manifest, _) = oci_client.pull_manifest(&signature_ref, auth).await?;
+    let OciManifest::Image(manifest) = manifest else {
+        bail!("Signature manifest {signature_ref} was not an image manifest");
+    };
+    let mut signatures = Vec::new();
+    for layer in &manifest.layers {
+        let annotations = layer.annotations.as_ref();
+        let signature = annotations.and_then(|a| a.get(SIGNATURE_ANNOTATION));
+        let Some(signature) = signature.filter(|_| layer.media_type == SIMPLE_SIGNING_MEDIA_TYPE)
+        else {
+            continue;
+        };
+        let mut payload = Vec::new();
+        oci_client
+            .pull_blob(&signature_ref, layer, &mut payload)
+            .await?;
+        let signature = signature.clone();
+        signatures.push(Signature { payload, signature });
+    }
+    Ok(signatures)
+}
+
+/// Whether a signature is valid for the key and its payload names the digest
+fn verify_signature(key: &PKey<Public>, signature: &Signature, digest: &str) -> Result<bool> {
+    let raw_signature = general_purpose::STANDARD.decode(&signature.signature)?;
+    let mut verifier = match key.id() {
+        Id::ED25519 => Verifier::new_without_digest(key)?,
+        _ => Verifier::new(MessageDigest::sha256(), key)?,
+    };
+    if !verifier.verify_oneshot(&raw_signature, &signature.payload)? {
+        return Ok(false);
+    }
+    let payload: SimpleSigning = serde_json::from_slice(&signature.payload)?;
+    Ok(payload.critical.image.docker_manifest_digest == digest)
+}
+
+async fn public_keys(
+    client: Client,
+    policy: &TrustedExecutionClusterSignaturePolicy,
+) -> Result<Vec<PKey<Public>>> {
+    let name = &policy.public_keys_config_map;
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client);
+    let config_map = config_maps.get(name).await?;
+    let mut keys = Vec::new();
+    for (key_name, pem) in config_map.data.iter().flatten() {
+        match PKey::public_key_from_pem(pem.as_bytes()) {
+            Ok(key) => keys.push(key),
+            Err(e) => warn!("Public key {key_name} of ConfigMap {name} could not be parsed: {e}"),
+        }
+    }
+    if keys.is_empty() {
+        bail!("ConfigMap {name} did not contain any public keys");
+    }
+    Ok(keys)
+}
+
+/// Whether an image is signed by a key of the signature policy. Images are always accepted
+/// without a signature policy.
+pub async fn verify_image(
+    client: Client,
+    image: &ApprovedImage,
+    image_ref: &Reference,
+    spec: &TrustedExecutionClusterSpec,
+) -> Result<bool> {
+    let Some(policy) = &spec.signature_policy else {
+        return Ok(true);
+    };
+    let keys = public_keys(client.clone(), policy).await?;
+    let digest = image_ref.digest().context("Image had no digest")?;
+    let config = spec.registry.as_ref();
+    let signatures = registry::read_image(client, image, image_ref, config, read_signatures).await;
+    let signatures = match signatures {
+        Ok(signatures) => signatures,
+        Err(e) => {
+            warn!("Reading signatures of {image_ref} failed: {e}");
+            return Ok(false);
+        }
+    };
+    for signature in &signatures {
+        for key in &keys {
+            match verify_signature(key, signature, digest) {
+                Ok(true) => {
+                    info!("Image {image_ref} has a valid signature");
+                    return Ok(true);
+                }
+                Ok(false) => {}
+                Err(e) => warn!("Signature of {image_ref} could not be verified: {e}"),
+            }
+        }
+    }
+    warn!("Image {image_ref} had no valid signature by a key of the signature policy");
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{Method, Request};
+    use kube::client::Body;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::pkey::Private;
+    use openssl::sign::Signer;
+    use std::collections::BTreeMap;
+    use trusted_cluster_operator_lib::ApprovedImageSpec;
+    use trusted_cluster_operator_test_utils::mock_client::*;
+
+    const DIGEST: &str = "sha256:4e2a3c5f5ad8bc7d8b1e6e0d3f3d07f3c0b7e2d5a6b4c9e8f1a2b3c4d5e6f7a8";
+
+    fn generate_key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    fn public_key(key: &PKey<Private>) -> PKey<Public> {
+        PKey::public_key_from_pem(&key.public_key_to_pem().unwrap()).unwrap()
+    }
+
+    fn sign(key: &PKey<Private>, digest: &str) -> Signature {
+        let payload = serde_json::json!({
+            "critical": {
+                "identity": {"docker-reference": "quay.io/example/image"},
+                "image": {"docker-manifest-digest": digest},
+                "type": "cosign container image signature",
+            },
+            "optional": null,
+        });
+        let payload = serde_json::to_vec(&payload).unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+        let signature = signer.sign_oneshot_to_vec(&payload).unwrap();
+        let signature = general_purpose::STANDARD.encode(signature);
+        Signature { payload, signature }
+    }
+
+    #[test]
+    fn test_signature_reference() {
+        let image_ref: Reference = format!("quay.io/example/image@{DIGEST}").parse().unwrap();
+        let reference = signature_reference(&image_ref, DIGEST);
+        let tag = format!("{}.sig", DIGEST.replace(':', "-"));
+        assert_eq!(
+            reference.to_string(),
+            format!("quay.io/example/image:{tag}")
+        );
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let key = generate_key();
+        let signature = sign(&key, DIGEST);
+        assert!(verify_signature(&public_key(&key), &signature, DIGEST).unwrap());
+    }
+
+    #[test]
+    fn test_verify_signature_other_digest() {
+        let key = generate_key();
+        let signature = sign(&key, "sha256:0000");
+        assert!(!verify_signature(&public_key(&key), &signature, DIGEST).unwrap());
+    }
+
+    #[test]
+    fn test_verify_signature_other_key() {
+        let signature = sign(&generate_key(), DIGEST);
+        let other_key = public_key(&generate_key());
+        assert!(!verify_signature(&other_key, &signature, DIGEST).unwrap());
+    }
+
+    #[test]
+    fn test_verify_signature_tampered_payload() {
+        let key = generate_key();
+        let mut signature = sign(&key, DIGEST);
+        signature.payload.push(b' ');
+        assert!(!verify_signature(&public_key(&key), &signature, DIGEST).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_public_keys() {
+        let pem = String::from_utf8(generate_key().public_key_to_pem().unwrap()).unwrap();
+        let clos = move |req: Request<Body>, _| {
+            let pem = pem.clone();
+            async move {
+                assert_eq!(req.method(), Method::GET);
+                let data = BTreeMap::from([
+                    ("cosign.pub".to_string(), pem),
+                    ("invalid.pub".to_string(), "invalid".to_string()),
+                ]);
+                let config_map = ConfigMap {
+                    data: Some(data),
+                    ..Default::default()
+                };
+                Ok(serde_json::to_string(&config_map).unwrap())
+            }
+        };
+        count_check!(1, clos, |client| {
+            let policy = TrustedExecutionClusterSignaturePolicy {
+                public_keys_config_map: "signing-keys".to_string(),
+            };
+            assert_eq!(public_keys(client, &policy).await.unwrap().len(), 1);
+        });
+    }
+
+    #[tokio::test]
+    async fn test_public_keys_empty() {
+        let clos = async |req: Request<_>, _| {
+            assert_eq!(req.method(), Method::GET);
+            Ok(serde_json::to_string(&ConfigMap::default()).unwrap())
+        };
+        count_check!(1, clos, |client| {
+            let policy = TrustedExecutionClusterSignaturePolicy {
+                public_keys_config_map: "signing-keys".to_string(),
+            };
+            assert!(public_keys(client, &policy).await.is_err());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_verify_image_no_policy() {
+        let clos = async |req: Request<_>, _| panic!("unexpected API interaction: {req:?}");
+        count_check!(0, clos, |client| {
+            let image_ref: Reference = format!("quay.io/example/image@{DIGEST}").parse().unwrap();
+            let cluster = dummy_cluster();
+            let image = ApprovedImage {
+                metadata: Default::default(),
+                spec: ApprovedImageSpec {
+                    image: image_ref.to_string(),
+                    not_before: None,
+                    not_after: None,
+                    image_pull_secrets: None,
+                    platform_profiles: None,
+                    kernel_command_lines: None,
+                },
+                status: None,
+            };
+            let verified = verify_image(client, &image, &image_ref, &cluster.spec).await;
+            assert!(verified.unwrap());
+        });
+    }
+}
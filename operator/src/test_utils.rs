@@ -2,13 +2,21 @@
 //
 // SPDX-License-Identifier: MIT
 
+use chrono::{DateTime, Utc};
 use compute_pcrs_lib::Pcr;
 use compute_pcrs_lib::tpmevents::{TPMEvent, TPMEventID};
-use k8s_openapi::{api::core::v1::ConfigMap, jiff::Timestamp};
+use k8s_openapi::api::core::v1::{ConfigMap, Secret};
+use k8s_openapi::jiff::Timestamp;
+use kube::api::{ObjectList, ObjectMeta};
 use std::collections::BTreeMap;
 
-use crate::trustee;
-use trusted_cluster_operator_lib::reference_values::{ImagePcr, ImagePcrs, PCR_CONFIG_FILE};
+use crate::{token_key, trustee};
+use trusted_cluster_operator_lib::reference_values::{
+    ImagePcr, ImagePcrs, PCR_CONFIG_FILE, image_pcr_map,
+};
+use trusted_cluster_operator_lib::{PlatformProfile, PlatformProfileSpec};
+use trusted_cluster_operator_lib::{PlatformProfileEvidenceType, TrustedExecutionCluster};
+use trusted_cluster_operator_test_utils::mock_client::dummy_cluster;
 
 pub const DUMMY_PCR_4_VALUE: &str =
     "3f263b96ccbc33bb53d808771f9ab1e02d4dec8854f9530f749cde853a723273";
@@ -49,10 +57,47 @@ pub fn dummy_pcrs() -> ImagePcrs {
                 },
             ],
             reference: "".to_string(),
+            not_after: None,
+            architectures: BTreeMap::new(),
+            origins: BTreeMap::new(),
+            profile_pcrs: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            kernel_cmdline_pcrs: Vec::new(),
+            kernel_command_lines: Vec::new(),
         },
     )]))
 }
 
+/// Profile with EFI variables in the ConfigMap <name>-efivars
+pub fn dummy_profile(name: &str) -> PlatformProfile {
+    PlatformProfile {
+        metadata: ObjectMeta {
+            name: Some(name.to_string()),
+            generation: Some(1),
+            ..Default::default()
+        },
+        spec: PlatformProfileSpec {
+            evidence_type: PlatformProfileEvidenceType::Tpm,
+            firmware: None,
+            efi_variables_config_map_name: Some(format!("{name}-efivars")),
+            mok_variables_config_map_name: None,
+        },
+    }
+}
+
+pub fn dummy_token_key_secret(rotated_at: Option<DateTime<Utc>>) -> Secret {
+    let (key, cert) = token_key::generate_token_key(1).unwrap();
+    Secret {
+        metadata: ObjectMeta {
+            name: Some(token_key::TOKEN_KEY_SECRET.to_string()),
+            annotations: rotated_at.map(token_key::rotation_annotation),
+            ..Default::default()
+        },
+        data: Some(token_key::token_key_data(key, cert.clone(), cert)),
+        ..Default::default()
+    }
+}
+
 pub fn dummy_trustee_map() -> ConfigMap {
     ConfigMap {
         data: Some(BTreeMap::from([(
@@ -73,3 +118,37 @@ pub fn dummy_pcrs_map() -> ConfigMap {
         ..Default::default()
     }
 }
+
+/// Per-image ConfigMaps of image PCRs
+pub fn image_pcr_maps(image_pcrs: ImagePcrs) -> ObjectList<ConfigMap> {
+    let items = image_pcrs
+        .0
+        .iter()
+        .map(|(name, image_pcr)| image_pcr_map(name, image_pcr, Default::default()).unwrap());
+    ObjectList {
+        items: items.collect(),
+        types: Default::default(),
+        metadata: Default::default(),
+    }
+}
+
+pub fn dummy_pcr_maps() -> ObjectList<ConfigMap> {
+    image_pcr_maps(dummy_pcrs())
+}
+
+pub fn dummy_clusters() -> ObjectList<TrustedExecutionCluster> {
+    ObjectList {
+        items: vec![dummy_cluster()],
+        types: Default::default(),
+        metadata: Default::default(),
+    }
+}
+
+/// Reference value history without any versions
+pub fn empty_rv_history() -> ObjectList<ConfigMap> {
+    ObjectList {
+        items: Vec::new(),
+        types: Default::default(),
+        metadata: Default::default(),
+    }
+}
@@ -0,0 +1,436 @@
+// SPDX-FileCopyrightText: Jakob Naucke <jnaucke@redhat.com>
+//
+// SPDX-License-Identifier: MIT
+
+// Management of the key that the attestation service signs attestation tokens with.
+// The key is kept in a Secret, mounted to Trustee and published as JWKS for relying parties.
+// On rotation, the certificate of the previous key stays trusted and published until the
+// next rotation, so that tokens issued shortly before a rotation remain verifiable.
+
+use anyhow::{Context, Result};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, TimeDelta, Utc};
+use k8s_openapi::ByteString;
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::core::v1::{ConfigMap, Secret, SecretVolumeSource, Volume, VolumeMount};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use kube::api::{ObjectMeta, Patch, PatchParams, PostParams};
+use kube::{Api, Client, Resource};
+use log::{info, warn};
+use openssl::asn1::{Asn1Integer, Asn1Time};
+use openssl::bn::{BigNum, BigNumContext, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::x509::{X509, X509NameBuilder};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use operator::{create_or_info_if_exists, generate_owner_reference};
+use trusted_cluster_operator_lib::endpoints::TRUSTEE_DEPLOYMENT;
+use trusted_cluster_operator_lib::get_opt_trusted_execution_cluster;
+
+pub const TOKEN_KEY_SECRET: &str = "attestation-token-key";
+pub const TOKEN_KEY_DIR: &str = "/etc/attestation-token";
+pub const TOKEN_KEY: &str = "token.key";
+pub const TOKEN_CERT: &str = "token.crt";
+pub const PREVIOUS_TOKEN_CERT: &str = "previous.crt";
+const TOKEN_JWKS_MAP: &str = "attestation-token-jwks";
+const TOKEN_JWKS_FILE: &str = "jwks.json";
+const ROTATED_AT_ANNOTATION: &str = "trusted-execution-clusters.io/token-key-rotated-at";
+
+const DEFAULT_ROTATION_DAYS: i32 = 30;
+/// Certificate validity if rotation is disabled
+const UNROTATED_VALIDITY_DAYS: i64 = 3650;
+const ROTATION_CHECK_INTERVAL: Duration = Duration::from_hours(1);
+
+fn cert_validity_days(rotation_days: i32) -> i64 {
+    match rotation_days {
+        // Cover the time as current and as previous key
+        days if days > 0 => 2 * i64::from(days),
+        _ => UNROTATED_VALIDITY_DAYS,
+    }
+}
+
+/// Generate an EC P-256 key and a self-signed certificate for it as PEM
+pub(crate) fn generate_token_key(validity_days: i64) -> Result<(Vec<u8>, Vec<u8>)> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    let key = PKey::from_ec_key(EcKey::generate(&group)?)?;
+
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, "trusted-cluster-attestation-token")?;
+    let name = name.build();
+
+    let mut serial = BigNum::new()?;
+    serial.rand(127, MsbOption::MAYBE_ZERO, false)?;
+    let mut cert = X509::builder()?;
+    cert.set_version(2)?;
+    cert.set_serial_number(Asn1Integer::from_bn(&serial)?.as_ref())?;
+    cert.set_subject_name(&name)?;
+    cert.set_issuer_name(&name)?;
+    cert.set_pubkey(&key)?;
+    let now = Utc::now().timestamp();
+    cert.set_not_before(Asn1Time::from_unix(now)?.as_ref())?;
+    let not_after = now + validity_days * 24 * 60 * 60;
+    cert.set_not_after(Asn1Time::from_unix(not_after)?.as_ref())?;
+    cert.sign(&key, MessageDigest::sha256())?;
+
+    let cert = cert.build().to_pem()?;
+    Ok((key.private_key_to_pem_pkcs8()?, cert))
+}
+
+/// Public JWK of the key in a PEM certificate, with the RFC 7638 thumbprint as key ID
+fn jwk_from_cert(cert: &[u8]) -> Result<serde_json::Value> {
+    let cert = X509::from_pem(cert)?;
+    let ec_key = cert.public_key()?.ec_key()?;
+    let mut ctx = BigNumContext::new()?;
+    let (mut x, mut y) = (BigNum::new()?, BigNum::new()?);
+    let point = ec_key.public_key();
+    point.affine_coordinates(ec_key.group(), &mut x, &mut y, &mut ctx)?;
+    let x = URL_SAFE_NO_PAD.encode(x.to_vec_padded(32)?);
+    let y = URL_SAFE_NO_PAD.encode(y.to_vec_padded(32)?);
+
+    // Members in lexicographic order, as required for the thumbprint
+    let thumbprint_input = format!(r#"{{"crv":"P-256","kty":"EC","x":"{x}","y":"{y}"}}"#);
+    let kid = URL_SAFE_NO_PAD.encode(openssl::sha::sha256(thumbprint_input.as_bytes()));
+    Ok(json!({
+        "kty": "EC",
+        "crv": "P-256",
+        "x": x,
+        "y": y,
+        "kid": kid,
+        "use": "sig",
+        "alg": "ES256",
+    }))
+}
+
+fn generate_jwks(secret: &Secret) -> Result<String> {
+    let data = secret
+        .data
+        .as_ref()
+        .context("Token key secret had no data")?;
+    let mut keys = vec![];
+    for name in [TOKEN_CERT, PREVIOUS_TOKEN_CERT] {
+        if let Some(cert) = data.get(name) {
+            let jwk = jwk_from_cert(&cert.0)?;
+            if !keys.contains(&jwk) {
+                keys.push(jwk);
+            }
+        }
+    }
+    Ok(serde_json::to_string(&json!({ "keys": keys }))?)
+}
+
+pub(crate) fn token_key_data(
+    key: Vec<u8>,
+    cert: Vec<u8>,
+    previous: Vec<u8>,
+) -> BTreeMap<String, ByteString> {
+    BTreeMap::from([
+        (TOKEN_KEY.to_string(), ByteString(key)),
+        (TOKEN_CERT.to_string(), ByteString(cert)),
+        (PREVIOUS_TOKEN_CERT.to_string(), ByteString(previous)),
+    ])
+}
+
+pub(crate) fn rotation_annotation(now: DateTime<Utc>) -> BTreeMap<String, String> {
+    BTreeMap::from([(ROTATED_AT_ANNOTATION.to_string(), now.to_rfc3339())])
+}
+
+pub async fn generate_token_key_secret(
+    client: Client,
+    owner_reference: OwnerReference,
+    rotation_days: Option<i32>,
+) -> Result<()> {
+    let rotation_days = rotation_days.unwrap_or(DEFAULT_ROTATION_DAYS);
+    let (key, cert) = generate_token_key(cert_validity_days(rotation_days))?;
+    // Without a previous key, trust the current certificate twice
+    let data = token_key_data(key, cert.clone(), cert);
+
+    let secret = Secret {
+        metadata: ObjectMeta {
+            name: Some(TOKEN_KEY_SECRET.to_string()),
+            owner_references: Some(vec![owner_reference]),
+            annotations: Some(rotation_annotation(Utc::now())),
+            ..Default::default()
+        },
+        data: Some(data),
+        ..Default::default()
+    };
+    create_or_info_if_exists!(client, Secret, secret);
+    Ok(())
+}
+
+pub fn generate_token_key_volume() -> (Volume, VolumeMount) {
+    (
+        Volume {
+            name: TOKEN_KEY_SECRET.to_string(),
+            secret: Some(SecretVolumeSource {
+                secret_name: Some(TOKEN_KEY_SECRET.to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        VolumeMount {
+            name: TOKEN_KEY_SECRET.to_string(),
+            mount_path: TOKEN_KEY_DIR.to_string(),
+            read_only: Some(true),
+            ..Default::default()
+        },
+    )
+}
+
+fn rotation_due(secret: &Secret, rotation_days: i32, now: DateTime<Utc>) -> bool {
+    if rotation_days <= 0 {
+        return false;
+    }
+    let annotations = secret.metadata.annotations.as_ref();
+    let rotated_at = annotations.and_then(|a| a.get(ROTATED_AT_ANNOTATION));
+    let rotated_at = rotated_at.and_then(|r| DateTime::parse_from_rfc3339(r).ok());
+    match rotated_at {
+        Some(rotated_at) => now - rotated_at.to_utc() >= TimeDelta::days(rotation_days.into()),
+        // Unknown age, rotate to get into a known state
+        None => true,
+    }
+}
+
+async fn rotate_token_key(secrets: &Api<Secret>, mut secret: Secret, days: i32) -> Result<Secret> {
+    let err = "Token key secret had no current certificate";
+    let data = secret.data.as_ref().context(err)?;
+    let previous = data.get(TOKEN_CERT).context(err)?.0.clone();
+    let (key, cert) = generate_token_key(cert_validity_days(days))?;
+
+    let now = Utc::now();
+    secret.data = Some(token_key_data(key, cert, previous));
+    let annotations = secret.metadata.annotations.get_or_insert_default();
+    annotations.extend(rotation_annotation(now));
+    // Replace carries the resource version, so a concurrent rotation results in a conflict
+    let secret = secrets
+        .replace(TOKEN_KEY_SECRET, &PostParams::default(), &secret)
+        .await?;
+    info!("Rotated attestation token signing key");
+    Ok(secret)
+}
+
+/// Trustee reads the key on startup, so restart it to pick up a rotated key
+async fn restart_trustee(client: Client, now: DateTime<Utc>) -> Result<()> {
+    let deployments: Api<Deployment> = Api::default_namespaced(client);
+    let patch = json!({
+        "spec": {
+            "template": {
+                "metadata": {
+                    "annotations": rotation_annotation(now)
+                }
+            }
+        }
+    });
+    deployments
+        .patch(
+            TRUSTEE_DEPLOYMENT,
+            &Default::default(),
+            &Patch::Merge(&patch),
+        )
+        .await?;
+    info!("Restarted {TRUSTEE_DEPLOYMENT} to use rotated attestation token signing key");
+    Ok(())
+}
+
+async fn publish_jwks(client: Client, secret: &Secret, owner: OwnerReference) -> Result<()> {
+    let jwks = generate_jwks(secret)?;
+    let config_map = ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(TOKEN_JWKS_MAP.to_string()),
+            owner_references: Some(vec![owner]),
+            ..Default::default()
+        },
+        data: Some(BTreeMap::from([(TOKEN_JWKS_FILE.to_string(), jwks)])),
+        ..Default::default()
+    };
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client);
+    let mut patch = serde_json::to_value(&config_map)?;
+    patch["apiVersion"] = ConfigMap::api_version(&()).into();
+    patch["kind"] = ConfigMap::kind(&()).into();
+    config_maps
+        .patch(
+            TOKEN_JWKS_MAP,
+            &PatchParams::apply("trusted-cluster-operator").force(),
+            &Patch::Apply(&patch),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Publish the trusted keys of the token key Secret, so that relying parties need not wait for
+/// the first rotation check after installation
+pub async fn publish_token_key_jwks(client: Client, owner: OwnerReference) -> Result<()> {
+    let secrets: Api<Secret> = Api::default_namespaced(client.clone());
+    let secret = secrets.get(TOKEN_KEY_SECRET).await?;
+    publish_jwks(client, &secret, owner).await
+}
+
+/// Rotate the token signing key if due and publish the trusted keys
+pub async fn reconcile_token_key(client: Client) -> Result<()> {
+    let Some(cluster) = get_opt_trusted_execution_cluster(client.clone()).await? else {
+        return Ok(());
+    };
+    let secrets: Api<Secret> = Api::default_namespaced(client.clone());
+    let Some(mut secret) = secrets.get_opt(TOKEN_KEY_SECRET).await? else {
+        info!("{TOKEN_KEY_SECRET} not found (yet), skipping token key reconciliation");
+        return Ok(());
+    };
+
+    let spec_days = cluster.spec.attestation_token_key_rotation_days;
+    let rotation_days = spec_days.unwrap_or(DEFAULT_ROTATION_DAYS);
+    let now = Utc::now();
+    if rotation_due(&secret, rotation_days, now) {
+        secret = rotate_token_key(&secrets, secret, rotation_days).await?;
+        restart_trustee(client.clone(), now).await?;
+    }
+    publish_jwks(client, &secret, generate_owner_reference(&cluster)?).await
+}
+
+pub async fn launch_token_key_rotation(client: Client) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ROTATION_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = reconcile_token_key(client.clone()).await {
+                warn!("Attestation token key reconciliation failed: {e:?}");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::dummy_token_key_secret;
+    use http::{Method, Request};
+    use kube::api::ObjectList;
+    use kube::client::Body;
+    use trusted_cluster_operator_test_utils::mock_client::*;
+
+    #[test]
+    fn test_kbs_config_uses_token_key() {
+        let config: toml::Table = toml::from_str(include_str!("kbs-config.toml")).unwrap();
+        let broker = &config["attestation_service"]["attestation_token_broker"];
+        let key_path = format!("{TOKEN_KEY_DIR}/{TOKEN_KEY}");
+        assert_eq!(
+            broker["signer"]["key_path"].as_str(),
+            Some(key_path.as_str())
+        );
+        let trusted = config["attestation_token"]["trusted_certs_paths"].as_array();
+        assert_eq!(trusted.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_jwks_deduplicates_initial_key() {
+        let secret = dummy_token_key_secret(None);
+        let jwks: serde_json::Value =
+            serde_json::from_str(&generate_jwks(&secret).unwrap()).unwrap();
+        let keys = jwks["keys"].as_array().unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0]["kty"], "EC");
+        assert_eq!(
+            URL_SAFE_NO_PAD
+                .decode(keys[0]["x"].as_str().unwrap())
+                .unwrap()
+                .len(),
+            32
+        );
+    }
+
+    #[test]
+    fn test_jwks_publishes_previous_key() {
+        let mut secret = dummy_token_key_secret(None);
+        let (_, other_cert) = generate_token_key(1).unwrap();
+        let data = secret.data.as_mut().unwrap();
+        data.insert(PREVIOUS_TOKEN_CERT.to_string(), ByteString(other_cert));
+        let jwks: serde_json::Value =
+            serde_json::from_str(&generate_jwks(&secret).unwrap()).unwrap();
+        assert_eq!(jwks["keys"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_rotation_due() {
+        let now = Utc::now();
+        let fresh = dummy_token_key_secret(Some(now - TimeDelta::days(1)));
+        assert!(!rotation_due(&fresh, 30, now));
+        let old = dummy_token_key_secret(Some(now - TimeDelta::days(31)));
+        assert!(rotation_due(&old, 30, now));
+        assert!(!rotation_due(&old, 0, now));
+        assert!(rotation_due(&dummy_token_key_secret(None), 30, now));
+    }
+
+    #[tokio::test]
+    async fn test_generate_token_key_secret_success() {
+        let clos = |client| generate_token_key_secret(client, Default::default(), None);
+        test_create_success::<_, _, Secret>(clos).await;
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_token_key_rotates() {
+        let rotated_at = Utc::now() - TimeDelta::days(31);
+        let secret = dummy_token_key_secret(Some(rotated_at));
+        let clos = move |req: Request<Body>, ctr| {
+            let secret = secret.clone();
+            async move {
+                match (ctr, req.method()) {
+                    (0, &Method::GET) => {
+                        let list = ObjectList {
+                            items: vec![dummy_cluster()],
+                            types: Default::default(),
+                            metadata: Default::default(),
+                        };
+                        Ok(serde_json::to_string(&list).unwrap())
+                    }
+                    (1, &Method::GET) => Ok(serde_json::to_string(&secret).unwrap()),
+                    (2, &Method::PUT) => {
+                        let body = get_body_string(req).await;
+                        let rotated: Secret = serde_json::from_str(&body).unwrap();
+                        let old_cert = &secret.data.unwrap()[TOKEN_CERT];
+                        let data = rotated.data.unwrap();
+                        assert_eq!(&data[PREVIOUS_TOKEN_CERT], old_cert);
+                        assert_ne!(&data[TOKEN_CERT], old_cert);
+                        Ok(body)
+                    }
+                    (3, &Method::PATCH) => {
+                        Ok(serde_json::to_string(&Deployment::default()).unwrap())
+                    }
+                    (4, &Method::PATCH) => {
+                        Ok(serde_json::to_string(&ConfigMap::default()).unwrap())
+                    }
+                    _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+                }
+            }
+        };
+        count_check!(5, clos, |client| {
+            assert!(reconcile_token_key(client).await.is_ok());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_publish_token_key_jwks() {
+        let secret = dummy_token_key_secret(None);
+        let clos = move |req: Request<Body>, ctr| {
+            let secret = secret.clone();
+            async move {
+                match (ctr, req.method()) {
+                    (0, &Method::GET) => Ok(serde_json::to_string(&secret).unwrap()),
+                    (1, &Method::PATCH) => {
+                        let body = get_body_string(req).await;
+                        assert!(body.contains(TOKEN_JWKS_FILE));
+                        Ok(serde_json::to_string(&ConfigMap::default()).unwrap())
+                    }
+                    _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+                }
+            }
+        };
+        count_check!(2, clos, |client| {
+            let owner = OwnerReference::default();
+            assert!(publish_token_key_jwks(client, owner).await.is_ok());
+        });
+    }
+}
@@ -4,18 +4,32 @@ import rego.v1
 
 default executables := 33
 
-## TPM validation
-executables := 3 if {
-  input.tpm.pcr04 in query_reference_value("tpm_pcr4")
-  input.tpm.pcr14 in query_reference_value("tpm_pcr14")
+# PCRs that only some images have reference values for, e.g. PCR7 of images with platform
+# profiles or PCR12 of images with kernel command lines, are only verified once there are any
+optional_pcr_valid(_, name) if not query_reference_value(name)
+optional_pcr_valid(value, name) if value in query_reference_value(name)
 
+# PCRs computed for a platform profile are prefixed with the profile name
+pcrs_valid(tpm, prefix) if {
+  tpm.pcr04 in query_reference_value(concat("", [prefix, "tpm_pcr4"]))
+  optional_pcr_valid(tpm.pcr07, concat("", [prefix, "tpm_pcr7"]))
+  optional_pcr_valid(tpm.pcr12, concat("", [prefix, "tpm_pcr12"]))
+  tpm.pcr14 in query_reference_value(concat("", [prefix, "tpm_pcr14"]))
 }
-# Azure SNP vTPM validation
-executables := 3 if {
-  input["az-snp-vtpm"].tpm.pcr04 in query_reference_value("tpm_pcr4")
-  input["az-snp-vtpm"].tpm.pcr14 in query_reference_value("tpm_pcr14")
+
+# Evidence is valid for PCRs without a profile, or for those of any platform profile of its
+# evidence type
+evidence_valid(_, tpm) if pcrs_valid(tpm, "")
+evidence_valid(evidence_type, tpm) if {
+  some profile in query_reference_value(concat("", ["platform_profiles_", evidence_type]))
+  pcrs_valid(tpm, concat("", [profile, "/"]))
 }
 
+## TPM validation
+executables := 3 if evidence_valid("tpm", input.tpm)
+# Azure SNP vTPM validation
+executables := 3 if evidence_valid("az-snp-vtpm", input["az-snp-vtpm"].tpm)
+
 default configuration := 0
 default hardware := 0
 default file_system := 0
@@ -0,0 +1,58 @@
+package policy
+
+import rego.v1
+
+# Run with `opa test operator/src/tpm.rego operator/src/tpm_test.rego`. Trustee provides
+# query_reference_value to attestation policies, here it reads the reference values below.
+query_reference_value(name) := reference_values[name]
+
+reference_values := {
+  "tpm_pcr4": ["04"],
+  "tpm_pcr14": ["14"],
+  "azure/tpm_pcr4": ["a4"],
+  "azure/tpm_pcr7": ["a7"],
+  "azure/tpm_pcr12": ["a12", "00"],
+  "azure/tpm_pcr14": ["a14"],
+  "platform_profiles_az-snp-vtpm": ["azure"],
+}
+
+image_tpm := {"pcr04": "04", "pcr07": "ff", "pcr12": "ff", "pcr14": "14"}
+
+azure_tpm := {"pcr04": "a4", "pcr07": "a7", "pcr12": "a12", "pcr14": "a14"}
+
+test_pcrs_valid_without_profile if {
+  # PCR7 and PCR12 have no reference values without a prefix and are not verified
+  pcrs_valid(image_tpm, "")
+}
+
+test_pcrs_invalid_pcr4 if {
+  not pcrs_valid(object.union(image_tpm, {"pcr04": "05"}), "")
+}
+
+test_pcrs_valid_profile if {
+  pcrs_valid(azure_tpm, "azure/")
+  pcrs_valid(object.union(azure_tpm, {"pcr12": "00"}), "azure/")
+}
+
+test_pcrs_invalid_profile_pcr7 if {
+  not pcrs_valid(object.union(azure_tpm, {"pcr07": "ff"}), "azure/")
+}
+
+test_pcrs_invalid_profile_pcr12 if {
+  not pcrs_valid(object.union(azure_tpm, {"pcr12": "ff"}), "azure/")
+}
+
+test_evidence_valid if {
+  evidence_valid("tpm", image_tpm)
+  evidence_valid("az-snp-vtpm", image_tpm)
+  evidence_valid("az-snp-vtpm", azure_tpm)
+}
+
+test_evidence_invalid_profile_of_other_evidence_type if {
+  not evidence_valid("tpm", azure_tpm)
+}
+
+test_executables if {
+  executables == 3 with input as {"az-snp-vtpm": {"tpm": azure_tpm}}
+  executables == 33 with input as {"tpm": azure_tpm}
+}
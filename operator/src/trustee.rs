@@ -5,10 +5,15 @@
 // SPDX-License-Identifier: MIT
 
 use crate::attestation_key_register::AkContextData;
-use anyhow::{Context, Result};
+use crate::conditions::pcr_combinations_condition;
+use crate::rv_history;
+use crate::rvps::{is_external_rvps, rvps_address};
+use crate::token_key::{TOKEN_KEY_DIR, generate_token_key_volume};
+use anyhow::{Context, Result, anyhow};
 use base64::{Engine as _, engine::general_purpose};
 use chrono::{DateTime, Utc};
 use clevis_pin_trustee_lib::Key as ClevisKey;
+use compute_pcrs_lib::Pcr;
 use compute_pcrs_lib::tpmevents::TPMEvent;
 use compute_pcrs_lib::tpmevents::combine::combine_images;
 use k8s_openapi::api::apps::v1::{Deployment, DeploymentSpec};
@@ -21,19 +26,33 @@ use k8s_openapi::apimachinery::pkg::{
     apis::meta::v1::{LabelSelector, OwnerReference},
     util::intstr::IntOrString,
 };
+use k8s_openapi::jiff::Timestamp;
 use kube::{
     Api, Client, Resource,
     api::{ObjectMeta, Patch, PatchParams},
     runtime::reflector::ObjectRef,
 };
 use log::info;
-use operator::{TLS_DIR, create_or_info_if_exists, read_certificate};
-use serde::{Serialize, Serializer};
+use operator::kbs_admin::{
+    self, ADMIN_PERSONA, ADMIN_PRIVATE_KEY, ADMIN_PUBLIC_KEY, KbsAdminClient, TRUSTEE_ADMIN_SECRET,
+    uses_admin_api,
+};
+use operator::upsert_condition;
+use operator::{TLS_DIR, create_or_info_if_exists, read_certificate, retry_on_conflict};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{Value::String as JsonString, json};
 use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
 use trusted_cluster_operator_lib::endpoints::*;
 use trusted_cluster_operator_lib::reference_values::*;
+use trusted_cluster_operator_lib::{
+    TrustedExecutionCluster, TrustedExecutionClusterSpec, TrustedExecutionClusterStatus,
+    TrustedExecutionClusterStatusPcrCombination, TrustedExecutionClusterTrustee,
+    generate_owner_reference, get_opt_trusted_execution_cluster, update_status,
+};
 
 const TRUSTEE_DATA_DIR: &str = "/opt/trustee";
 pub const TRUSTEE_SECRETS_PATH: &str = "/opt/trustee/kbs-repository/default";
@@ -44,6 +63,67 @@ pub(crate) const TRUSTEE_DATA_MAP: &str = "trustee-data";
 const ATT_POLICY_MAP: &str = "attestation-policy";
 const TRUSTED_AK_KEYS_VOLUME: &str = "trusted-ak-keys";
 const TRUSTED_AK_KEYS_DIR: &str = "/etc/tpm/trusted_ak_keys";
+const TRUSTEE_ADMIN_DIR: &str = "/etc/kbs-admin";
+const ATT_POLICY_DIR: &str = "/opt/trustee/policies/opa";
+const RVPS_STORAGE_VOLUME: &str = "rvps-storage";
+/// Volumes of the Trustee deployment whose presence depends on settings that may change after
+/// installation
+const SETTING_VOLUMES: [&str; 2] = [TRUSTEE_ADMIN_SECRET, RVPS_STORAGE_VOLUME];
+const KBS_CONFIG_HASH_ANNOTATION: &str = "trusted-execution-clusters.io/kbs-config-hash";
+const RV_HASH_ANNOTATION: &str = "trusted-execution-clusters.io/reference-values-hash";
+/// Records which reference values were registered with an external RVPS
+const RVPS_REGISTRATION_MAP: &str = "rvps-registration";
+const REGISTERED_NAMES_FILE: &str = "registered-names.json";
+
+const DEFAULT_LOG_LEVEL: &str = "debug";
+const LOG_LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+/// KBS plugin that serves machine secrets, configured by the operator only
+const RESOURCE_PLUGIN: &str = "resource";
+const DEFAULT_CPU_POLICY_ID: &str = "default_cpu";
+/// Default of maxPcrCombinations
+const DEFAULT_MAX_PCR_COMBINATIONS: u64 = 10_000;
+
+/// Reference value names, e.g. pcr4, mapped to their values
+type PcrValues = BTreeMap<String, BTreeSet<String>>;
+
+/// PCR values that the PCRs of images combine to, and how they were obtained
+#[derive(Clone)]
+struct PcrCombination {
+    values: PcrValues,
+    images: usize,
+    /// Number of combinations, or their estimate if it exceeded the ceiling
+    combinations: u64,
+    duration: Duration,
+    within_limit: bool,
+}
+
+impl PcrCombination {
+    fn message(&self) -> String {
+        let Self {
+            images,
+            combinations,
+            duration,
+            ..
+        } = self;
+        match self.within_limit {
+            true => format!(
+                "Combined PCRs of {images} images into {combinations} combinations in {duration:?}"
+            ),
+            false => format!(
+                "PCRs of {images} images would combine into an estimated {combinations} combinations, \
+                 exceeding maxPcrCombinations. Reference values only cover the PCRs of each image \
+                 by itself, so machines that boot components of different images fail attestation."
+            ),
+        }
+    }
+}
+
+/// Last PCR combination with a hash of its inputs
+type CombinationCache = Mutex<Option<(String, PcrCombination)>>;
+
+/// PCRs are only combined again when the valid images or the ceiling changed, not e.g. when a
+/// version is unpinned or the RVPS restarts
+static COMBINATION_CACHE: CombinationCache = Mutex::new(None);
 
 fn primitive_date_time_to_str<S>(d: &DateTime<Utc>, s: S) -> Result<S::Ok, S::Error>
 where
@@ -52,70 +132,538 @@ where
     s.serialize_str(&d.format("%Y-%m-%dT%H:%M:%SZ").to_string())
 }
 
+fn str_to_primitive_date_time<'de, D>(d: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(d)?;
+    let date_time = DateTime::parse_from_rfc3339(&s).map_err(serde::de::Error::custom)?;
+    Ok(date_time.with_timezone(&Utc))
+}
+
 /// Sync with Trustee
 /// reference_value_provider_service::reference_value::ReferenceValue
 /// (cannot import directly because its expiration doesn't serialize
 /// right)
-#[derive(Serialize)]
-struct ReferenceValue {
+#[derive(Deserialize, Serialize)]
+pub(crate) struct ReferenceValue {
     pub version: String,
     pub name: String,
-    #[serde(serialize_with = "primitive_date_time_to_str")]
+    #[serde(
+        serialize_with = "primitive_date_time_to_str",
+        deserialize_with = "str_to_primitive_date_time"
+    )]
     pub expiration: DateTime<Utc>,
     pub value: serde_json::Value,
 }
 
-pub fn get_image_pcrs(image_pcrs_map: ConfigMap) -> Result<ImagePcrs> {
-    let err = "Image PCRs map existed, but had no data";
-    let image_pcrs_data = image_pcrs_map.data.context(err)?;
-    let err = "Image PCRs data existed, but had no file";
-    let image_pcrs_str = image_pcrs_data.get(PCR_CONFIG_FILE).context(err)?;
-    serde_json::from_str(image_pcrs_str).map_err(Into::into)
-}
-
-fn recompute_reference_values(image_pcrs: ImagePcrs) -> Vec<ReferenceValue> {
-    let mut reference_values_in =
-        BTreeMap::from([("svn".to_string(), BTreeSet::from(["1".to_string()]))]);
-    let tpm_events: Vec<Vec<TPMEvent>> = image_pcrs
+/// Images whose reference values have not expired, without those whose kernel command lines
+/// cannot be verified
+fn valid_images(image_pcrs: &ImagePcrs, now: Timestamp) -> Vec<&ImagePcr> {
+    image_pcrs
         .0
         .values()
-        .map(|v| v.pcrs.iter().flat_map(|p| p.events.clone()).collect())
+        .filter(|v| v.expiration() > now && !v.unmeasured_command_lines())
+        .collect()
+}
+
+/// Estimate of the PCR combinations of images as an upper bound: Per PCR, the product of the
+/// distinct event hashes at each position of the event log, summed over PCRs
+fn combination_estimate(tpm_events: &[Vec<TPMEvent>]) -> u64 {
+    let mut variants = BTreeMap::new();
+    for events in tpm_events {
+        let mut positions = BTreeMap::new();
+        for event in events {
+            let position = positions.entry(event.pcr).or_insert(0);
+            let hashes: &mut BTreeSet<_> = variants.entry((event.pcr, *position)).or_default();
+            hashes.insert(&event.hash);
+            *position += 1;
+        }
+    }
+    let mut estimates = BTreeMap::new();
+    for ((pcr, _), hashes) in &variants {
+        let estimate: &mut u64 = estimates.entry(*pcr).or_insert(1);
+        *estimate = estimate.saturating_mul(hashes.len() as u64);
+    }
+    estimates.values().fold(0, |sum, e| sum.saturating_add(*e))
+}
+
+/// Event logs of images grouped by platform profile and architecture. Components only combine
+/// within a group, and PCRs without a profile form groups of their own. PCRs of an unknown
+/// architecture, stored by earlier versions, are combined with every architecture without a
+/// profile.
+fn platform_events<'a>(images: &[&'a ImagePcr]) -> Vec<(Option<&'a str>, Vec<Vec<TPMEvent>>)> {
+    let events =
+        |pcrs: &[Pcr]| -> Vec<TPMEvent> { pcrs.iter().flat_map(|p| p.events.clone()).collect() };
+    let mut unknown = Vec::new();
+    let mut groups: BTreeMap<_, Vec<Vec<TPMEvent>>> = BTreeMap::new();
+    for (architecture, platform, pcrs) in images.iter().flat_map(|image| image.platform_pcrs()) {
+        let group = match architecture {
+            Some(architecture) => groups.entry((platform, architecture)).or_default(),
+            None => &mut unknown,
+        };
+        group.push(events(&pcrs));
+    }
+    if !groups.keys().any(|(platform, _)| platform.is_none()) {
+        let unknown = (groups.is_empty() || !unknown.is_empty()).then_some((None, unknown));
+        return groups
+            .into_iter()
+            .map(|((platform, _), group)| (platform, group))
+            .chain(unknown)
+            .collect();
+    }
+    let groups = groups
+        .into_iter()
+        .map(|((platform, _), group)| match platform {
+            Some(_) => (platform, group),
+            None => (platform, group.into_iter().chain(unknown.clone()).collect()),
+        });
+    groups.collect()
+}
+
+/// Name of the reference value of PCR `id`, namespaced by `platform` if the PCR was computed for a
+/// platform profile
+fn pcr_name(platform: Option<&str>, id: impl std::fmt::Display) -> String {
+    match platform {
+        Some(platform) => format!("{platform}/pcr{id}"),
+        None => format!("pcr{id}"),
+    }
+}
+
+/// Combine the PCRs of images unless their estimated combinations exceed `max_combinations`, in
+/// which case each image only contributes its own PCR values
+fn combine_pcrs(
+    images: &[&ImagePcr],
+    max_combinations: u64,
+    cache: &CombinationCache,
+) -> PcrCombination {
+    let pcrs: Vec<_> = images
+        .iter()
+        .map(|image| {
+            (
+                &image.pcrs,
+                &image.architectures,
+                &image.profile_pcrs,
+                &image.kernel_cmdline_pcrs,
+            )
+        })
         .collect();
+    let inputs = serde_json::to_vec(&(max_combinations, &pcrs)).unwrap_or_default();
+    let inputs_hash = hex::encode(openssl::sha::sha256(&inputs));
+    let mut cache = cache.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((hash, combination)) = cache.as_ref()
+        && *hash == inputs_hash
+    {
+        return combination.clone();
+    }
 
-    let pcr_combinations = combine_images(&tpm_events);
-    for pcr in pcr_combinations.iter().flatten() {
-        reference_values_in
-            .entry(format!("pcr{}", pcr.id))
+    let start = Instant::now();
+    let groups = platform_events(images);
+    let estimates = groups
+        .iter()
+        .map(|(_, events)| combination_estimate(events));
+    let estimate = estimates.fold(0, u64::saturating_add);
+    let within_limit = estimate <= max_combinations;
+    let mut values = PcrValues::new();
+    let mut insert = |platform: Option<&str>, pcr: &Pcr| {
+        let name = pcr_name(platform, pcr.id);
+        values
+            .entry(name)
             .or_default()
             .insert(hex::encode(&pcr.value));
+    };
+    let combinations = if within_limit {
+        let mut combinations = 0;
+        for (platform, events) in &groups {
+            let pcr_combinations = combine_images(events);
+            for pcr in pcr_combinations.iter().flatten() {
+                insert(*platform, pcr);
+            }
+            combinations += pcr_combinations.len() as u64;
+        }
+        combinations
+    } else {
+        for (_, platform, pcrs) in images.iter().flat_map(|image| image.platform_pcrs()) {
+            pcrs.iter().for_each(|pcr| insert(platform, pcr));
+        }
+        estimate
+    };
+    // Command lines do not change with updates of boot components, but are only valid on the
+    // platforms of the images that allowed them. Once PCR12 is verified, machines of images
+    // without measured command lines keep it unextended.
+    let unmeasured = [unmeasured_cmdline_pcr()];
+    let measured = images
+        .iter()
+        .any(|image| !image.kernel_cmdline_pcrs.is_empty());
+    for image in images {
+        let cmdline_pcrs = match image.kernel_cmdline_pcrs.as_slice() {
+            [] if measured => &unmeasured[..],
+            pcrs => pcrs,
+        };
+        let platform_pcrs = image.platform_pcrs();
+        let platforms: BTreeSet<_> = platform_pcrs.iter().map(|(_, p, _)| *p).collect();
+        for platform in platforms {
+            cmdline_pcrs.iter().for_each(|pcr| insert(platform, pcr));
+        }
     }
-    reference_values_in
+    let combination = PcrCombination {
+        values,
+        images: images.len(),
+        combinations,
+        duration: start.elapsed(),
+        within_limit,
+    };
+    info!("{}", combination.message());
+    *cache = Some((inputs_hash, combination.clone()));
+    combination
+}
+
+/// Reference values combine all images, so they remain valid as long as any image does
+fn reference_values_expiration(valid_images: &[&ImagePcr], now: Timestamp) -> DateTime<Utc> {
+    let expiration = valid_images.iter().map(|v| v.expiration()).max();
+    let expiration = expiration.unwrap_or(now + DEFAULT_IMAGE_VALIDITY);
+    DateTime::from_timestamp(expiration.as_second(), 0).unwrap_or_default()
+}
+
+/// Refresh the expiration of reference values from a pinned version to that of the still valid
+/// ApprovedImages that contributed to it, as if they were recomputed. Returns whether any such
+/// image remained, i.e. whether the expiration is stable.
+fn refresh_pinned_expiration(
+    reference_values: &mut [ReferenceValue],
+    images: &BTreeSet<String>,
+    image_pcrs: &ImagePcrs,
+) -> bool {
+    let now = Timestamp::now();
+    let valid_images: Vec<_> = image_pcrs
+        .0
         .iter()
+        .filter(|(name, image)| images.contains(*name) && image.expiration() > now)
+        .map(|(_, image)| image)
+        .collect();
+    let expiration = reference_values_expiration(&valid_images, now);
+    for reference_value in reference_values.iter_mut() {
+        reference_value.expiration = expiration;
+    }
+    !valid_images.is_empty()
+}
+
+fn recompute_reference_values(
+    image_pcrs: &ImagePcrs,
+    max_combinations: u64,
+    cache: &CombinationCache,
+) -> (Vec<ReferenceValue>, PcrCombination) {
+    let now = Timestamp::now();
+    let mut reference_values_in =
+        BTreeMap::from([("svn".to_string(), BTreeSet::from(["1".to_string()]))]);
+    // Expired images are disallowed by the ApprovedImage controller, but must not be trusted in
+    // the meantime either
+    let valid_images = valid_images(image_pcrs, now);
+    let expiration = reference_values_expiration(&valid_images, now);
+
+    let combination = combine_pcrs(&valid_images, max_combinations, cache);
+    reference_values_in.extend(combination.values.clone());
+    let tpm_name = |name: &String| match name.split_once('/') {
+        Some((platform, pcr)) => format!("{platform}/tpm_{pcr}"),
+        None => format!("tpm_{name}"),
+    };
+    let mut reference_values_in: BTreeMap<_, _> = reference_values_in
+        .into_iter()
+        .map(|(name, values)| (tpm_name(&name), values))
+        .collect();
+    // The attestation policy verifies evidence against the PCRs of each platform profile of its
+    // evidence type
+    for image in &valid_images {
+        for (profile, record) in &image.profiles {
+            let name = format!("platform_profiles_{}", record.evidence_type);
+            let profiles = reference_values_in.entry(name).or_default();
+            profiles.insert(profile.clone());
+        }
+    }
+    let reference_values = reference_values_in
+        .into_iter()
         .map(|(name, values)| ReferenceValue {
             version: "0.1.0".to_string(),
-            name: format!("tpm_{name}"),
-            expiration: Utc::now() + chrono::Duration::days(365),
+            name,
+            expiration,
             value: serde_json::Value::Array(values.iter().map(|v| JsonString(v.clone())).collect()),
         })
-        .collect()
+        .collect();
+    (reference_values, combination)
 }
 
-pub async fn update_reference_values(client: Client) -> Result<()> {
-    let config_maps: Api<ConfigMap> = Api::default_namespaced(client);
+/// Report a PCR combination in the TrustedExecutionCluster status unless it is already reported
+async fn update_combination_status(
+    client: Client,
+    cluster: &TrustedExecutionCluster,
+    combination: &PcrCombination,
+) -> Result<()> {
+    let existing_status = &cluster.status;
+    let condition = pcr_combinations_condition(
+        combination.within_limit,
+        combination.message(),
+        cluster.metadata.generation,
+        existing_status,
+    );
+    let mut conditions = existing_status.as_ref().and_then(|s| s.conditions.clone());
+    let changed = upsert_condition(&mut conditions, condition);
+    let pcr_combination = TrustedExecutionClusterStatusPcrCombination {
+        images: i32::try_from(combination.images)?,
+        combinations: i64::try_from(combination.combinations).unwrap_or(i64::MAX),
+        duration_millis: i64::try_from(combination.duration.as_millis())?,
+    };
+    let fields = |c: &TrustedExecutionClusterStatusPcrCombination| {
+        (c.images, c.combinations, c.duration_millis)
+    };
+    let existing = existing_status
+        .as_ref()
+        .and_then(|s| s.pcr_combination.as_ref());
+    if !changed && existing.map(fields) == Some(fields(&pcr_combination)) {
+        return Ok(());
+    }
+    let clusters: Api<TrustedExecutionCluster> = Api::default_namespaced(client);
+    let name = cluster.metadata.name.as_deref();
+    let name = name.context("TrustedExecutionCluster had no name")?;
+    let status = TrustedExecutionClusterStatus {
+        conditions,
+        pcr_combination: Some(pcr_combination),
+    };
+    update_status!(clusters, name, status)?;
+    Ok(())
+}
 
-    let image_pcrs_map = config_maps.get(PCR_CONFIG_MAP).await?;
-    let reference_values = recompute_reference_values(get_image_pcrs(image_pcrs_map)?);
-    let rv_json = serde_json::to_string(&reference_values)?;
+/// Hash of reference values to detect whether they changed. Without valid images, the expiration
+/// is counted from now and thus excluded.
+fn reference_values_hash(reference_values: &[ReferenceValue], stable_expiration: bool) -> String {
+    let content: Vec<_> = reference_values
+        .iter()
+        .map(|rv| {
+            let expiration = stable_expiration.then_some(rv.expiration.timestamp());
+            (&rv.version, &rv.name, &rv.value, expiration)
+        })
+        .collect();
+    let content = serde_json::to_vec(&content).unwrap_or_default();
+    hex::encode(openssl::sha::sha256(&content))
+}
 
+/// Write reference values to the Trustee data ConfigMap unless they are unchanged. Returns
+/// whether they were written.
+async fn write_trustee_reference_values(
+    config_maps: &Api<ConfigMap>,
+    rv_json: &str,
+    rv_hash: &str,
+) -> Result<bool> {
     let mut trustee_map = config_maps.get(TRUSTEE_DATA_MAP).await?;
+    let annotations = trustee_map.metadata.annotations.get_or_insert_default();
+    if annotations.get(RV_HASH_ANNOTATION).map(String::as_str) == Some(rv_hash) {
+        return Ok(false);
+    }
+    annotations.insert(RV_HASH_ANNOTATION.to_string(), rv_hash.to_string());
     let err = format!("ConfigMap {TRUSTEE_DATA_MAP} existed, but had no data");
     let trustee_data = trustee_map.data.as_mut().context(err)?;
-    trustee_data.insert(REFERENCE_VALUES_FILE.to_string(), rv_json);
+    trustee_data.insert(REFERENCE_VALUES_FILE.to_string(), rv_json.to_string());
 
+    // Fails with a conflict if the map changed since it was read
     config_maps
         .replace(TRUSTEE_DATA_MAP, &Default::default(), &trustee_map)
         .await?;
-    info!("Recomputed reference values");
+    Ok(true)
+}
+
+/// Names and hash of the reference values that were last registered with an external RVPS
+async fn registered_reference_values(
+    config_maps: &Api<ConfigMap>,
+) -> Result<(BTreeSet<String>, Option<String>)> {
+    let Some(config_map) = config_maps.get_opt(RVPS_REGISTRATION_MAP).await? else {
+        return Ok(Default::default());
+    };
+    let annotations = config_map.metadata.annotations.as_ref();
+    let rv_hash = annotations.and_then(|a| a.get(RV_HASH_ANNOTATION)).cloned();
+    let data = config_map.data.as_ref();
+    let names = data.and_then(|d| d.get(REGISTERED_NAMES_FILE));
+    let names = names.map(|n| serde_json::from_str(n)).transpose()?;
+    Ok((names.unwrap_or_default(), rv_hash))
+}
+
+/// Remember the names and hash of reference values registered with an external RVPS, so that
+/// unchanged reference values are not registered again and names which leave the set can be
+/// withdrawn later
+async fn record_registered_reference_values(
+    config_maps: &Api<ConfigMap>,
+    reference_values: &[ReferenceValue],
+    rv_hash: &str,
+    owner_reference: OwnerReference,
+) -> Result<()> {
+    let names: BTreeSet<_> = reference_values.iter().map(|rv| &rv.name).collect();
+    let config_map = ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(RVPS_REGISTRATION_MAP.to_string()),
+            annotations: Some(BTreeMap::from([(
+                RV_HASH_ANNOTATION.to_string(),
+                rv_hash.to_string(),
+            )])),
+            owner_references: Some(vec![owner_reference]),
+            ..Default::default()
+        },
+        data: Some(BTreeMap::from([(
+            REGISTERED_NAMES_FILE.to_string(),
+            serde_json::to_string(&names)?,
+        )])),
+        ..Default::default()
+    };
+    let mut patch = serde_json::to_value(&config_map)?;
+    patch["apiVersion"] = ConfigMap::api_version(&()).into();
+    patch["kind"] = ConfigMap::kind(&()).into();
+    config_maps
+        .patch(
+            RVPS_REGISTRATION_MAP,
+            &PatchParams::apply("trusted-cluster-operator").force(),
+            &Patch::Apply(&patch),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Register reference values with an external RVPS unless they are unchanged, or always when
+/// `reregister`, e.g. because the RVPS lost them on a restart. Returns whether they changed.
+async fn register_reference_values(
+    client: Client,
+    cluster: &TrustedExecutionCluster,
+    reference_values: &[ReferenceValue],
+    rv_hash: &str,
+    reregister: bool,
+) -> Result<bool> {
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client.clone());
+    let (registered, registered_hash) = registered_reference_values(&config_maps).await?;
+    let changed = registered_hash.as_deref() != Some(rv_hash);
+    if !changed && !reregister {
+        return Ok(false);
+    }
+    let admin_client = KbsAdminClient::new(client, cluster).await?;
+    let message = rvps_sample_message(reference_values, &registered)?;
+    admin_client.set_reference_values(&message).await?;
+    let owner_reference = generate_owner_reference(cluster)?;
+    record_registered_reference_values(&config_maps, reference_values, rv_hash, owner_reference)
+        .await?;
+    Ok(changed)
+}
+
+/// RVPS message for the sample provider. Its payload is a base64-encoded list of reference values
+/// in the RVPS's own format, so that their expiration is carried along. Names that were
+/// `registered` before, but are no longer present, are withdrawn by registering them without
+/// values.
+fn rvps_sample_message(
+    reference_values: &[ReferenceValue],
+    registered: &BTreeSet<String>,
+) -> Result<serde_json::Value> {
+    let present: BTreeSet<_> = reference_values.iter().map(|rv| &rv.name).collect();
+    let expiration = reference_values
+        .first()
+        .map_or_else(Utc::now, |rv| rv.expiration);
+    let withdrawn: Vec<_> = registered
+        .iter()
+        .filter(|name| !present.contains(name))
+        .map(|name| ReferenceValue {
+            version: "0.1.0".to_string(),
+            name: name.clone(),
+            expiration,
+            value: serde_json::Value::Array(vec![]),
+        })
+        .collect();
+    let values: Vec<_> = reference_values.iter().chain(&withdrawn).collect();
+    let payload = general_purpose::STANDARD.encode(serde_json::to_vec(&values)?);
+    Ok(json!({
+        "version": "0.1.0",
+        "type": "sample",
+        "payload": payload,
+    }))
+}
+
+/// Recompute reference values and hand them to Trustee. With `reregister`, an external RVPS gets
+/// them registered even if they are unchanged.
+pub async fn update_reference_values(client: Client, reregister: bool) -> Result<()> {
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client.clone());
+
+    let image_pcrs = list_image_pcrs(&config_maps).await?;
+
+    let cluster = get_opt_trusted_execution_cluster(client.clone()).await?;
+    let external_trustee = cluster
+        .as_ref()
+        .is_some_and(|c| c.spec.external_trustee.is_some());
+    let pinned_version = cluster
+        .as_ref()
+        .and_then(|c| c.spec.reference_values_version);
+    let max_combinations = cluster.as_ref().and_then(|c| c.spec.max_pcr_combinations);
+    let max_combinations = max_combinations.map(u64::try_from).transpose()?;
+    let max_combinations = max_combinations.unwrap_or(DEFAULT_MAX_PCR_COMBINATIONS);
+    let (reference_values, stable_expiration, combination) = match pinned_version {
+        Some(version) => {
+            info!("Using reference values of pinned version {version}");
+            let version = u32::try_from(version)?;
+            let (mut reference_values, images) =
+                rv_history::get_version(client.clone(), version).await?;
+            let stable_expiration =
+                refresh_pinned_expiration(&mut reference_values, &images, &image_pcrs);
+            (reference_values, stable_expiration, None)
+        }
+        None => {
+            let (reference_values, combination) =
+                recompute_reference_values(&image_pcrs, max_combinations, &COMBINATION_CACHE);
+            let has_valid_images = combination.images > 0;
+            (reference_values, has_valid_images, Some(combination))
+        }
+    };
+    let rv_json = serde_json::to_string(&reference_values)?;
+    let rv_hash = reference_values_hash(&reference_values, stable_expiration);
+
+    // An external Trustee has no ConfigMap of ours. Otherwise, the ConfigMap remains the record
+    // of reference values.
+    let mut changed = false;
+    if !external_trustee {
+        let write = async || write_trustee_reference_values(&config_maps, &rv_json, &rv_hash).await;
+        if retry_on_conflict(write).await? {
+            info!("Recomputed reference values");
+            changed = true;
+        } else {
+            info!("Reference values were unchanged");
+        }
+    }
+
+    // An external Trustee or RVPS gets reference values registered directly
+    if let Some(cluster) = &cluster
+        && (external_trustee || is_external_rvps(&cluster.spec))
+    {
+        let register = register_reference_values(
+            client.clone(),
+            cluster,
+            &reference_values,
+            &rv_hash,
+            reregister,
+        );
+        if register.await? {
+            info!("Registered reference values with the RVPS");
+            changed = true;
+        } else if reregister {
+            info!("Registered unchanged reference values with the RVPS again");
+        } else {
+            info!("Reference values registered with the RVPS were unchanged");
+        }
+    }
+
+    // A pinned version is already in the history, and unchanged values are its latest version
+    if pinned_version.is_none() && changed {
+        let owner_reference = cluster.as_ref().map(generate_owner_reference).transpose()?;
+        rv_history::record(
+            client.clone(),
+            owner_reference,
+            &image_pcrs,
+            &reference_values,
+        )
+        .await?;
+    }
+
+    if let (Some(cluster), Some(combination)) = (&cluster, &combination) {
+        update_combination_status(client, cluster, combination).await?;
+    }
     Ok(())
 }
 
@@ -173,15 +721,19 @@ pub async fn do_mount_secret(client: Client, id: &str, add: bool) -> Result<()>
     let container = pod_spec.containers.get_mut(0).context(err)?;
     let vol_mounts = container.volume_mounts.get_or_insert_default();
 
+    let volumes = pod_spec.volumes.get_or_insert_default();
+    if add && volumes.iter().any(|v| v.name == id) {
+        info!("Secret {id} was to be mounted, but volume already existed");
+        return Ok(());
+    }
+
     if add {
         let (volume, volume_mount) = generate_secret_volume(id);
-        pod_spec.volumes.get_or_insert_default().push(volume);
+        volumes.push(volume);
         vol_mounts.push(volume_mount);
     } else {
-        let vol_result = pod_spec.volumes.as_mut().and_then(|vs| {
-            let pos = vs.iter().position(|v| v.name == id);
-            pos.map(|p| vs.swap_remove(p))
-        });
+        let pos = volumes.iter().position(|v| v.name == id);
+        let vol_result = pos.map(|p| volumes.swap_remove(p));
         if vol_result.is_none() {
             info!("Secret {id} was to be dropped, but volume had already been removed");
         }
@@ -200,6 +752,95 @@ pub async fn do_mount_secret(client: Client, id: &str, add: bool) -> Result<()>
     Ok(())
 }
 
+fn machine_resource_path(id: &str) -> String {
+    // Matches the layout of secrets mounted to TRUSTEE_SECRETS_PATH
+    format!("default/{id}/root")
+}
+
+/// Deliver the secret of a machine to Trustee. With the admin API enabled or an external Trustee,
+/// the secret is written through the KBS resource endpoint, so that Trustee is not restarted for
+/// every machine. Otherwise, the secret is mounted to the Trustee deployment.
+pub async fn deliver_secret(
+    client: Client,
+    cluster: &TrustedExecutionCluster,
+    id: &str,
+) -> Result<()> {
+    if !uses_admin_api(&cluster.spec) {
+        return mount_secret(client, id).await;
+    }
+    let secrets: Api<Secret> = Api::default_namespaced(client.clone());
+    let secret = secrets.get(id).await?;
+    let err = format!("Secret {id} had no root key");
+    let root = secret
+        .data
+        .as_ref()
+        .and_then(|d| d.get("root"))
+        .context(err)?;
+    let admin_client = KbsAdminClient::new(client, cluster).await?;
+    admin_client
+        .set_resource(&machine_resource_path(id), &root.0)
+        .await?;
+    info!("Pushed secret {id} to Trustee");
+    Ok(())
+}
+
+/// Counterpart to `deliver_secret`
+pub async fn withdraw_secret(
+    client: Client,
+    cluster: &TrustedExecutionCluster,
+    id: &str,
+) -> Result<()> {
+    if !uses_admin_api(&cluster.spec) {
+        return unmount_secret(client, id).await;
+    }
+    let admin_client = KbsAdminClient::new(client, cluster).await?;
+    admin_client
+        .delete_resource(&machine_resource_path(id))
+        .await?;
+    info!("Deleted secret {id} from Trustee");
+    Ok(())
+}
+
+fn attestation_key_resource_path(secret_name: &str) -> String {
+    format!("default/{TRUSTED_AK_KEYS_VOLUME}/{secret_name}.pub")
+}
+
+/// Deliver the public key of an AttestationKey Secret to an external Trustee through the KBS
+/// resource endpoint. Its TPM verifier is expected to trust the keys in the
+/// `default/trusted-ak-keys` directory of the KBS repository.
+pub async fn deliver_attestation_key(
+    client: Client,
+    cluster: &TrustedExecutionCluster,
+    secret: &Secret,
+) -> Result<()> {
+    let name = secret.metadata.name.as_deref();
+    let name = name.context("AttestationKey Secret had no name")?;
+    let err = format!("Secret {name} had no public_key");
+    let data = secret.data.as_ref();
+    let public_key = data.and_then(|d| d.get("public_key")).context(err)?;
+    let admin_client = KbsAdminClient::new(client, cluster).await?;
+    admin_client
+        .set_resource(&attestation_key_resource_path(name), &public_key.0)
+        .await?;
+    info!("Pushed attestation key {name} to the external Trustee");
+    Ok(())
+}
+
+/// Counterpart to `deliver_attestation_key`
+pub async fn withdraw_attestation_key(
+    client: Client,
+    cluster: &TrustedExecutionCluster,
+    secret_name: &str,
+) -> Result<()> {
+    let admin_client = KbsAdminClient::new(client, cluster).await?;
+    admin_client
+        .delete_resource(&attestation_key_resource_path(secret_name))
+        .await?;
+    info!("Deleted attestation key {secret_name} from the external Trustee");
+    Ok(())
+}
+
+/// Mount the public keys of all AttestationKey Secrets to the Trustee deployment
 pub async fn update_attestation_keys(ctx: &AkContextData) -> Result<()> {
     let client = &ctx.client;
     let ak_secrets: Vec<String> = ctx
@@ -364,6 +1005,32 @@ pub async fn generate_secret(
     Ok(())
 }
 
+pub async fn generate_admin_secret(client: Client, owner_reference: OwnerReference) -> Result<()> {
+    let (private_key, public_key) = kbs_admin::generate_admin_keypair()?;
+    let data = BTreeMap::from([
+        (
+            ADMIN_PRIVATE_KEY.to_string(),
+            k8s_openapi::ByteString(private_key),
+        ),
+        (
+            ADMIN_PUBLIC_KEY.to_string(),
+            k8s_openapi::ByteString(public_key),
+        ),
+    ]);
+
+    let secret = Secret {
+        metadata: ObjectMeta {
+            name: Some(TRUSTEE_ADMIN_SECRET.to_string()),
+            owner_references: Some(vec![owner_reference]),
+            ..Default::default()
+        },
+        data: Some(data),
+        ..Default::default()
+    };
+    create_or_info_if_exists!(client, Secret, secret);
+    Ok(())
+}
+
 pub async fn generate_attestation_policy(
     client: Client,
     owner_reference: OwnerReference,
@@ -388,16 +1055,221 @@ pub async fn generate_attestation_policy(
     Ok(())
 }
 
-fn generate_kbs_config(has_certificate: bool) -> Result<String> {
+/// Set up an external Trustee with the policies that the operator would deploy its own Trustee
+/// with, and register the current reference values
+pub async fn configure_external_trustee(
+    client: Client,
+    cluster: &TrustedExecutionCluster,
+) -> Result<()> {
+    let admin_client = KbsAdminClient::new(client.clone(), cluster).await?;
+    let attestation_policy = include_str!("tpm.rego");
+    admin_client
+        .set_attestation_policy(DEFAULT_CPU_POLICY_ID, attestation_policy)
+        .await?;
+    info!("Set the attestation policy of the external Trustee");
+    admin_client
+        .set_resource_policy(include_str!("resource.rego"))
+        .await?;
+    info!("Set the resource policy of the external Trustee");
+    update_reference_values(client, false).await
+}
+
+fn validate_log_level(log_level: &str) -> Result<()> {
+    for directive in log_level.split(',') {
+        let (target, level) = match directive.split_once('=') {
+            Some((target, level)) => (Some(target), level),
+            None => (None, directive),
+        };
+        if target.is_some_and(str::is_empty) || !LOG_LEVELS.contains(&level.to_lowercase().as_str())
+        {
+            return Err(anyhow!(
+                "logLevel directive {directive:?} is not of the form [target=]level \
+                 with level one of {LOG_LEVELS:?}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn validate_rvps_storage_path(path: &str) -> Result<()> {
+    let path = Path::new(path);
+    let err = format!("rvpsStoragePath {path:?} must be an absolute file path");
+    let parent = path
+        .parent()
+        .filter(|_| path.is_absolute())
+        .context(err.clone())?;
+    path.file_name().context(err.clone())?;
+    if parent == Path::new("/") {
+        return Err(anyhow!(err));
+    }
+    if path == Path::new(TRUSTEE_DATA_DIR).join(REFERENCE_VALUES_FILE) {
+        return Ok(());
+    }
+    let managed_dirs = [
+        TRUSTEE_DATA_DIR,
+        ATT_POLICY_DIR,
+        TRUSTEE_SECRETS_PATH,
+        TLS_DIR,
+        TOKEN_KEY_DIR,
+        TRUSTEE_ADMIN_DIR,
+        TRUSTED_AK_KEYS_DIR,
+    ];
+    if managed_dirs.iter().any(|dir| parent == Path::new(dir)) {
+        let err = format!("rvpsStoragePath {path:?} is in a directory managed by the operator");
+        return Err(anyhow!(err));
+    }
+    Ok(())
+}
+
+fn validate_external_trustee_address(address: &str) -> Result<()> {
+    let err = format!("externalTrustee address {address:?} must be of the form host:port");
+    let (host, port) = address.rsplit_once(':').context(err.clone())?;
+    if host.is_empty() || host.contains('/') || port.parse::<u16>().is_err() {
+        return Err(anyhow!(err));
+    }
+    Ok(())
+}
+
+/// Validate the Trustee settings of a TrustedExecutionCluster beyond what the CRD schema covers
+pub fn validate_trustee_config(spec: &TrustedExecutionClusterSpec) -> Result<()> {
+    if let Some(external) = &spec.external_trustee {
+        if spec.trustee.is_some() {
+            return Err(anyhow!("trustee cannot be combined with externalTrustee"));
+        }
+        validate_external_trustee_address(&external.address)?;
+    }
+    let Some(trustee) = &spec.trustee else {
+        return Ok(());
+    };
+    if trustee.external_rvps == Some(true) && spec.trustee_admin_api != Some(true) {
+        return Err(anyhow!("externalRvps requires trusteeAdminApi"));
+    }
+    if let Some(duration) = trustee.token_duration_minutes
+        && duration < 1
+    {
+        return Err(anyhow!(
+            "tokenDurationMinutes must be positive, got {duration}"
+        ));
+    }
+    if let Some(token_type) = &trustee.token_type
+        && (token_type.is_empty() || !token_type.chars().all(|c| c.is_ascii_alphanumeric()))
+    {
+        return Err(anyhow!("tokenType {token_type:?} is not alphanumeric"));
+    }
+    if let Some(path) = &trustee.rvps_storage_path {
+        validate_rvps_storage_path(path)?;
+    }
+
+    let mut names = BTreeSet::new();
+    for plugin in trustee.plugins.iter().flatten() {
+        let name = &plugin.name;
+        if name.is_empty() {
+            return Err(anyhow!("Plugin names must not be empty"));
+        }
+        if name == RESOURCE_PLUGIN {
+            return Err(anyhow!("Plugin {name} is managed by the operator"));
+        }
+        if !names.insert(name) {
+            return Err(anyhow!("Plugin {name} is configured more than once"));
+        }
+        if plugin
+            .settings
+            .iter()
+            .flatten()
+            .any(|(key, _)| key == "name")
+        {
+            return Err(anyhow!("Plugin {name} must not set name in settings"));
+        }
+    }
+
+    if let Some(log_level) = &trustee.log_level {
+        validate_log_level(log_level)?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct KbsConfigOptions {
+    has_certificate: bool,
+    admin_api: bool,
+    trustee: Option<TrustedExecutionClusterTrustee>,
+}
+
+fn config_table<'a>(config: &'a mut toml::Table, path: &[&str]) -> Result<&'a mut toml::Table> {
+    path.iter().try_fold(config, |table, key| {
+        let err = format!("kbs-config.toml missing {key} table");
+        table
+            .get_mut(*key)
+            .and_then(|v| v.as_table_mut())
+            .context(err)
+    })
+}
+
+fn apply_trustee_config(
+    config: &mut toml::Table,
+    trustee: &TrustedExecutionClusterTrustee,
+) -> Result<()> {
+    if let Some(duration) = trustee.token_duration_minutes {
+        let path = ["attestation_service", "attestation_token_config"];
+        let token_config = config_table(config, &path)?;
+        token_config.insert("duration_min".to_string(), i64::from(duration).into());
+    }
+    if let Some(token_type) = &trustee.token_type {
+        let token_section = config_table(config, &["attestation_token"])?;
+        let token_type = token_type.clone().into();
+        token_section.insert("attestation_token_type".to_string(), token_type);
+    }
+    if trustee.external_rvps == Some(true) {
+        let mut rvps_config = toml::Table::new();
+        rvps_config.insert("type".to_string(), "GrpcRemote".into());
+        rvps_config.insert("address".to_string(), rvps_address().into());
+        let attestation_service = config_table(config, &["attestation_service"])?;
+        attestation_service.insert("rvps_config".to_string(), rvps_config.into());
+    } else if let Some(path) = &trustee.rvps_storage_path {
+        let storage_path = ["attestation_service", "rvps_config", "storage"];
+        let storage = config_table(config, &storage_path)?;
+        storage.insert("file_path".to_string(), path.clone().into());
+    }
+
+    let plugins_err = "kbs-config.toml missing plugins array";
+    let plugins = config.get_mut("plugins").and_then(|p| p.as_array_mut());
+    let plugins = plugins.context(plugins_err)?;
+    for plugin in trustee.plugins.iter().flatten() {
+        let mut table = toml::Table::new();
+        table.insert("name".to_string(), plugin.name.clone().into());
+        for (key, value) in plugin.settings.iter().flatten() {
+            table.insert(key.clone(), value.clone().into());
+        }
+        plugins.push(toml::Value::Table(table));
+    }
+    Ok(())
+}
+
+fn generate_kbs_config(options: &KbsConfigOptions) -> Result<String> {
     let kbs_config_template = include_str!("kbs-config.toml");
     let mut config: toml::Table = toml::from_str(kbs_config_template)?;
 
+    if options.admin_api {
+        let mut persona = toml::Table::new();
+        persona.insert("id".to_string(), ADMIN_PERSONA.into());
+        let public_key_path = format!("{TRUSTEE_ADMIN_DIR}/{ADMIN_PUBLIC_KEY}");
+        persona.insert("public_key_path".to_string(), public_key_path.into());
+        let mut admin = toml::Table::new();
+        admin.insert("type".to_string(), "Simple".into());
+        let personas = toml::Value::Array(vec![toml::Value::Table(persona)]);
+        admin.insert("personas".to_string(), personas);
+        config.insert("admin".to_string(), toml::Value::Table(admin));
+    }
+    if let Some(trustee) = &options.trustee {
+        apply_trustee_config(&mut config, trustee)?;
+    }
+
     let section_err = "kbs-config.toml missing http_server section";
     let http_section = config.get_mut("http_server").context(section_err)?;
     let server_err = "http_server is not a table";
     let http_server = http_section.as_table_mut().context(server_err)?;
 
-    if has_certificate {
+    if options.has_certificate {
         let tls_key = toml::Value::String(format!("{TLS_DIR}/tls.key"));
         http_server.insert("private_key".to_string(), tls_key);
         let tls_cert = toml::Value::String(format!("{TLS_DIR}/tls.crt"));
@@ -409,17 +1281,39 @@ fn generate_kbs_config(has_certificate: bool) -> Result<String> {
     Ok(toml::to_string(&config)?)
 }
 
+async fn generate_kbs_config_for(
+    client: Client,
+    spec: &TrustedExecutionClusterSpec,
+) -> Result<String> {
+    let secret = &spec.trustee_secret;
+    let has_certificate = read_certificate(client, secret).await?.is_some();
+    let options = KbsConfigOptions {
+        has_certificate,
+        admin_api: spec.trustee_admin_api.unwrap_or(false),
+        trustee: spec.trustee.clone(),
+    };
+    generate_kbs_config(&options)
+}
+
+fn kbs_config_hash(kbs_config: &str) -> String {
+    hex::encode(openssl::sha::sha256(kbs_config.as_bytes()))
+}
+
+pub(crate) fn kbs_log_level(spec: &TrustedExecutionClusterSpec) -> String {
+    let log_level = spec.trustee.as_ref().and_then(|t| t.log_level.clone());
+    log_level.unwrap_or(DEFAULT_LOG_LEVEL.to_string())
+}
+
 pub async fn generate_trustee_data(
     client: Client,
     owner_reference: OwnerReference,
-    secret: &Option<String>,
+    spec: &TrustedExecutionClusterSpec,
 ) -> Result<()> {
-    let has_certificate = read_certificate(client.clone(), secret).await?.is_some();
-    let kbs_config = generate_kbs_config(has_certificate)?;
+    let kbs_config = generate_kbs_config_for(client.clone(), spec).await?;
     let policy_rego = include_str!("resource.rego");
 
     let data = BTreeMap::from([
-        ("kbs-config.toml".to_string(), kbs_config),
+        (KBS_CONFIG_FILE.to_string(), kbs_config),
         ("policy.rego".to_string(), policy_rego.to_string()),
         (REFERENCE_VALUES_FILE.to_string(), "[]".to_string()),
     ]);
@@ -471,7 +1365,7 @@ fn generate_kbs_volume_templates() -> [(&'static str, &'static str, Volume); 3]
     [
         (
             ATT_POLICY_MAP,
-            "/opt/trustee/policies/opa",
+            ATT_POLICY_DIR,
             Volume {
                 config_map: Some(ConfigMapVolumeSource {
                     name: ATT_POLICY_MAP.to_string(),
@@ -498,14 +1392,73 @@ fn generate_kbs_volume_templates() -> [(&'static str, &'static str, Volume); 3]
                 empty_dir: Some(EmptyDirVolumeSource {
                     medium: Some("Memory".to_string()),
                     ..Default::default()
-                }),
+                }),
+                ..Default::default()
+            },
+        ),
+    ]
+}
+
+fn generate_admin_volume() -> (Volume, VolumeMount) {
+    (
+        Volume {
+            name: TRUSTEE_ADMIN_SECRET.to_string(),
+            secret: Some(SecretVolumeSource {
+                secret_name: Some(TRUSTEE_ADMIN_SECRET.to_string()),
+                // Trustee only needs to verify, never mount the private key
+                items: Some(vec![KeyToPath {
+                    key: ADMIN_PUBLIC_KEY.to_string(),
+                    path: ADMIN_PUBLIC_KEY.to_string(),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        VolumeMount {
+            name: TRUSTEE_ADMIN_SECRET.to_string(),
+            mount_path: TRUSTEE_ADMIN_DIR.to_string(),
+            read_only: Some(true),
+            ..Default::default()
+        },
+    )
+}
+
+/// Mount the reference values of the Trustee data ConfigMap at a custom RVPS storage path
+fn generate_rvps_storage_volume(path: &str) -> Option<(Volume, VolumeMount)> {
+    let path = Path::new(path);
+    if path == Path::new(TRUSTEE_DATA_DIR).join(REFERENCE_VALUES_FILE) {
+        return None;
+    }
+    let file_name = path.file_name()?.to_string_lossy().to_string();
+    let parent = path.parent()?.to_string_lossy().to_string();
+    Some((
+        Volume {
+            name: RVPS_STORAGE_VOLUME.to_string(),
+            config_map: Some(ConfigMapVolumeSource {
+                name: TRUSTEE_DATA_MAP.to_string(),
+                items: Some(vec![KeyToPath {
+                    key: REFERENCE_VALUES_FILE.to_string(),
+                    path: file_name,
+                    ..Default::default()
+                }]),
                 ..Default::default()
-            },
-        ),
-    ]
+            }),
+            ..Default::default()
+        },
+        VolumeMount {
+            name: RVPS_STORAGE_VOLUME.to_string(),
+            mount_path: parent,
+            ..Default::default()
+        },
+    ))
 }
 
-fn generate_kbs_pod_spec(image: &str, tls_volumes: Option<(Volume, VolumeMount)>) -> PodSpec {
+fn generate_kbs_pod_spec(
+    image: &str,
+    tls_volumes: Option<(Volume, VolumeMount)>,
+    spec: &TrustedExecutionClusterSpec,
+) -> PodSpec {
     let volume_templates = generate_kbs_volume_templates();
     let mut volumes: Vec<Volume> = volume_templates
         .iter()
@@ -528,6 +1481,21 @@ fn generate_kbs_pod_spec(image: &str, tls_volumes: Option<(Volume, VolumeMount)>
         volumes.push(volume);
         volume_mounts.push(volume_mount);
     }
+    let (volume, volume_mount) = generate_token_key_volume();
+    volumes.push(volume);
+    volume_mounts.push(volume_mount);
+    if spec.trustee_admin_api == Some(true) {
+        let (volume, volume_mount) = generate_admin_volume();
+        volumes.push(volume);
+        volume_mounts.push(volume_mount);
+    }
+    // An external RVPS has its own storage
+    let trustee = spec.trustee.as_ref().filter(|_| !is_external_rvps(spec));
+    let rvps_storage_path = trustee.and_then(|t| t.rvps_storage_path.as_deref());
+    if let Some((volume, volume_mount)) = rvps_storage_path.and_then(generate_rvps_storage_volume) {
+        volumes.push(volume);
+        volume_mounts.push(volume_mount);
+    }
 
     PodSpec {
         containers: vec![Container {
@@ -538,7 +1506,7 @@ fn generate_kbs_pod_spec(image: &str, tls_volumes: Option<(Volume, VolumeMount)>
             ]),
             env: Some(vec![EnvVar {
                 name: "RUST_LOG".to_string(),
-                value: Some("debug".to_string()),
+                value: Some(kbs_log_level(spec)),
                 ..Default::default()
             }]),
             image: Some(image.to_string()),
@@ -559,11 +1527,17 @@ pub async fn generate_kbs_deployment(
     client: Client,
     owner_reference: OwnerReference,
     image: &str,
-    secret: &Option<String>,
+    spec: &TrustedExecutionClusterSpec,
 ) -> Result<()> {
     let selector = Some(BTreeMap::from([("app".to_string(), "kbs".to_string())]));
-    let tls_volumes = read_certificate(client.clone(), secret).await?;
-    let pod_spec = generate_kbs_pod_spec(image, tls_volumes);
+    let tls_volumes = read_certificate(client.clone(), &spec.trustee_secret).await?;
+    let pod_spec = generate_kbs_pod_spec(image, tls_volumes, spec);
+    let kbs_config = generate_kbs_config_for(client.clone(), spec).await?;
+    // Trustee reads its configuration on startup, so changes must restart it
+    let config_hash = (
+        KBS_CONFIG_HASH_ANNOTATION.to_string(),
+        kbs_config_hash(&kbs_config),
+    );
 
     // Inspired by trustee-operator
     let deployment = Deployment {
@@ -581,6 +1555,7 @@ pub async fn generate_kbs_deployment(
             template: PodTemplateSpec {
                 metadata: Some(ObjectMeta {
                     labels: selector,
+                    annotations: Some(BTreeMap::from([config_hash])),
                     ..Default::default()
                 }),
                 spec: Some(pod_spec),
@@ -593,20 +1568,139 @@ pub async fn generate_kbs_deployment(
     Ok(())
 }
 
+/// Strategic merge patch entries for the volumes and volume mounts of `SETTING_VOLUMES`, which add
+/// those that `desired` has and delete the others from `current`. Other volumes, e.g. of machine
+/// secrets or attestation keys, are left as they are.
+fn setting_volume_patches(
+    current: Option<&PodSpec>,
+    desired: &PodSpec,
+) -> Result<(Vec<serde_json::Value>, Vec<serde_json::Value>)> {
+    fn setting_volumes(pod_spec: Option<&PodSpec>) -> Vec<&Volume> {
+        let volumes = pod_spec
+            .and_then(|p| p.volumes.as_ref())
+            .into_iter()
+            .flatten();
+        let volumes = volumes.filter(|v| SETTING_VOLUMES.contains(&v.name.as_str()));
+        volumes.collect()
+    }
+    fn setting_mounts(pod_spec: Option<&PodSpec>) -> Vec<&VolumeMount> {
+        let container = pod_spec.and_then(|p| p.containers.first());
+        let mounts = container
+            .and_then(|c| c.volume_mounts.as_ref())
+            .into_iter()
+            .flatten();
+        let mounts = mounts.filter(|m| SETTING_VOLUMES.contains(&m.name.as_str()));
+        mounts.collect()
+    }
+    let desired_volumes = setting_volumes(Some(desired));
+    let desired_mounts = setting_mounts(Some(desired));
+    let volumes = desired_volumes.iter().map(serde_json::to_value);
+    let mut volumes = volumes.collect::<Result<Vec<_>, _>>()?;
+    let volume_mounts = desired_mounts.iter().map(serde_json::to_value);
+    let mut volume_mounts = volume_mounts.collect::<Result<Vec<_>, _>>()?;
+    for volume in setting_volumes(current) {
+        if !desired_volumes.iter().any(|v| v.name == volume.name) {
+            volumes.push(json!({"name": volume.name, "$patch": "delete"}));
+        }
+    }
+    for mount in setting_mounts(current) {
+        if !desired_mounts
+            .iter()
+            .any(|m| m.mount_path == mount.mount_path)
+        {
+            volume_mounts.push(json!({"mountPath": mount.mount_path, "$patch": "delete"}));
+        }
+    }
+    Ok((volumes, volume_mounts))
+}
+
+/// Apply changed Trustee settings of a TrustedExecutionCluster to the installed Trustee
+pub async fn update_trustee_config(
+    client: Client,
+    owner_reference: OwnerReference,
+    spec: &TrustedExecutionClusterSpec,
+) -> Result<()> {
+    // The admin API may have been enabled after installation
+    if spec.trustee_admin_api == Some(true) {
+        generate_admin_secret(client.clone(), owner_reference).await?;
+    }
+    let kbs_config = generate_kbs_config_for(client.clone(), spec).await?;
+    let config_hash = kbs_config_hash(&kbs_config);
+
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client.clone());
+    let mut trustee_map = config_maps.get(TRUSTEE_DATA_MAP).await?;
+    let data = trustee_map.data.get_or_insert_default();
+    if data.get(KBS_CONFIG_FILE) != Some(&kbs_config) {
+        data.insert(KBS_CONFIG_FILE.to_string(), kbs_config);
+        config_maps
+            .replace(TRUSTEE_DATA_MAP, &Default::default(), &trustee_map)
+            .await?;
+        info!("Updated KBS configuration");
+    }
+
+    let deployments: Api<Deployment> = Api::default_namespaced(client.clone());
+    let deployment = deployments.get(TRUSTEE_DEPLOYMENT).await?;
+    let current = deployment.spec.and_then(|s| s.template.spec);
+    let tls_volumes = read_certificate(client, &spec.trustee_secret).await?;
+    let desired = generate_kbs_pod_spec("", tls_volumes, spec);
+    let (volumes, volume_mounts) = setting_volume_patches(current.as_ref(), &desired)?;
+
+    // Unchanged values leave the pod template as is and do not cause a rollout
+    let patch = json!({
+        "spec": {
+            "template": {
+                "metadata": {
+                    "annotations": {
+                        KBS_CONFIG_HASH_ANNOTATION: config_hash
+                    }
+                },
+                "spec": {
+                    "containers": [{
+                        "name": "kbs",
+                        "env": [{
+                            "name": "RUST_LOG",
+                            "value": kbs_log_level(spec)
+                        }],
+                        "volumeMounts": volume_mounts
+                    }],
+                    "volumes": volumes
+                }
+            }
+        }
+    });
+    deployments
+        .patch(
+            TRUSTEE_DEPLOYMENT,
+            &Default::default(),
+            &Patch::Strategic(&patch),
+        )
+        .await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::test_utils::*;
-    use compute_pcrs_lib::Pcr;
     use compute_pcrs_lib::tpmevents::TPMEventID;
     use http::{Method, Request, StatusCode};
-    use k8s_openapi::jiff::Timestamp;
+    use k8s_openapi::ByteString;
+    use k8s_openapi::jiff::SignedDuration;
     use kube::client::Body;
+    use trusted_cluster_operator_lib::conditions::PCR_COMBINATIONS_WITHIN_LIMIT_REASON;
+    use trusted_cluster_operator_lib::{
+        TrustedExecutionClusterExternalTrustee, TrustedExecutionClusterTrusteePlugins,
+    };
     use trusted_cluster_operator_test_utils::constants::*;
     use trusted_cluster_operator_test_utils::mock_client::*;
     use trusted_cluster_operator_test_utils::test_error_method;
     use trusted_cluster_operator_test_utils::*;
 
+    fn recompute(image_pcrs: &ImagePcrs) -> Vec<ReferenceValue> {
+        let cache = CombinationCache::default();
+        recompute_reference_values(image_pcrs, DEFAULT_MAX_PCR_COMBINATIONS, &cache).0
+    }
+
     fn reference_values_from(reference_values: &[ReferenceValue], rv_name: &str) -> Vec<String> {
         let rv = reference_values
             .iter()
@@ -660,7 +1754,7 @@ mod tests {
 
     #[test]
     fn test_recompute_reference_values() {
-        let result = recompute_reference_values(dummy_pcrs());
+        let result = recompute(&dummy_pcrs());
         assert_eq!(result.len(), 3);
         let vals = reference_values_from(&result, "tpm_pcr4");
         assert_eq!(vals, vec![DUMMY_PCR_4_VALUE,]);
@@ -668,62 +1762,432 @@ mod tests {
         assert_eq!(vals, vec![DUMMY_PCR_7_VALUE,]);
     }
 
+    #[tokio::test]
+    async fn test_recompute_reference_values_profile_pcrs() {
+        let clos = async |req: Request<Body>, _| {
+            assert!(req.uri().path().ends_with("/configmaps/platform-efivars"));
+            let config_map = ConfigMap {
+                data: Some(BTreeMap::from([("db".to_string(), "00".to_string())])),
+                ..Default::default()
+            };
+            Ok(serde_json::to_string(&config_map).unwrap())
+        };
+        let profile = dummy_profile("platform");
+        let mut variables_hash = String::new();
+        count_check!(1, clos, |client| {
+            variables_hash = profile_variables_hash(client, &profile).await.unwrap();
+        });
+        let mut image_pcrs = dummy_pcrs();
+        let image_pcr = image_pcrs.0.get_mut("cos").unwrap();
+        let pcr7 = image_pcr.pcrs.pop().unwrap();
+        let pcr4 = image_pcr.pcrs.pop().unwrap();
+        let architecture = "amd64".to_string();
+        let platforms = BTreeMap::from([("platform".to_string(), vec![pcr7])]);
+        image_pcr.architectures = BTreeMap::from([(architecture.clone(), vec![pcr4])]);
+        image_pcr.profile_pcrs = BTreeMap::from([(architecture, platforms)]);
+        let record = ProfileRecord::new(&profile, variables_hash);
+        image_pcr.profiles = BTreeMap::from([("platform".to_string(), record)]);
+        let result = recompute(&image_pcrs);
+        let vals = reference_values_from(&result, "platform/tpm_pcr4");
+        assert_eq!(vals, vec![DUMMY_PCR_4_VALUE]);
+        let vals = reference_values_from(&result, "platform/tpm_pcr7");
+        assert_eq!(vals, vec![DUMMY_PCR_7_VALUE]);
+        let vals = reference_values_from(&result, "platform_profiles_tpm");
+        assert_eq!(vals, vec!["platform"]);
+        assert!(!result.iter().any(|rv| rv.name == "tpm_pcr4"));
+    }
+
+    #[test]
+    fn test_recompute_reference_values_kernel_cmdline_pcrs() {
+        let mut image_pcrs = dummy_pcrs();
+        let image_pcr = image_pcrs.0.get_mut("cos").unwrap();
+        let cmdlines = ["rw", "rw quiet"];
+        image_pcr.kernel_cmdline_pcrs = cmdlines.map(kernel_cmdline_pcr).to_vec();
+        let result = recompute(&image_pcrs);
+        let vals = reference_values_from(&result, "tpm_pcr12");
+        assert_eq!(vals.len(), 2);
+
+        // Images that do not measure command lines keep PCR12 unextended
+        let mut grub = image_pcrs.0["cos"].clone();
+        grub.kernel_cmdline_pcrs = Vec::new();
+        image_pcrs.0.insert("grub".to_string(), grub);
+        let result = recompute(&image_pcrs);
+        let vals = reference_values_from(&result, "tpm_pcr12");
+        assert_eq!(vals.len(), 3);
+        assert!(vals.contains(&hex::encode(unmeasured_cmdline_pcr().value)));
+        let vals = reference_values_from(&result, "tpm_pcr4");
+        assert_eq!(vals, vec![DUMMY_PCR_4_VALUE]);
+
+        // Images with command lines that did not boot as a UKI are not trusted
+        let mut unmeasured = image_pcrs.0["grub"].clone();
+        unmeasured.pcrs[0].events[0].hash = [0; 32].to_vec();
+        unmeasured.kernel_command_lines = vec!["rw".to_string()];
+        image_pcrs.0.insert("unmeasured".to_string(), unmeasured);
+        let result = recompute(&image_pcrs);
+        let vals = reference_values_from(&result, "tpm_pcr4");
+        assert_eq!(vals, vec![DUMMY_PCR_4_VALUE]);
+        assert_eq!(reference_values_from(&result, "tpm_pcr12").len(), 3);
+    }
+
+    #[test]
+    fn test_recompute_reference_values_expiration() {
+        let not_after = Timestamp::now() + SignedDuration::from_hours(24);
+        let mut image_pcrs = dummy_pcrs();
+        image_pcrs.0.get_mut("cos").unwrap().not_after = Some(not_after);
+        let result = recompute(&image_pcrs);
+        assert_eq!(result[0].expiration.timestamp(), not_after.as_second());
+
+        let mut image_pcrs = dummy_pcrs();
+        let expired = Timestamp::now() - SignedDuration::from_hours(24);
+        image_pcrs.0.get_mut("cos").unwrap().not_after = Some(expired);
+        let result = recompute(&image_pcrs);
+        // Only the SVN remains
+        assert_eq!(result.len(), 1);
+    }
+
+    #[test]
+    fn test_combination_estimate() {
+        let event = |pcr, hash: &str| TPMEvent {
+            name: "EV_EFI_BOOT_SERVICES_APPLICATION".into(),
+            pcr,
+            hash: hash.as_bytes().to_vec(),
+            id: TPMEventID::Pcr4EfiCall,
+        };
+        let first = vec![event(4, "shim"), event(4, "grub"), event(7, "db")];
+        let second = vec![event(4, "shim"), event(4, "grub2"), event(7, "db")];
+        let third = vec![event(4, "shim2"), event(4, "grub3"), event(7, "db")];
+        assert_eq!(combination_estimate(&[first.clone()]), 2);
+        assert_eq!(combination_estimate(&[first.clone(), second.clone()]), 3);
+        // 2 shims * 3 grubs for PCR 4, 1 for PCR 7
+        assert_eq!(combination_estimate(&[first, second, third]), 7);
+    }
+
+    #[test]
+    fn test_combine_pcrs_limit_exceeded() {
+        let image_pcrs = dummy_pcrs();
+        let images: Vec<_> = image_pcrs.0.values().collect();
+        let combination = combine_pcrs(&images, 1, &Default::default());
+        assert!(!combination.within_limit);
+        assert_eq!(combination.combinations, 2);
+        // Only the values of the image itself
+        let pcr4 = BTreeSet::from([DUMMY_PCR_4_VALUE.to_string()]);
+        assert_eq!(combination.values["pcr4"], pcr4);
+        let pcr7 = BTreeSet::from([DUMMY_PCR_7_VALUE.to_string()]);
+        assert_eq!(combination.values["pcr7"], pcr7);
+    }
+
+    #[test]
+    fn test_combine_pcrs_cached() {
+        let image_pcrs = dummy_pcrs();
+        let images: Vec<_> = image_pcrs.0.values().collect();
+        let cache = CombinationCache::default();
+        let combination = combine_pcrs(&images, DEFAULT_MAX_PCR_COMBINATIONS, &cache);
+        assert!(combination.within_limit);
+
+        // Mark the cached combination to tell whether it is reused
+        let marker = BTreeSet::from(["cached".to_string()]);
+        let mut cached = cache.lock().unwrap();
+        let (_, cached_combination) = cached.as_mut().unwrap();
+        cached_combination
+            .values
+            .insert("marker".to_string(), marker);
+        drop(cached);
+        let combination = combine_pcrs(&images, DEFAULT_MAX_PCR_COMBINATIONS, &cache);
+        assert!(combination.values.contains_key("marker"));
+
+        // A different ceiling combines again
+        let combination = combine_pcrs(&images, 1, &cache);
+        assert!(!combination.values.contains_key("marker"));
+        assert!(!combination.within_limit);
+    }
+
     #[tokio::test]
     async fn test_update_rvs_success() {
         let clos = async |req: Request<_>, ctr| match (ctr, req.method()) {
             (0, &Method::GET) => {
-                assert!(req.uri().path().contains(PCR_CONFIG_MAP));
-                Ok(serde_json::to_string(&dummy_pcrs_map()).unwrap())
+                assert!(req.uri().query().unwrap().contains(PCR_CONFIG_MAP));
+                Ok(serde_json::to_string(&dummy_pcr_maps()).unwrap())
             }
-            (1, &Method::GET) | (2, &Method::PUT) => {
+            (1, &Method::GET) => Ok(serde_json::to_string(&dummy_clusters()).unwrap()),
+            (2, &Method::GET) | (3, &Method::PUT) => {
                 assert!(req.uri().path().contains(TRUSTEE_DATA_MAP));
                 Ok(serde_json::to_string(&dummy_trustee_map()).unwrap())
             }
+            // Recorded as the first version
+            (4, &Method::GET) => Ok(serde_json::to_string(&empty_rv_history()).unwrap()),
+            (5, &Method::POST) => Ok(serde_json::to_string(&ConfigMap::default()).unwrap()),
+            (6, &Method::PATCH) => {
+                assert!(req.uri().path().ends_with("/status"));
+                let body = get_body_string(req).await;
+                assert!(body.contains(PCR_COMBINATIONS_WITHIN_LIMIT_REASON));
+                assert!(body.contains("\"images\":1"));
+                Ok(serde_json::to_string(&dummy_cluster()).unwrap())
+            }
             _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
         };
+        count_check!(7, clos, |client| {
+            assert!(update_reference_values(client, false).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn test_refresh_pinned_expiration() {
+        let mut image_pcrs = dummy_pcrs();
+        let not_after = "2100-01-01T00:00:00Z".parse().unwrap();
+        image_pcrs.0.get_mut("cos").unwrap().not_after = Some(not_after);
+        let mut reference_values = recompute(&image_pcrs);
+        for reference_value in reference_values.iter_mut() {
+            reference_value.expiration = DateTime::default();
+        }
+        let images = BTreeSet::from(["cos".to_string()]);
+        assert!(refresh_pinned_expiration(
+            &mut reference_values,
+            &images,
+            &image_pcrs
+        ));
+        let expiration = reference_values[0].expiration.timestamp();
+        assert_eq!(expiration, not_after.as_second());
+
+        // No contributing image remains
+        let images = BTreeSet::from(["gone".to_string()]);
+        assert!(!refresh_pinned_expiration(
+            &mut reference_values,
+            &images,
+            &image_pcrs
+        ));
+        assert!(reference_values[0].expiration > Utc::now());
+    }
+
+    #[test]
+    fn test_reference_values_hash() {
+        let reference_values = recompute(&dummy_pcrs());
+        let mut later = recompute(&dummy_pcrs());
+        later[0].expiration += chrono::Duration::days(1);
+        let hash = reference_values_hash(&reference_values, false);
+        assert_eq!(hash, reference_values_hash(&later, false));
+        let hash = reference_values_hash(&reference_values, true);
+        assert_ne!(hash, reference_values_hash(&later, true));
+    }
+
+    #[tokio::test]
+    async fn test_update_rvs_unchanged() {
+        let mut image_pcrs = dummy_pcrs();
+        let not_after = "2100-01-01T00:00:00Z".parse().unwrap();
+        image_pcrs.0.get_mut("cos").unwrap().not_after = Some(not_after);
+        let rv_hash = reference_values_hash(&recompute(&image_pcrs), true);
+        let mut trustee_map = dummy_trustee_map();
+        let annotation = (RV_HASH_ANNOTATION.to_string(), rv_hash);
+        trustee_map.metadata.annotations = Some(BTreeMap::from([annotation]));
+
+        let pcr_maps = serde_json::to_string(&image_pcr_maps(image_pcrs)).unwrap();
+        let trustee_map = serde_json::to_string(&trustee_map).unwrap();
+        let clos = move |req: Request<_>, ctr| {
+            let (pcr_maps, trustee_map) = (pcr_maps.clone(), trustee_map.clone());
+            async move {
+                match (ctr, req.method()) {
+                    (0, &Method::GET) => Ok(pcr_maps),
+                    (1, &Method::GET) => Ok(serde_json::to_string(&dummy_clusters()).unwrap()),
+                    // No replacement
+                    (2, &Method::GET) => {
+                        assert!(req.uri().path().contains(TRUSTEE_DATA_MAP));
+                        Ok(trustee_map)
+                    }
+                    // Not recorded again
+                    (3, &Method::PATCH) => Ok(serde_json::to_string(&dummy_cluster()).unwrap()),
+                    _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+                }
+            }
+        };
+        count_check!(4, clos, |client| {
+            assert!(update_reference_values(client, false).await.is_ok());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_register_rvs_unchanged() {
+        let reference_values = recompute(&dummy_pcrs());
+        let rv_hash = reference_values_hash(&reference_values, true);
+        let registration_map = ConfigMap {
+            metadata: ObjectMeta {
+                name: Some(RVPS_REGISTRATION_MAP.to_string()),
+                annotations: Some(BTreeMap::from([(
+                    RV_HASH_ANNOTATION.to_string(),
+                    rv_hash.clone(),
+                )])),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let registration_map = serde_json::to_string(&registration_map).unwrap();
+        let clos = move |req: Request<_>, ctr| {
+            let registration_map = registration_map.clone();
+            async move {
+                match (ctr, req.method()) {
+                    // No admin client or registration
+                    (0, &Method::GET) => {
+                        assert!(req.uri().path().contains(RVPS_REGISTRATION_MAP));
+                        Ok(registration_map)
+                    }
+                    _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+                }
+            }
+        };
+        count_check!(1, clos, |client| {
+            let cluster = dummy_cluster();
+            let result =
+                register_reference_values(client, &cluster, &reference_values, &rv_hash, false);
+            assert!(!result.await.unwrap());
+        });
+    }
+
+    /// Trustee admin API on localhost that answers one request and passes on its body
+    fn serve_admin_request() -> (String, std::thread::JoinHandle<String>) {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            let body = loop {
+                let read = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                    continue;
+                };
+                let length = head.lines().find_map(|l| {
+                    let (name, value) = l.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().unwrap())
+                });
+                if body.len() >= length.unwrap_or(0) {
+                    break body.to_string();
+                }
+            };
+            let response = "HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+            stream.write_all(response.as_bytes()).unwrap();
+            body
+        });
+        (address, handle)
+    }
+
+    #[tokio::test]
+    async fn test_reregister_rvs_unchanged() {
+        let reference_values = recompute(&dummy_pcrs());
+        let rv_hash = reference_values_hash(&reference_values, true);
+        let registration_map = ConfigMap {
+            metadata: ObjectMeta {
+                name: Some(RVPS_REGISTRATION_MAP.to_string()),
+                annotations: Some(BTreeMap::from([(
+                    RV_HASH_ANNOTATION.to_string(),
+                    rv_hash.clone(),
+                )])),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let registration_map = serde_json::to_string(&registration_map).unwrap();
+        let clos = move |req: Request<_>, ctr| {
+            let registration_map = registration_map.clone();
+            async move {
+                match (ctr, req.method()) {
+                    (0, &Method::GET) => {
+                        assert!(req.uri().path().contains(RVPS_REGISTRATION_MAP));
+                        Ok(registration_map)
+                    }
+                    (1, &Method::GET) => {
+                        assert!(req.uri().path().ends_with("/secrets/kbs-admin"));
+                        let (private_pem, _) = kbs_admin::generate_admin_keypair().unwrap();
+                        let key = ADMIN_PRIVATE_KEY.to_string();
+                        let secret = Secret {
+                            data: Some(BTreeMap::from([(key, ByteString(private_pem))])),
+                            ..Default::default()
+                        };
+                        Ok(serde_json::to_string(&secret).unwrap())
+                    }
+                    (2, &Method::PATCH) => {
+                        assert!(req.uri().path().contains(RVPS_REGISTRATION_MAP));
+                        Ok(serde_json::to_string(&ConfigMap::default()).unwrap())
+                    }
+                    _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+                }
+            }
+        };
+        let (address, admin) = serve_admin_request();
         count_check!(3, clos, |client| {
-            assert!(update_reference_values(client).await.is_ok());
+            let mut cluster = dummy_cluster();
+            cluster.spec.external_trustee = Some(TrustedExecutionClusterExternalTrustee {
+                address,
+                ca_secret: None,
+                admin_key_secret: "kbs-admin".to_string(),
+            });
+            // The RVPS restarted and lost the values that the recorded hash matches
+            let result =
+                register_reference_values(client, &cluster, &reference_values, &rv_hash, true);
+            assert!(!result.await.unwrap());
+        });
+        let message: serde_json::Value = serde_json::from_str(&admin.join().unwrap()).unwrap();
+        assert_eq!(message["type"], "sample");
+    }
+
+    #[tokio::test]
+    async fn test_update_rvs_conflict() {
+        let clos = async |req: Request<_>, ctr| match (ctr, req.method()) {
+            (0, &Method::GET) => Ok(serde_json::to_string(&dummy_pcr_maps()).unwrap()),
+            (1, &Method::GET) => Ok(serde_json::to_string(&dummy_clusters()).unwrap()),
+            (2, &Method::GET) | (4, &Method::GET) | (5, &Method::PUT) => {
+                assert!(req.uri().path().contains(TRUSTEE_DATA_MAP));
+                Ok(serde_json::to_string(&dummy_trustee_map()).unwrap())
+            }
+            // Changed concurrently since it was read
+            (3, &Method::PUT) => Err(StatusCode::CONFLICT),
+            (6, &Method::GET) => Ok(serde_json::to_string(&empty_rv_history()).unwrap()),
+            (7, &Method::POST) => Ok(serde_json::to_string(&ConfigMap::default()).unwrap()),
+            (8, &Method::PATCH) => Ok(serde_json::to_string(&dummy_cluster()).unwrap()),
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(9, clos, |client| {
+            assert!(update_reference_values(client, false).await.is_ok());
         });
     }
 
     #[tokio::test]
-    async fn test_update_rvs_no_pcr_map() {
-        let clos = async |req: Request<_>, _| match (req.uri().path(), req.method()) {
-            (p, &Method::GET) if p.contains(PCR_CONFIG_MAP) => Err(StatusCode::NOT_FOUND),
+    async fn test_update_rvs_no_pcr_maps() {
+        let clos = async |req: Request<_>, _| match req.method() {
+            &Method::GET => Err(StatusCode::INTERNAL_SERVER_ERROR),
             _ => panic!("unexpected API interaction: {req:?}"),
         };
         count_check!(1, clos, |client| {
-            assert!(update_reference_values(client).await.is_err());
+            assert!(update_reference_values(client, false).await.is_err());
         });
     }
 
     #[tokio::test]
     async fn test_update_rvs_no_trustee_map() {
         let clos = async |req: Request<_>, ctr| match (ctr, req.uri().path()) {
-            (0, p) if p.contains(PCR_CONFIG_MAP) => {
-                Ok(serde_json::to_string(&dummy_pcrs_map()).unwrap())
-            }
-            (1, p) if p.contains(TRUSTEE_DATA_MAP) => Err(StatusCode::NOT_FOUND),
+            (0, _) => Ok(serde_json::to_string(&dummy_pcr_maps()).unwrap()),
+            (1, _) => Ok(serde_json::to_string(&dummy_clusters()).unwrap()),
+            (2, p) if p.contains(TRUSTEE_DATA_MAP) => Err(StatusCode::NOT_FOUND),
             _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
         };
-        count_check!(2, clos, |client| {
-            assert!(update_reference_values(client).await.is_err())
+        count_check!(3, clos, |client| {
+            assert!(update_reference_values(client, false).await.is_err())
         });
     }
 
     #[tokio::test]
     async fn test_update_rvs_no_trustee_data() {
         let clos = async |req: Request<_>, ctr| match (ctr, req.uri().path()) {
-            (0, p) if p.contains(PCR_CONFIG_MAP) => {
-                Ok(serde_json::to_string(&dummy_pcrs_map()).unwrap())
-            }
-            (1, p) if p.contains(TRUSTEE_DATA_MAP) => {
+            (0, _) => Ok(serde_json::to_string(&dummy_pcr_maps()).unwrap()),
+            (1, _) => Ok(serde_json::to_string(&dummy_clusters()).unwrap()),
+            (2, p) if p.contains(TRUSTEE_DATA_MAP) => {
                 Ok(serde_json::to_string(&ConfigMap::default()).unwrap())
             }
             _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
         };
-        count_check!(2, clos, |client| {
-            let err = update_reference_values(client).await.err().unwrap();
+        count_check!(3, clos, |client| {
+            let err = update_reference_values(client, false).await.err().unwrap();
             assert!(err.to_string().contains("but had no data"));
         });
     }
@@ -764,6 +2228,26 @@ mod tests {
         });
     }
 
+    #[tokio::test]
+    async fn test_mount_secret_already_mounted() {
+        let clos = async |req: Request<_>, ctr| match (ctr, req.method()) {
+            (0, &Method::GET) => {
+                let mut depl = dummy_deployment();
+                let spec = depl.spec.as_mut().unwrap();
+                let pod_spec = spec.template.spec.as_mut().unwrap();
+                pod_spec.volumes = Some(vec![Volume {
+                    name: "id".to_string(),
+                    ..Default::default()
+                }]);
+                Ok(serde_json::to_string(&depl).unwrap())
+            }
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(1, clos, |client| {
+            assert!(mount_secret(client, "id").await.is_ok());
+        });
+    }
+
     #[tokio::test]
     async fn test_mount_secret_no_depl() {
         let clos = async |_, _| Err(StatusCode::NOT_FOUND);
@@ -814,6 +2298,20 @@ mod tests {
         });
     }
 
+    #[tokio::test]
+    async fn test_deliver_secret_mounts_without_admin_api() {
+        let clos = async |req: Request<_>, ctr| match (ctr, req.method()) {
+            (0, &Method::GET) | (1, &Method::PUT) => {
+                Ok(serde_json::to_string(&dummy_deployment()).unwrap())
+            }
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(2, clos, |client| {
+            let cluster = dummy_cluster();
+            assert!(deliver_secret(client, &cluster, "id").await.is_ok());
+        });
+    }
+
     #[tokio::test]
     async fn test_unmount_secret() {
         let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
@@ -881,21 +2379,281 @@ mod tests {
         test_error_method!(clos, Method::POST);
     }
 
+    #[tokio::test]
+    async fn test_generate_admin_secret_success() {
+        let clos = |client| generate_admin_secret(client, Default::default());
+        test_create_success::<_, _, Secret>(clos).await;
+    }
+
+    #[tokio::test]
+    async fn test_generate_admin_secret_already_exists() {
+        let clos = |client| generate_admin_secret(client, Default::default());
+        test_create_already_exists(clos).await;
+    }
+
+    #[test]
+    fn test_generate_kbs_config_deny_admin() {
+        let config = generate_kbs_config(&Default::default()).unwrap();
+        let config: toml::Table = toml::from_str(&config).unwrap();
+        assert_eq!(config["admin"]["type"].as_str(), Some("DenyAll"));
+        assert_eq!(config["http_server"]["insecure_http"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn test_generate_kbs_config_admin_api() {
+        let options = KbsConfigOptions {
+            has_certificate: true,
+            admin_api: true,
+        };
+        let config = generate_kbs_config(&options).unwrap();
+        let config: toml::Table = toml::from_str(&config).unwrap();
+        assert_eq!(config["admin"]["type"].as_str(), Some("Simple"));
+        let persona = &config["admin"]["personas"][0];
+        assert_eq!(persona["id"].as_str(), Some(ADMIN_PERSONA));
+        assert!(config["http_server"].get("insecure_http").is_none());
+    }
+
+    fn dummy_trustee_config() -> TrustedExecutionClusterTrustee {
+        TrustedExecutionClusterTrustee {
+            token_duration_minutes: Some(10),
+            token_type: Some("CoCo".to_string()),
+            rvps_storage_path: Some("/var/lib/rvps/reference-values.json".to_string()),
+            plugins: Some(vec![TrustedExecutionClusterTrusteePlugins {
+                name: "sample".to_string(),
+                settings: Some(BTreeMap::from([("item".to_string(), "value".to_string())])),
+            }]),
+            external_rvps: None,
+            log_level: Some("info,kbs=debug".to_string()),
+        }
+    }
+
+    fn dummy_trustee_spec(trustee: TrustedExecutionClusterTrustee) -> TrustedExecutionClusterSpec {
+        let mut spec = dummy_cluster().spec;
+        spec.trustee = Some(trustee);
+        spec
+    }
+
+    #[test]
+    fn test_validate_trustee_config_valid() {
+        let spec = dummy_trustee_spec(dummy_trustee_config());
+        assert!(validate_trustee_config(&spec).is_ok());
+    }
+
+    #[test]
+    fn test_validate_trustee_config_invalid() {
+        let mut config = dummy_trustee_config();
+        config.log_level = Some("kbs=verbose".to_string());
+        assert!(validate_trustee_config(&dummy_trustee_spec(config)).is_err());
+
+        let mut config = dummy_trustee_config();
+        config.rvps_storage_path = Some("relative/reference-values.json".to_string());
+        assert!(validate_trustee_config(&dummy_trustee_spec(config)).is_err());
+
+        let mut config = dummy_trustee_config();
+        config.rvps_storage_path = Some(format!("{TOKEN_KEY_DIR}/reference-values.json"));
+        assert!(validate_trustee_config(&dummy_trustee_spec(config)).is_err());
+
+        let mut config = dummy_trustee_config();
+        let plugins = config.plugins.as_mut().unwrap();
+        plugins[0].name = RESOURCE_PLUGIN.to_string();
+        assert!(validate_trustee_config(&dummy_trustee_spec(config)).is_err());
+
+        let mut config = dummy_trustee_config();
+        let plugins = config.plugins.as_mut().unwrap();
+        plugins.push(plugins[0].clone());
+        assert!(validate_trustee_config(&dummy_trustee_spec(config)).is_err());
+
+        let mut config = dummy_trustee_config();
+        config.external_rvps = Some(true);
+        assert!(validate_trustee_config(&dummy_trustee_spec(config)).is_err());
+    }
+
+    #[test]
+    fn test_validate_external_trustee() {
+        let mut spec = dummy_cluster().spec;
+        spec.external_trustee = Some(TrustedExecutionClusterExternalTrustee {
+            address: "kbs.example.com:8080".to_string(),
+            ca_secret: Some("kbs-ca".to_string()),
+            admin_key_secret: "kbs-admin".to_string(),
+        });
+        assert!(validate_trustee_config(&spec).is_ok());
+
+        let external = spec.external_trustee.as_mut().unwrap();
+        external.address = "https://kbs.example.com:8080".to_string();
+        assert!(validate_trustee_config(&spec).is_err());
+
+        let external = spec.external_trustee.as_mut().unwrap();
+        external.address = "kbs.example.com:8080".to_string();
+        spec.trustee = Some(dummy_trustee_config());
+        assert!(validate_trustee_config(&spec).is_err());
+    }
+
+    #[test]
+    fn test_generate_kbs_config_external_rvps() {
+        let mut trustee = dummy_trustee_config();
+        trustee.external_rvps = Some(true);
+        let options = KbsConfigOptions {
+            trustee: Some(trustee),
+            ..Default::default()
+        };
+        let config = generate_kbs_config(&options).unwrap();
+        let config: toml::Table = toml::from_str(&config).unwrap();
+        let rvps_config = &config["attestation_service"]["rvps_config"];
+        assert_eq!(rvps_config["type"].as_str(), Some("GrpcRemote"));
+        assert_eq!(
+            rvps_config["address"].as_str(),
+            Some(rvps_address().as_str())
+        );
+        assert!(rvps_config.get("storage").is_none());
+    }
+
+    #[test]
+    fn test_rvps_sample_message() {
+        let reference_values = recompute(&dummy_pcrs());
+        let registered = BTreeSet::from(["tpm_svn".to_string(), "tpm_pcr15".to_string()]);
+        let message = rvps_sample_message(&reference_values, &registered).unwrap();
+        assert_eq!(message["type"], "sample");
+        let payload = message["payload"].as_str().unwrap();
+        let payload = general_purpose::STANDARD.decode(payload).unwrap();
+        let values: Vec<ReferenceValue> = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(values.len(), reference_values.len() + 1);
+        let svn = values.iter().find(|rv| rv.name == "tpm_svn").unwrap();
+        assert_eq!(svn.value, json!(["1"]));
+        assert_eq!(
+            svn.expiration.timestamp(),
+            reference_values[0].expiration.timestamp()
+        );
+        // No longer present, thus withdrawn
+        let withdrawn = values.iter().find(|rv| rv.name == "tpm_pcr15").unwrap();
+        assert_eq!(withdrawn.value, json!([]));
+    }
+
+    #[test]
+    fn test_generate_kbs_config_trustee_section() {
+        let options = KbsConfigOptions {
+            trustee: Some(dummy_trustee_config()),
+            ..Default::default()
+        };
+        let config = generate_kbs_config(&options).unwrap();
+        let config: toml::Table = toml::from_str(&config).unwrap();
+        let attestation_service = &config["attestation_service"];
+        let duration = &attestation_service["attestation_token_config"]["duration_min"];
+        assert_eq!(duration.as_integer(), Some(10));
+        let storage = &attestation_service["rvps_config"]["storage"];
+        let path = "/var/lib/rvps/reference-values.json";
+        assert_eq!(storage["file_path"].as_str(), Some(path));
+        let plugins = config["plugins"].as_array().unwrap();
+        assert_eq!(plugins.len(), 2);
+        assert_eq!(plugins[1]["name"].as_str(), Some("sample"));
+        assert_eq!(plugins[1]["item"].as_str(), Some("value"));
+    }
+
+    #[test]
+    fn test_generate_kbs_pod_spec_trustee_section() {
+        let spec = dummy_trustee_spec(dummy_trustee_config());
+        let pod_spec = generate_kbs_pod_spec("image", None, &spec);
+        let container = &pod_spec.containers[0];
+        let env = container.env.as_ref().unwrap();
+        assert_eq!(env[0].value.as_deref(), Some("info,kbs=debug"));
+        let mounts = container.volume_mounts.as_ref().unwrap();
+        let rvps_mount = mounts.iter().find(|m| m.name == RVPS_STORAGE_VOLUME);
+        assert_eq!(rvps_mount.unwrap().mount_path, "/var/lib/rvps");
+    }
+
+    #[tokio::test]
+    async fn test_update_trustee_config() {
+        // Installed with the admin API, which was disabled since
+        let mut deployment = dummy_deployment();
+        let pod_spec = deployment
+            .spec
+            .as_mut()
+            .unwrap()
+            .template
+            .spec
+            .as_mut()
+            .unwrap();
+        let (volume, volume_mount) = generate_admin_volume();
+        pod_spec.volumes = Some(vec![volume]);
+        pod_spec.containers[0].volume_mounts = Some(vec![volume_mount]);
+        let clos = move |req: Request<Body>, ctr| {
+            let deployment = deployment.clone();
+            async move {
+                match (ctr, req.method()) {
+                    (0, &Method::GET) => Ok(serde_json::to_string(&ConfigMap::default()).unwrap()),
+                    (1, &Method::PUT) => {
+                        let body = get_body_string(req).await;
+                        assert!(body.contains("duration_min = 10"));
+                        Ok(body)
+                    }
+                    (2, &Method::GET) => Ok(serde_json::to_string(&deployment).unwrap()),
+                    (3, &Method::PATCH) => {
+                        let body = get_body_string(req).await;
+                        assert!(body.contains(KBS_CONFIG_HASH_ANNOTATION));
+                        assert!(body.contains("info,kbs=debug"));
+                        let patch: serde_json::Value = serde_json::from_str(&body).unwrap();
+                        let pod_spec = &patch["spec"]["template"]["spec"];
+                        let volumes = pod_spec["volumes"].as_array().unwrap();
+                        assert_eq!(volumes[0]["name"], RVPS_STORAGE_VOLUME);
+                        let deleted = json!({"name": TRUSTEE_ADMIN_SECRET, "$patch": "delete"});
+                        assert_eq!(volumes[1], deleted);
+                        let mounts = pod_spec["containers"][0]["volumeMounts"].as_array();
+                        let deleted = json!({"mountPath": TRUSTEE_ADMIN_DIR, "$patch": "delete"});
+                        assert!(mounts.unwrap().contains(&deleted));
+                        Ok(serde_json::to_string(&dummy_deployment()).unwrap())
+                    }
+                    _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+                }
+            }
+        };
+        count_check!(4, clos, |client| {
+            let spec = dummy_trustee_spec(dummy_trustee_config());
+            let owner = OwnerReference::default();
+            assert!(update_trustee_config(client, owner, &spec).await.is_ok());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_update_trustee_config_admin_api() {
+        let clos = async |req: Request<Body>, ctr| match (ctr, req.method()) {
+            (0, &Method::POST) => Ok(serde_json::to_string(&Secret::default()).unwrap()),
+            (1, &Method::GET) => Ok(serde_json::to_string(&ConfigMap::default()).unwrap()),
+            (2, &Method::PUT) => Ok(get_body_string(req).await),
+            (3, &Method::GET) => Ok(serde_json::to_string(&dummy_deployment()).unwrap()),
+            (4, &Method::PATCH) => {
+                let body = get_body_string(req).await;
+                let patch: serde_json::Value = serde_json::from_str(&body).unwrap();
+                let volumes = &patch["spec"]["template"]["spec"]["volumes"];
+                assert_eq!(volumes[0]["name"], TRUSTEE_ADMIN_SECRET);
+                Ok(serde_json::to_string(&dummy_deployment()).unwrap())
+            }
+            _ => panic!("unexpected API interaction: {req:?}, counter {ctr}"),
+        };
+        count_check!(5, clos, |client| {
+            let mut spec = dummy_cluster().spec;
+            spec.trustee_admin_api = Some(true);
+            let owner = OwnerReference::default();
+            assert!(update_trustee_config(client, owner, &spec).await.is_ok());
+        });
+    }
+
     #[tokio::test]
     async fn test_generate_trustee_data_success() {
-        let clos = |client| generate_trustee_data(client, Default::default(), &None);
+        let spec = dummy_cluster().spec;
+        let clos = |client| generate_trustee_data(client, Default::default(), &spec);
         test_create_success::<_, _, ConfigMap>(clos).await;
     }
 
     #[tokio::test]
     async fn test_generate_trustee_data_already_exists() {
-        let clos = |client| generate_trustee_data(client, Default::default(), &None);
+        let spec = dummy_cluster().spec;
+        let clos = |client| generate_trustee_data(client, Default::default(), &spec);
         test_create_already_exists(clos).await;
     }
 
     #[tokio::test]
     async fn test_generate_trustee_data_error() {
-        let clos = |client| generate_trustee_data(client, Default::default(), &None);
+        let spec = dummy_cluster().spec;
+        let clos = |client| generate_trustee_data(client, Default::default(), &spec);
         test_error_method!(clos, Method::POST);
     }
 
@@ -913,26 +2671,48 @@ mod tests {
 
     #[tokio::test]
     async fn test_generate_kbs_depl_success() {
-        let clos = |client| generate_kbs_deployment(client, Default::default(), "image", &None);
+        let spec = dummy_cluster().spec;
+        let clos = |client| generate_kbs_deployment(client, Default::default(), "image", &spec);
         test_create_success::<_, _, Deployment>(clos).await;
     }
 
     #[tokio::test]
     async fn test_generate_kbs_depl_error() {
-        let clos = |client| generate_kbs_deployment(client, Default::default(), "image", &None);
+        let spec = dummy_cluster().spec;
+        let clos = |client| generate_kbs_deployment(client, Default::default(), "image", &spec);
         test_error_method!(clos, Method::POST);
     }
 
     #[test]
-    fn test_recompute_reference_values_pcr4() {
-        let cos2_pcr4_hash = "c7fc63ec604348d8258993a9e344ba72041afd1473ad291a3171199b551aedbd";
-        let image_pcrs = ImagePcrs(BTreeMap::from([
+    fn test_generate_kbs_pod_spec_admin_volume() {
+        let mut spec = dummy_cluster().spec;
+        spec.trustee_admin_api = Some(true);
+        let pod_spec = generate_kbs_pod_spec("image", None, &spec);
+        let volumes = pod_spec.volumes.unwrap();
+        let admin_volume = volumes.iter().find(|v| v.name == TRUSTEE_ADMIN_SECRET);
+        let items = admin_volume.and_then(|v| v.secret.as_ref()?.items.clone());
+        let keys: Vec<_> = items.unwrap().into_iter().map(|i| i.key).collect();
+        assert_eq!(keys, vec![ADMIN_PUBLIC_KEY.to_string()]);
+    }
+
+    const COS2_PCR4_HASH: &str = "c7fc63ec604348d8258993a9e344ba72041afd1473ad291a3171199b551aedbd";
+
+    /// Two images whose PCR 4 components combine
+    fn combining_pcrs() -> ImagePcrs {
+        ImagePcrs(BTreeMap::from([
             (
                 "cos1".to_string(),
                 ImagePcr {
                     first_seen: Timestamp::now(),
                     pcrs: vec![primary_pcr4!(), expected_pcr7!()],
                     reference: "".to_string(),
+                    not_after: None,
+                    architectures: BTreeMap::new(),
+                    origins: BTreeMap::new(),
+                    profile_pcrs: BTreeMap::new(),
+                    profiles: BTreeMap::new(),
+                    kernel_cmdline_pcrs: Vec::new(),
+                    kernel_command_lines: Vec::new(),
                 },
             ),
             (
@@ -941,7 +2721,7 @@ mod tests {
                     first_seen: Timestamp::now(),
                     pcrs: vec![Pcr {
                         id: 4,
-                        value: hex::decode(cos2_pcr4_hash).unwrap(),
+                        value: hex::decode(COS2_PCR4_HASH).unwrap(),
                         events: vec![
                             pcr4_ev_efi_action_event!(),
                             pcr_separator_event!(4, TPMEventID::Pcr4Separator),
@@ -970,18 +2750,28 @@ mod tests {
                     },
                     expected_pcr7!()],
                     reference: "".to_string(),
+                    not_after: None,
+                    architectures: BTreeMap::new(),
+                    origins: BTreeMap::new(),
+                    profile_pcrs: BTreeMap::new(),
+                    profiles: BTreeMap::new(),
+                    kernel_cmdline_pcrs: Vec::new(),
+                    kernel_command_lines: Vec::new(),
                 },
             ),
-        ]));
+        ]))
+    }
 
-        let result = recompute_reference_values(image_pcrs);
+    #[test]
+    fn test_recompute_reference_values_pcr4() {
+        let result = recompute(&combining_pcrs());
         assert_eq!(result.len(), 3);
         let vals_pcr4 = reference_values_from(&result, "tpm_pcr4");
         assert_eq!(
             vals_pcr4,
             vec![
                 "514259b499f88d74cce9ff4763bb95d5c4e9a6703df48467a99dbcae02c3d974",
-                cos2_pcr4_hash,
+                COS2_PCR4_HASH,
                 "c9c3add791efc98f59977c89e673a34ad0b357872e9eb2c43d14607488e5d9e2",
                 PRIMARY_PCR4_HASH
             ]
@@ -989,4 +2779,27 @@ mod tests {
         let vals_pcr7 = reference_values_from(&result, "tpm_pcr7");
         assert_eq!(vals_pcr7, vec![PCR7_HASH]);
     }
+
+    #[test]
+    fn test_recompute_reference_values_architectures() {
+        let mut image_pcrs = combining_pcrs();
+        for (image, architecture) in [("cos1", "amd64"), ("cos2", "arm64")] {
+            let image_pcr = image_pcrs.0.get_mut(image).unwrap();
+            let pcrs = std::mem::take(&mut image_pcr.pcrs);
+            image_pcr
+                .architectures
+                .insert(architecture.to_string(), pcrs);
+        }
+        let result = recompute(&image_pcrs);
+        // Components of different architectures do not combine
+        let vals_pcr4 = reference_values_from(&result, "tpm_pcr4");
+        assert_eq!(vals_pcr4, vec![COS2_PCR4_HASH, PRIMARY_PCR4_HASH]);
+
+        // PCRs of an unknown architecture combine with every architecture
+        let image_pcr = image_pcrs.0.get_mut("cos1").unwrap();
+        image_pcr.pcrs = image_pcr.architectures.remove("amd64").unwrap();
+        let result = recompute(&image_pcrs);
+        let vals_pcr4 = reference_values_from(&result, "tpm_pcr4");
+        assert_eq!(vals_pcr4.len(), 4);
+    }
 }
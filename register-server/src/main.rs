@@ -68,12 +68,19 @@ impl EndpointInfo {
     async fn create(client: Client) -> anyhow::Result<Self> {
         let cluster = get_trusted_execution_cluster(client.clone()).await?;
         let name = cluster.metadata.name.as_deref().unwrap_or("<no name>");
-        let trustee_addr = cluster.spec.public_trustee_addr.context(format!(
+        let external_trustee = cluster.spec.external_trustee.as_ref();
+        let external_addr = external_trustee.map(|e| e.address.clone());
+        let trustee_addr = cluster.spec.public_trustee_addr.or(external_addr);
+        let trustee_addr = trustee_addr.context(format!(
             "TrustedExecutionCluster {name} did not specify a public Trustee address. \
              Add an address and re-register the node."
         ))?;
 
-        let trustee_ca_cert = match &cluster.spec.trustee_secret {
+        let trustee_secret = match external_trustee {
+            Some(external) => &external.ca_secret,
+            None => &cluster.spec.trustee_secret,
+        };
+        let trustee_ca_cert = match trustee_secret {
             Some(name) => Some(get_ca(client.clone(), name).await?),
             None => None,
         };
@@ -262,6 +269,7 @@ mod tests {
     use kube::api::ObjectMeta;
     use trusted_cluster_operator_lib::MachineSpec;
     use trusted_cluster_operator_lib::TrustedExecutionCluster;
+    use trusted_cluster_operator_lib::TrustedExecutionClusterExternalTrustee;
     use trusted_cluster_operator_test_utils::mock_client::*;
     use trusted_cluster_operator_test_utils::test_error_method;
 
@@ -323,6 +331,26 @@ mod tests {
         });
     }
 
+    #[tokio::test]
+    async fn test_get_trustee_info_external() {
+        let clos = async |_, _| {
+            let mut clusters = dummy_clusters();
+            let spec = &mut clusters.items[0].spec;
+            spec.public_trustee_addr = None;
+            spec.external_trustee = Some(TrustedExecutionClusterExternalTrustee {
+                address: "kbs.example.com:8080".to_string(),
+                ca_secret: None,
+                admin_key_secret: "kbs-admin".to_string(),
+            });
+            Ok(serde_json::to_string(&clusters).unwrap())
+        };
+        count_check!(1, clos, |client| {
+            let endpoint_info = EndpointInfo::create(client).await.unwrap();
+            assert_eq!(endpoint_info.trustee_addr, "kbs.example.com:8080");
+            assert_eq!(endpoint_info.trustee_ca_cert, None);
+        });
+    }
+
     #[tokio::test]
     async fn test_get_public_trustee_error() {
         let clos = async |c| EndpointInfo::create(c).await.map(|_| ());
@@ -22,7 +22,7 @@ done
 [[ -z "$BUNDLE_VERSION" ]] && { echo "Error: bundle version cannot be empty"; exit 1; }
 
 # Required environment variables
-for var in OPERATOR_IMAGE COMPUTE_PCRS_IMAGE REG_SERVER_IMAGE ATTESTATION_KEY_REGISTER_IMAGE TRUSTEE_IMAGE; do
+for var in OPERATOR_IMAGE COMPUTE_PCRS_IMAGE REG_SERVER_IMAGE ATTESTATION_KEY_REGISTER_IMAGE TRUSTEE_IMAGE RVPS_IMAGE; do
     : "${!var:?Please export $var}"
 done
 
@@ -78,9 +78,11 @@ yq -i "(.spec.relatedImages[] | select(.name == \"compute-pcrs\")).image = \"${C
 yq -i "(.spec.relatedImages[] | select(.name == \"registration-server\")).image = \"${REG_SERVER_IMAGE}\"" "$CSV_FILE"
 yq -i "(.spec.relatedImages[] | select(.name == \"attestation-key-register\")).image = \"${ATTESTATION_KEY_REGISTER_IMAGE}\"" "$CSV_FILE"
 yq -i "(.spec.relatedImages[] | select(.name == \"trustee\")).image = \"${TRUSTEE_IMAGE}\"" "$CSV_FILE"
+yq -i "(.spec.relatedImages[] | select(.name == \"rvps\")).image = \"${RVPS_IMAGE}\"" "$CSV_FILE"
 
 # Patch RELATED_IMAGE_* environment variables for OLM
 yq -i "(.spec.install.spec.deployments[0].spec.template.spec.containers[0].env[] | select(.name == \"RELATED_IMAGE_TRUSTEE\")).value = \"${TRUSTEE_IMAGE}\"" "$CSV_FILE"
+yq -i "(.spec.install.spec.deployments[0].spec.template.spec.containers[0].env[] | select(.name == \"RELATED_IMAGE_RVPS\")).value = \"${RVPS_IMAGE}\"" "$CSV_FILE"
 yq -i "(.spec.install.spec.deployments[0].spec.template.spec.containers[0].env[] | select(.name == \"RELATED_IMAGE_COMPUTE_PCRS\")).value = \"${COMPUTE_PCRS_IMAGE}\"" "$CSV_FILE"
 yq -i "(.spec.install.spec.deployments[0].spec.template.spec.containers[0].env[] | select(.name == \"RELATED_IMAGE_REGISTRATION_SERVER\")).value = \"${REG_SERVER_IMAGE}\"" "$CSV_FILE"
 yq -i "(.spec.install.spec.deployments[0].spec.template.spec.containers[0].env[] | select(.name == \"RELATED_IMAGE_ATTESTATION_KEY_REGISTER\")).value = \"${ATTESTATION_KEY_REGISTER_IMAGE}\"" "$CSV_FILE"
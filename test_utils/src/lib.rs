@@ -27,7 +27,7 @@ use trusted_cluster_operator_lib::certificates::{
 };
 use trusted_cluster_operator_lib::conditions::COMMITTED_CONDITION;
 use trusted_cluster_operator_lib::issuers::{Issuer, IssuerCa, IssuerSpec};
-use trusted_cluster_operator_lib::reference_values::ImagePcrs;
+use trusted_cluster_operator_lib::reference_values::{ImagePcrs, list_image_pcrs};
 
 use trusted_cluster_operator_lib::{ApprovedImage, ApprovedImageStatus, AttestationKey, Machine};
 use trusted_cluster_operator_lib::{TrustedExecutionCluster, endpoints::*, images::*};
@@ -1037,10 +1037,6 @@ impl TestContext {
         let info = format!("Updated TEC resource with publicTrusteeAddr: {trustee_addr}");
         self.info(info);
 
-        self.info("Waiting for image-pcrs ConfigMap to be created");
-        let configmap_api: Api<ConfigMap> = Api::namespaced(self.client.clone(), ns);
-        wait_for_resource_created(&configmap_api, "image-pcrs", scaled_timeout(60)).await?;
-
         let info = format!("Waiting for ApprovedImage {APPROVED_IMAGE_NAME} to be Committed");
         self.info(info);
         let images: Api<ApprovedImage> = Api::namespaced(self.client.clone(), ns);
@@ -1065,23 +1061,26 @@ impl TestContext {
         let namespace = self.namespace();
 
         let configmap_api: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
-        let populated = |cm: Option<&ConfigMap>| {
-            let data = cm.and_then(|cm| cm.data.as_ref());
-            let json = data.and_then(|data| data.get("image-pcrs.json"));
-            let pcrs = json.and_then(|json| serde_json::from_str::<ImagePcrs>(json).ok());
-            pcrs.map(|pcrs| {
-                pcrs.0.len() == expected_pcrs.len()
-                    && pcrs.0.values().all(|image_data| {
-                        expected_pcrs
-                            .iter()
-                            .any(|exp| compare_pcrs(&image_data.pcrs, exp))
+        let populated = |pcrs: &ImagePcrs| {
+            pcrs.0.len() == expected_pcrs.len()
+                && pcrs.0.values().all(|image_data| {
+                    expected_pcrs.iter().any(|exp| {
+                        let mut pcrs = image_data.architecture_pcrs();
+                        pcrs.any(|(_, pcrs)| compare_pcrs(pcrs, exp))
                     })
-            })
-            .unwrap_or(false)
+                })
+        };
+        let done = async {
+            loop {
+                let pcrs = list_image_pcrs(&configmap_api).await;
+                if pcrs.is_ok_and(|pcrs| populated(&pcrs)) {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
         };
-        let done = await_condition(configmap_api.clone(), "image-pcrs", populated);
-        let ctx = "waiting for ConfigMap image-pcrs to be populated with expected PCR values";
-        timeout(scaled_duration(180), done).await.context(ctx)??;
+        let ctx = "waiting for image PCR ConfigMaps to be populated with expected PCR values";
+        timeout(scaled_duration(180), done).await.context(ctx)?;
 
         Ok(())
     }
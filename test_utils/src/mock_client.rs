@@ -5,7 +5,8 @@
 
 use http::{Method, Request, Response, StatusCode};
 use kube::api::ObjectMeta;
-use kube::core::{Status, response::StatusSummary};
+use kube::core::Status;
+use kube::core::response::{StatusCause, StatusDetails, StatusSummary};
 use kube::{Client, client::Body};
 use serde::Serialize;
 use std::fmt::Debug;
@@ -69,13 +70,29 @@ async fn create_response<T: Future<Output = Result<String, StatusCode>>>(
                 StatusCode::INTERNAL_SERVER_ERROR => ("internal server error", "ServerTimeout"),
                 StatusCode::NOT_FOUND => ("resource not found", "NotFound"),
                 StatusCode::BAD_REQUEST => ("bad request", "BadRequest"),
+                // As for an image volume on a cluster without the ImageVolume feature
+                StatusCode::UNPROCESSABLE_ENTITY => (
+                    "spec.template.spec.volumes[0]: Required value: must specify a volume type",
+                    "Invalid",
+                ),
                 _ => (unknown_msg.as_str(), "Unknown"),
             };
+            let details =
+                (status_code == StatusCode::UNPROCESSABLE_ENTITY).then(|| StatusDetails {
+                    kind: "Job".to_string(),
+                    causes: vec![StatusCause {
+                        reason: "FieldValueRequired".to_string(),
+                        message: "Required value: must specify a volume type".to_string(),
+                        field: "spec.template.spec.volumes[0]".to_string(),
+                    }],
+                    ..Default::default()
+                });
             let error_response = Status {
                 status: Some(StatusSummary::Failure),
                 message: message.to_string(),
                 reason: reason.to_string(),
                 code: status_code.as_u16(),
+                details,
                 ..Default::default()
             };
             let error_json = serde_json::to_string(&error_response).unwrap();
@@ -191,6 +208,15 @@ pub fn dummy_cluster() -> TrustedExecutionCluster {
             trustee_kbs_port: None,
             attestation_key_register_port: None,
             public_attestation_key_register_addr: Some("::".to_string()),
+            trustee_admin_api: None,
+            attestation_token_key_rotation_days: None,
+            trustee: None,
+            external_trustee: None,
+            reference_values_version: None,
+            max_pcr_combinations: None,
+            registry: None,
+            signature_policy: None,
+            pcr_computation: None,
         },
     }
 }
@@ -271,6 +271,11 @@ async fn test_nonexistent_approved_image() -> anyhow::Result<()> {
         },
         spec: trusted_cluster_operator_lib::ApprovedImageSpec {
             image: "quay.io/trusted-execution-clusters/fedora-coreos@sha256:0000000000000000000000000000000000000000000000000000000000000000".to_string(),
+            not_before: None,
+            not_after: None,
+            image_pull_secrets: None,
+            platform_profiles: None,
+            kernel_command_lines: None,
         },
         status: None,
     }).await?;